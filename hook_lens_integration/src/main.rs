@@ -6,6 +6,7 @@ use std::env;
 use rusty_brain::{
     bitboards::{self, Bitboards},
     board::{self, Board},
+    fen::FenError,
     transposition::TranspositionTable,
 };
 
@@ -14,9 +15,13 @@ fn main() {
 
     if args.len() > 1 {
         let fen = &args[1];
-        let mut fen_string = fen.to_string();
-        fen_string.push_str(" w - - 0 1");
-        let mut board = Board::from_fen(fen_string.to_string());
+        let mut board = match board_from_image_fen(fen) {
+            Ok(board) => board,
+            Err(error) => {
+                eprintln!("invalid position \"{}\": {}", fen, error);
+                std::process::exit(1);
+            }
+        };
         let mut trans_table = TranspositionTable::init();
         let best_move = board.find_best_move(&mut trans_table, 5);
         println!("{}", best_move.0);
@@ -30,3 +35,15 @@ fn main() {
         // println!("|--------------------------------------------------------------|");
     }
 }
+
+// The image only gives the piece placement. Without the other fields it is white to move,
+// unless that would leave black in check, then it has to be black's turn
+fn board_from_image_fen(fen: &str) -> Result<Board, FenError> {
+    if fen.split_whitespace().count() > 1 {
+        return Board::try_from_fen(fen);
+    }
+    match Board::try_from_fen(&format!("{} w - - 0 1", fen)) {
+        Err(FenError::OpponentInCheck) => Board::try_from_fen(&format!("{} b - - 0 1", fen)),
+        result => result,
+    }
+}
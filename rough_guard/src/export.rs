@@ -0,0 +1,201 @@
+use burn::module::Module;
+use burn::prelude::*;
+use burn_efficient_kan::{Kan as EfficientKan, KanOptions};
+use std::io;
+use std::path::Path;
+
+// Layout of the RBKN files rusty_brain's KanFile option reads: a little endian header,
+//   magic "RBKN", version, number of layers, grid size, spline order, flags, output scale (f32),
+//   then the width of every layer boundary,
+// followed by the f32 weights of each layer in turn:
+//   grid           inputs x (grid_size + 2 * spline_order + 1)
+//   base_weight    outputs x inputs
+//   spline_weight  outputs x inputs x (grid_size + spline_order)
+const RBKN_MAGIC: &[u8; 4] = b"RBKN";
+const RBKN_VERSION: u32 = 1;
+// bit 0 marks a separate spline_scaler after every spline_weight, never needed here
+const RBKN_FLAGS: u32 = 0;
+
+// The base weights of one KAN layer, laid out the way RBKN stores them
+struct RbknLayer {
+    inputs: usize,
+    outputs: usize,
+    base_weight: Vec<f32>,
+}
+
+// The two layers of an efficient-kan Kan. Its grid is a constant of the module and isn't part of
+// the record, it is rebuilt from the options when the file is written
+fn rbkn_layers<B: Backend>(kan: &EfficientKan<B>, coefficients: usize) -> Vec<RbknLayer> {
+    let record = kan.clone().into_record();
+    [record.layer_one, record.layer_two]
+        .into_iter()
+        .map(|layer| {
+            // stored as inputs x outputs, the transpose of RBKN
+            let base_weight = layer.base_weight.val();
+            let [inputs, outputs] = base_weight.dims();
+            assert_eq!(
+                layer.spline_weight.val().dims(),
+                [outputs, inputs, coefficients],
+                "spline weights don't match the grid size and spline order of the options"
+            );
+
+            RbknLayer {
+                inputs,
+                outputs,
+                base_weight: base_weight.transpose().to_data().iter::<f32>().collect(),
+            }
+        })
+        .collect()
+}
+
+// Converts KANs trained with burn-efficient-kan into the RBKN format of rusty_brain.
+// The Kans are chained one after the other, as ModifiedKan runs them, and all have to use the
+// grid size, spline order and grid range of the options. scale is the centipawn value of an
+// output of 1. rusty_brain only loads networks taking its 768 piece-square inputs to 1 output.
+//
+// burn-efficient-kan 0.3 builds the order 0 B-spline bases as (x >= knot i) == (x < knot i),
+// which never holds, so every spline it evaluates is 0 and a trained Kan is its SiLU base alone.
+// The spline weights are written as zeros so the engine evaluates the network that was trained.
+pub fn kan_to_rbkn<B: Backend>(kans: &[EfficientKan<B>], options: &KanOptions, scale: f32) -> Vec<u8> {
+    let grid_size = options.grid_size as usize;
+    let spline_order = options.spline_order as usize;
+    let layers: Vec<RbknLayer> = kans
+        .iter()
+        .flat_map(|kan| rbkn_layers(kan, grid_size + spline_order))
+        .collect();
+    for pair in layers.windows(2) {
+        assert_eq!(pair[0].outputs, pair[1].inputs, "the Kans don't chain");
+    }
+
+    let mut bytes = RBKN_MAGIC.to_vec();
+    let header = [
+        RBKN_VERSION,
+        layers.len() as u32,
+        grid_size as u32,
+        spline_order as u32,
+        RBKN_FLAGS,
+    ];
+    for field in header {
+        bytes.extend(field.to_le_bytes());
+    }
+    bytes.extend(scale.to_le_bytes());
+    bytes.extend((layers[0].inputs as u32).to_le_bytes());
+    for layer in &layers {
+        bytes.extend((layer.outputs as u32).to_le_bytes());
+    }
+
+    // the same evenly spaced knots efficient-kan sets up for every input
+    let step = (options.grid_range_end - options.grid_range_start) as f32 / grid_size as f32;
+    let knots: Vec<f32> = (0..grid_size + 2 * spline_order + 1)
+        .map(|knot| (knot as f32 - spline_order as f32) * step + options.grid_range_start as f32)
+        .collect();
+    for layer in &layers {
+        for _ in 0..layer.inputs {
+            for knot in &knots {
+                bytes.extend(knot.to_le_bytes());
+            }
+        }
+        for value in &layer.base_weight {
+            bytes.extend(value.to_le_bytes());
+        }
+        let spline_weights = layer.outputs * layer.inputs * (grid_size + spline_order);
+        bytes.extend(std::iter::repeat_n(0u8, 4 * spline_weights));
+    }
+    bytes
+}
+
+// Writes the Kans as an RBKN file for the KanFile option, see kan_to_rbkn
+pub fn write_rbkn<B: Backend>(
+    kans: &[EfficientKan<B>],
+    options: &KanOptions,
+    scale: f32,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    std::fs::write(path, kan_to_rbkn(kans, options, scale))
+}
+
+#[cfg(test)]
+mod export {
+    use super::kan_to_rbkn;
+    use burn::backend::NdArray;
+    use burn::module::Module;
+    use burn::record::{CompactRecorder, Recorder};
+    use burn::tensor::{Tensor, TensorData};
+    use burn_efficient_kan::{Kan as EfficientKan, KanOptions};
+
+    type TestBackend = NdArray<f32>;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn silu(x: f32) -> f32 {
+        x / (1.0 + (-x).exp())
+    }
+
+    #[test]
+    fn saved_kan_is_written_as_rbkn() {
+        let device = Default::default();
+        let options = KanOptions::new([768, 4, 1]);
+        let artifact_dir = std::env::temp_dir().join("rough_guard_rbkn_export");
+        std::fs::create_dir_all(&artifact_dir).unwrap();
+        let model_path = artifact_dir.join("model");
+        EfficientKan::<TestBackend>::new(&options, &device)
+            .save_file(model_path.clone(), &CompactRecorder::new())
+            .unwrap();
+        let record = CompactRecorder::new().load(model_path, &device).unwrap();
+        let kan = EfficientKan::<TestBackend>::new(&options, &device).load_record(record);
+
+        let bytes = kan_to_rbkn(std::slice::from_ref(&kan), &options, 400.0);
+
+        // magic, version, 2 layers, grid size 5, spline order 3, no flags, scale, widths
+        assert_eq!(&bytes[..4], b"RBKN");
+        let header: Vec<u32> = (1..6).map(|field| read_u32(&bytes, 4 * field)).collect();
+        assert_eq!(header, [1, 2, 5, 3, 0]);
+        assert_eq!(read_f32(&bytes, 24), 400.0);
+        let widths: Vec<u32> = (0..3).map(|width| read_u32(&bytes, 28 + 4 * width)).collect();
+        assert_eq!(widths, [768, 4, 1]);
+
+        let knots = 5 + 2 * 3 + 1;
+        let coefficients = 5 + 3;
+        // byte offsets of the grid, base weights and spline weights of a layer
+        let layout = |start: usize, inputs: usize, outputs: usize| {
+            let base = start + 4 * inputs * knots;
+            let spline = base + 4 * outputs * inputs;
+            (base, spline, spline + 4 * outputs * inputs * coefficients)
+        };
+        let (first_base, first_spline, second_start) = layout(40, 768, 4);
+        let (second_base, second_spline, end) = layout(second_start, 4, 1);
+        assert_eq!(bytes.len(), end);
+
+        // efficient-kan's knots over [-1, 1], 3 steps of 0.4 past either end
+        assert!((read_f32(&bytes, 40) + 2.2).abs() < 1e-6);
+        assert!((read_f32(&bytes, 40 + 4 * (knots - 1)) - 2.2).abs() < 1e-6);
+        assert!(bytes[first_spline..second_start].iter().all(|&byte| byte == 0));
+        assert!(bytes[second_spline..end].iter().all(|&byte| byte == 0));
+
+        // the written network gives what burn gives for a position's worth of inputs
+        let mut inputs = vec![0.0; 768];
+        for index in [0, 9, 100, 333, 500, 767] {
+            inputs[index] = 1.0;
+        }
+        let input = Tensor::<TestBackend, 2>::from_data(TensorData::new(inputs.clone(), [1, 768]), &device);
+        let expected: f32 = kan.forward(input).into_scalar();
+
+        let hidden: Vec<f32> = (0..4)
+            .map(|output| {
+                (0..768)
+                    .map(|input| silu(inputs[input]) * read_f32(&bytes, first_base + 4 * (output * 768 + input)))
+                    .sum()
+            })
+            .collect();
+        let output: f32 = (0..4)
+            .map(|input| silu(hidden[input]) * read_f32(&bytes, second_base + 4 * input))
+            .sum();
+        assert!((output - expected).abs() < 1e-4, "{} written vs {} in burn", output, expected);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod data;
+pub mod export;
 pub mod inference;
 pub mod model;
 pub mod training;
@@ -1,446 +1,1027 @@
-use std::i32;
-use crate::board::{Board, Turn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crate::board::Board;
+use crate::evaluator::{EvalTiming, EvalType, Evaluator};
+use crate::move_picker::{MovePicker, OrderingHeuristics};
+use crate::movegen::{MoveList, MAX_MOVES};
 use crate::movement::Move;
-use crate::nnue::NNUE;
-use crate::transposition::{TranspositionTable, Node};
-use crate::square::Square;
+use crate::time_management::TimeManager;
+use crate::transposition::{score_from_tt, score_to_tt, TranspositionTable, Node};
 
+// Bound of the search window, above any score a search can return and small enough
+// that negating or widening it never overflows
+pub const INFINITY: i32 = 32_001;
+// Score of delivering mate at the root, a mate n plies away scores MATE - n
+pub const MATE: i32 = 32_000;
+// Depth limit of searches that are only bounded by time
+pub const MAX_DEPTH: i32 = 64;
+// Deepest ply a search can reach, sizes the pv table
+pub const MAX_PLY: i32 = 128;
+// Scores from here up are mates
+pub const MATE_IN_MAX_PLY: i32 = MATE - MAX_PLY;
+// Upper bound of the Threads option
+pub const MAX_THREADS: usize = 256;
+// The clock and the stop signal are only read every this many nodes
+const TIME_CHECK_INTERVAL: u64 = 1024;
 
-impl Board {
+// Selective search tuning, depths are in plies and margins in centipawns
+const NULL_MOVE_MIN_DEPTH: i32 = 3;
+const REVERSE_FUTILITY_MAX_DEPTH: i32 = 6;
+const REVERSE_FUTILITY_MARGIN: i32 = 80;
+// indexed by the remaining depth
+const FUTILITY_MARGINS: [i32; 4] = [0, 150, 300, 450];
+const RAZORING_MARGINS: [i32; 3] = [0, 300, 550];
+const LMR_MIN_DEPTH: i32 = 3;
+// moves searched at full depth before the rest are reduced
+const LMR_FULL_DEPTH_MOVES: usize = 3;
 
-    pub fn find_best_move(&mut self, transposition_table: &mut TranspositionTable, depth: i32) -> (Move, i32) {
-        
-        //In all functions below
-            //Maximizing and Minimizing is an indicator of root call
-            //So the first call has these values set to True
-            //This is equivalent to just storing original_depth and using it
-
-        /*
-        //using vanilla minimax
-        let eval = match self.turn {
-            Turn::White => self.maxi(true, depth),
-            Turn::Black => self.mini(true, depth)
-        };
-        (self.best_move.unwrap_or_else( || Move::encode(0, 0, 0)), eval)
-        */
-
-        
-        //using normal alphabeta
-        let eval = match self.turn {
-            Turn::White => self.alpha_beta_max(true, i32::MIN, i32::MAX, depth),
-            Turn::Black => self.alpha_beta_min(true, i32::MIN, i32::MAX, depth),
-        };
-        (self.best_move.unwrap_or_else( || Move::encode(0, 0, 0)), eval)
-          
-
-        
-        /*
-        //using alphabeta with transposition table
-        let eval = match self.turn {
-            Turn::White => self.alpha_beta_max_tt(transposition_table, true, i32::MIN, i32::MAX, depth),
-            Turn::Black => self.alpha_beta_min_tt(transposition_table,true, i32::MIN, i32::MAX, depth),
-        };
-        (self.best_move.unwrap_or_else( || Move::encode(0, 0, 0)), eval)
-        */
-        
-        /* 
-        //Iterative deepening, needs move ordering to show its strength
-        let eval = match self.turn {
-            Turn::White => self.iterative_deepening(transposition_table, true, depth),
-            Turn::Black => self.iterative_deepening(transposition_table, false, depth),
-        }; 
-        (self.best_move.unwrap_or_else( || Move::encode(0, 0, 0)), eval)
-        */
+// Runtime switches for the search.
+// Every variant that used to be picked by commenting code in and out of
+// find_best_move is now a combination of these flags.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchConfig {
+    // Cut off branches outside the (alpha, beta) window.
+    // When false the search is plain negamax (minimax) over the full tree.
+    pub alpha_beta: bool,
+    // Probe and store positions in the transposition table.
+    // Always on with more than one thread, the table is all the threads share.
+    pub transposition_table: bool,
+    // Search depth 1, 2, .. up to the requested depth instead of going there directly.
+    pub iterative_deepening: bool,
+    // Centre each iteration's window on the previous score (needs iterative deepening).
+    pub aspiration_windows: bool,
+    // Initial half width of the aspiration window, doubled on every fail.
+    pub aspiration_delta: i32,
+    // Resolve captures and promotions at the horizon instead of evaluating straight away.
+    pub quiescence: bool,
+    // Search every evasion when quiescence search finds the side to move in check.
+    pub quiescence_evasions: bool,
+    // Skip quiescence captures that lose material by static exchange evaluation.
+    pub see_pruning: bool,
+    // Search the moves most likely to cause a cutoff first instead of in generation order.
+    pub move_ordering: bool,
+    // Search every move after the first with a null window, re-searching the ones that beat alpha.
+    pub principal_variation_search: bool,
+    // Searches run side by side on the same transposition table (lazy SMP), 1 is a single threaded search.
+    pub threads: usize,
+    // Let the opponent move twice, a position that still fails high is cut off at reduced depth.
+    pub null_move_pruning: bool,
+    // Search quiet moves late in the move order at reduced depth, re-searching the ones that beat alpha.
+    pub late_move_reductions: bool,
+    // Skip quiet moves near the horizon when the static evaluation plus a margin can't reach alpha.
+    pub futility_pruning: bool,
+    // Cut off nodes near the horizon whose static evaluation beats beta by a margin.
+    pub reverse_futility_pruning: bool,
+    // Drop nodes near the horizon far below alpha straight into the quiescence search.
+    pub razoring: bool,
+    // Search one ply deeper when the side to move is in check.
+    pub check_extensions: bool,
+}
+
+impl SearchConfig {
+    // Any pruning, reduction or extension enabled
+    pub fn is_selective(&self) -> bool {
+        self.null_move_pruning
+            || self.late_move_reductions
+            || self.futility_pruning
+            || self.reverse_futility_pruning
+            || self.razoring
+            || self.check_extensions
+    }
+}
 
+impl Default for SearchConfig {
+    // Principal variation search deepened iteratively inside aspiration windows,
+    // with a quiescence search at the horizon
+    fn default() -> Self {
+        SearchConfig {
+            alpha_beta: true,
+            transposition_table: true,
+            iterative_deepening: true,
+            aspiration_windows: true,
+            aspiration_delta: 100,
+            quiescence: true,
+            quiescence_evasions: true,
+            see_pruning: true,
+            move_ordering: true,
+            principal_variation_search: true,
+            threads: 1,
+            null_move_pruning: false,
+            late_move_reductions: false,
+            futility_pruning: false,
+            reverse_futility_pruning: false,
+            razoring: false,
+            check_extensions: false,
+        }
+    }
+}
 
+// Flags shared between a running search and the thread that started it
+#[derive(Debug, Default)]
+pub struct SearchSignals {
+    // abort as soon as possible and return the best move found so far
+    pub stop: AtomicBool,
+    // the search runs on the opponent's time, the clock is ignored until ponderhit clears this
+    pub ponder: AtomicBool,
+}
 
+// Everything a search carries from node to node besides the board and the transposition table
+pub struct SearchInfo {
+    pub config: SearchConfig,
+    pub evaluator: Box<dyn Evaluator>,
+    pub time_manager: TimeManager,
+    pub nodes: u64,
+    // deepest iteration that finished, the search never stops before the first one
+    pub completed_depth: i32,
+    // set once the time is up or a stop was requested, every node then returns without storing anything
+    pub stopped: bool,
+    pub signals: Arc<SearchSignals>,
+    // print uci info lines after every iteration
+    pub report: bool,
+    // deepest ply reached in the current iteration
+    pub seldepth: i32,
+    // triangular pv table, row n holds the best line found from ply n
+    pub pv_table: Vec<Vec<Move>>,
+    // principal variation of the last finished iteration
+    pub pv: Vec<Move>,
+    pub heuristics: OrderingHeuristics,
+    // evaluations and evaluation time of the last search over all its threads, for evaluators that measure it
+    pub eval_timing: Option<EvalTiming>,
+    // row n is set while ply n is searched after a null move, two in a row prove nothing
+    null_move: Vec<bool>,
+}
+
+impl SearchInfo {
+    pub fn new(config: SearchConfig, evaluator: Box<dyn Evaluator>) -> Self {
+        SearchInfo {
+            config,
+            evaluator,
+            time_manager: TimeManager::infinite(),
+            nodes: 0,
+            completed_depth: 0,
+            stopped: false,
+            signals: Arc::new(SearchSignals::default()),
+            report: false,
+            seldepth: 0,
+            pv_table: vec![Vec::new(); MAX_PLY as usize + 1],
+            pv: Vec::new(),
+            heuristics: OrderingHeuristics::new(),
+            eval_timing: None,
+            null_move: vec![false; MAX_PLY as usize + 1],
+        }
     }
 
-    fn maxi(&mut self, maximizing: bool, depth_left: i32) -> i32 {
-        if depth_left == 0 {
-            return match self.turn {
-                Turn::White => NNUE.evaluate(&self.white_accumulator, &self.black_accumulator),
-                Turn::Black => -NNUE.evaluate(&self.black_accumulator, &self.white_accumulator),
-            };
+    fn update_pv(&mut self, ply: usize, best_move: Move) {
+        let (parent, child) = self.pv_table.split_at_mut(ply + 1);
+        parent[ply].clear();
+        parent[ply].push(best_move);
+        parent[ply].extend_from_slice(&child[0]);
+    }
+
+    fn pondering(&self) -> bool {
+        self.signals.ponder.load(Ordering::Relaxed)
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.stopped && self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
+            self.stopped = self.signals.stop.load(Ordering::Relaxed)
+                || (self.completed_depth > 0 && !self.pondering() && self.time_manager.hard_limit_reached());
         }
-        let mut max = i32::MIN;
-        let moves: Vec<Move> = self.generate_legal_moves();
-
-        for current_move in moves {
-            self.make_move(current_move);
-            let mut score = self.mini(false, depth_left - 1);
-            if self.checkmate {
-                self.undo_move();
-                if maximizing {self.best_move = Some(current_move)};
-                match self.turn {
-                    Turn::White => return i32::MAX,
-                    Turn::Black => return i32::MIN,
-                };
+        self.stopped
+    }
+}
+
+impl Board {
+
+    pub fn find_best_move(&mut self, transposition_table: &TranspositionTable, depth: i32) -> (Move, i32) {
+        let mut search_info = SearchInfo::new(SearchConfig::default(), EvalType::Nnue.create());
+        self.search(transposition_table, &mut search_info, depth)
+    }
+
+    // Searches the current position and returns the best move with its score.
+    // The score is relative to the side to move, positive means the side to move is better.
+    //
+    // With more than one thread the helpers search copies of the position at the same time and
+    // only talk to the main search through the transposition table, their entries speed it up
+    // and steer its move ordering. The result, info lines and node count are the main search's,
+    // the evaluation timing adds up every thread's.
+    pub fn search(&mut self, transposition_table: &TranspositionTable, search_info: &mut SearchInfo, depth: i32) -> (Move, i32) {
+        transposition_table.new_search();
+
+        let helpers = search_info.config.threads.clamp(1, MAX_THREADS) - 1;
+        if helpers == 0 {
+            let result = self.search_thread(transposition_table, search_info, depth);
+            search_info.eval_timing = search_info.evaluator.timing();
+            return result;
+        }
+
+        // without the table the helpers would search for nothing, so it is on for this search
+        // whatever the caller's config says and the caller gets its config back afterwards
+        let caller_config = search_info.config;
+        search_info.config.transposition_table = true;
+
+        // stopped as soon as the main search is done
+        let helper_signals = Arc::new(SearchSignals::default());
+        let result = thread::scope(|scope| {
+            let mut helper_threads = Vec::with_capacity(helpers);
+            for helper_id in 1..=helpers {
+                let mut board = self.clone();
+                // helpers always deepen and half of them run a ply ahead, so they don't all search the same tree
+                let config = SearchConfig { iterative_deepening: true, threads: 1, ..search_info.config };
+                let mut helper_info = SearchInfo::new(config, search_info.evaluator.new_instance());
+                helper_info.signals = Arc::clone(&helper_signals);
+                let helper_depth = (depth + (helper_id % 2) as i32).min(MAX_DEPTH);
+                helper_threads.push(scope.spawn(move || {
+                    board.search_thread(transposition_table, &mut helper_info, helper_depth);
+                    helper_info.evaluator.timing()
+                }));
             }
-            else if self.draw || self.stalemate {
-                score = 0;
-                if score > max {
-                    if maximizing {self.best_move = Some(current_move)}
+
+            let result = self.search_thread(transposition_table, search_info, depth);
+            helper_signals.stop.store(true, Ordering::Relaxed);
+            search_info.eval_timing = search_info.evaluator.timing();
+            for helper in helper_threads {
+                if let (Some(total), Some(timing)) = (search_info.eval_timing.as_mut(), helper.join().unwrap()) {
+                    *total += timing;
                 }
-            
             }
-            self.undo_move();
+            result
+        });
+        search_info.config = caller_config;
+        result
+    }
 
-            if score > max {
-                max = score;
-                if maximizing {
-                    self.best_move = Some(current_move);
-                }
+    // One thread's share of a search, the whole search when it runs single threaded
+    fn search_thread(&mut self, transposition_table: &TranspositionTable, search_info: &mut SearchInfo, depth: i32) -> (Move, i32) {
+        self.best_move = None;
+        search_info.nodes = 0;
+        search_info.completed_depth = 0;
+        search_info.stopped = false;
+        search_info.pv.clear();
+        search_info.heuristics.clear();
+        search_info.evaluator.reset(self);
+
+        // a clock can only be followed by deepening until the time runs out
+        let eval = if search_info.config.iterative_deepening || search_info.time_manager.is_time_limited() {
+            self.iterative_deepening(transposition_table, search_info, depth)
+        } else {
+            search_info.seldepth = 0;
+            let eval = self.negamax(transposition_table, search_info, -INFINITY, INFINITY, depth, 0);
+            if !search_info.stopped {
+                search_info.pv = search_info.pv_table[0].clone();
+                Self::report_iteration(transposition_table, search_info, depth, eval);
             }
-        }
-        return max;
+            eval
+        };
+
+        // the root move of an unfinished iteration may come from a failed aspiration window,
+        // the last finished one is trusted first.
+        // a search stopped before it finished a single root move still has to answer with a legal move
+        let best_move = search_info.pv.first().copied()
+            .or(self.best_move)
+            .or_else(|| self.generate_legal_moves().first().copied());
+        (best_move.unwrap_or_else( || Move::encode(0, 0, 0)), eval)
     }
 
-    fn mini(&mut self, minimizing: bool, depth_left: i32) -> i32 {
-        if depth_left == 0 {
-            return match self.turn {
-                Turn::White => NNUE.evaluate(&self.white_accumulator, &self.black_accumulator),
-                Turn::Black => -NNUE.evaluate(&self.black_accumulator, &self.white_accumulator),
-            };
+    // make_move and undo_move that keep the evaluator in step with the board
+    fn make_search_move(&mut self, search_info: &mut SearchInfo, move_to_make: Move) {
+        search_info.evaluator.on_make(self, move_to_make);
+        self.make_move(move_to_make);
+    }
+
+    fn undo_search_move(&mut self, search_info: &mut SearchInfo) {
+        self.undo_move();
+        search_info.evaluator.on_undo(self);
+    }
+
+    // Side to move relative alpha-beta, every node maximizes its own score
+    // and the score of a child is the negation of the child's result.
+    // ply is the distance from the root, the root (ply 0) records the best move.
+    fn negamax(&mut self, transposition_table: &TranspositionTable, search_info: &mut SearchInfo, mut alpha: i32, mut beta: i32, mut depth_left: i32, ply: i32) -> i32 {
+        let config = search_info.config;
+        let original_alpha = alpha;
+        // a window wider than a null window can change the principal variation
+        let pv_node = beta - alpha > 1;
+
+        // only the selective techniques need to know, the check test isn't free
+        let in_check = config.is_selective() && self.is_in_check();
+        if in_check && config.check_extensions && ply < MAX_PLY {
+            depth_left += 1;
         }
-        let mut min = i32::MAX;
-        let moves: Vec<Move> = self.generate_legal_moves();
-
-        for current_move in moves {
-            self.make_move(current_move);
-            let mut score = self.maxi(false, depth_left - 1);
-            if self.checkmate {
-                self.undo_move();
-                if minimizing {self.best_move = Some(current_move)};
-                match self.turn {
-                    Turn::White => return i32::MAX,
-                    Turn::Black => return i32::MIN,
-                };
-            }
-            else if self.draw || self.stalemate {
-                score = 0;
-                if score < min {
-                    if minimizing {self.best_move = Some(current_move)}
+
+        if depth_left == 0 && config.quiescence {
+            return self.quiescence(search_info, alpha, beta, ply);
+        }
+
+        search_info.nodes += 1;
+        if search_info.should_stop() {
+            return 0;
+        }
+        search_info.seldepth = search_info.seldepth.max(ply);
+        search_info.pv_table[ply as usize].clear();
+
+        let mut tt_move = None;
+        if config.transposition_table {
+            if let Some(entry) = transposition_table.retrieve_from_table(self) {
+                tt_move = entry.best_move;
+                // the root never returns straight from the table, it has to produce a move
+                if ply > 0 && entry.depth >= depth_left {
+                    let score = score_from_tt(entry.score, ply);
+                    match entry.node_type {
+                        Node::Exact => return score,
+                        Node::LowerBound => alpha = alpha.max(score),
+                        Node::UpperBound => beta = beta.min(score),
+                    }
+                    if alpha >= beta {
+                        return score;
+                    }
                 }
-                
             }
-            self.undo_move();
+        }
 
-            if score < min {
-                min = score;
-                if minimizing {
-                    self.best_move = Some(current_move);
-                }
+        if depth_left == 0 || ply >= MAX_PLY {
+            return self.static_eval(search_info);
+        }
+
+        // the selective techniques all lean on the bounds, minimax searches everything
+        let prunable = config.alpha_beta && ply > 0 && !in_check && !pv_node
+            && (config.null_move_pruning || config.reverse_futility_pruning || config.razoring || config.futility_pruning);
+        let static_eval = if prunable { self.static_eval(search_info) } else { -INFINITY };
+
+        if prunable {
+            if let Some(score) = self.prune_node(transposition_table, search_info, alpha, beta, depth_left, ply, static_eval) {
+                return score;
             }
         }
-        return min;
-    }
 
-    fn alpha_beta_max(&mut self, maximizing: bool, mut alpha: i32, beta: i32, depth_left: i32) -> i32 {
+        // quiet moves this close to the horizon can't bring the score up to alpha
+        let futile = prunable
+            && config.futility_pruning
+            && depth_left < FUTILITY_MARGINS.len() as i32
+            && alpha.abs() < MATE_IN_MAX_PLY
+            && static_eval + FUTILITY_MARGINS[depth_left as usize] <= alpha;
 
-        if depth_left == 0 {
-            return match self.turn {
-                Turn::White => NNUE.evaluate(&self.white_accumulator, &self.black_accumulator),
-                Turn::Black => -NNUE.evaluate(&self.black_accumulator, &self.white_accumulator),
-            };
+        // without a table the root still starts with the best move of the previous iteration
+        if ply == 0 && tt_move.is_none() {
+            tt_move = search_info.pv.first().copied();
         }
+        let mut move_picker = if config.move_ordering {
+            MovePicker::new(self, tt_move, &search_info.heuristics, ply)
+        } else {
+            MovePicker::unordered(self)
+        };
 
-        let mut best_value = i32::MIN;
-        let moves: Vec<Move> = self.generate_legal_moves();
+        let mut best_value = -INFINITY;
+        let mut best_move = None;
+        let mut moves_searched = 0;
+        let principal_variation = config.principal_variation_search && config.alpha_beta;
 
-        for current_move in moves {
-            self.make_move(current_move);
-            let mut score: i32 = self.alpha_beta_min(false, alpha, beta, depth_left - 1);
-            if self.checkmate {
-                self.undo_move();
-                if maximizing {self.best_move = Some(current_move);}
-                match self.turn {
-                    Turn::White => return i32::MAX,
-                    Turn::Black => return i32::MIN,
-                };
+        while let Some(current_move) = move_picker.next(self, &search_info.heuristics) {
+            let reducible = config.late_move_reductions
+                && config.alpha_beta
+                && !in_check
+                && depth_left >= LMR_MIN_DEPTH
+                && moves_searched >= LMR_FULL_DEPTH_MOVES;
+
+            self.make_search_move(search_info, current_move);
+            // moves that give check are never pruned or reduced
+            let quiet = (futile || reducible) && current_move.is_quiet() && !self.is_in_check();
+
+            if futile && quiet && best_move.is_some() {
+                self.undo_search_move(search_info);
+                continue;
             }
-            else if self.draw || self.stalemate {
-                score = 0;
-                if score > best_value {
-                    if maximizing {self.best_move = Some(current_move)}
+
+            // a drawn child is never searched, its line would be left over from a sibling
+            search_info.pv_table[ply as usize + 1].clear();
+            let score = if self.is_draw(ply + 1) {
+                0
+            } else {
+                let reduction = if reducible && quiet {
+                    late_move_reduction(depth_left, moves_searched, pv_node)
+                } else {
+                    0
+                };
+
+                // after the first move the others only have to be proven no better than alpha,
+                // which a null window search does cheaper
+                let null_window = principal_variation && moves_searched > 0;
+
+                // a reduced move only gets the full depth when it unexpectedly beats alpha
+                let mut score = alpha + 1;
+                if reduction > 0 {
+                    score = -self.negamax(transposition_table, search_info, -alpha - 1, -alpha, depth_left - 1 - reduction, ply + 1);
+                }
+                if score > alpha && null_window {
+                    score = -self.negamax(transposition_table, search_info, -alpha - 1, -alpha, depth_left - 1, ply + 1);
+                }
+                // a move that beats alpha without failing high is a new principal variation and needs its exact score
+                if score > alpha && (!null_window || score < beta) {
+                    score = -self.negamax(transposition_table, search_info, -beta, -alpha, depth_left - 1, ply + 1);
                 }
+                score
+            };
+            self.undo_search_move(search_info);
+            moves_searched += 1;
+
+            // the score of an interrupted subtree means nothing
+            if search_info.stopped {
+                return 0;
             }
-            self.undo_move();
-            if score > best_value {
+
+            if score > best_value || best_move.is_none() {
                 best_value = score;
-                if maximizing {
-                    self.best_move = Some(current_move);
-                }
-                if score > alpha {
-                    alpha = score;
+                best_move = Some(current_move);
+                search_info.update_pv(ply as usize, current_move);
+                // without a finished iteration to fall back on, a stop returns the best root move so far
+                if ply == 0 && search_info.completed_depth == 0 {
+                    self.best_move = best_move;
                 }
             }
-            if score >= beta {
-                if maximizing {
-                    self.best_move = Some(current_move);
+
+            if config.alpha_beta {
+                alpha = alpha.max(score);
+                if alpha >= beta {
+                    if current_move.is_quiet() {
+                        search_info.heuristics.update(self, current_move, ply, depth_left);
+                    }
+                    break;
                 }
-                return score;
             }
         }
 
-        return best_value;
+        // the first legal move is never pruned, so nothing searched means there was nothing to search.
+        // The ply makes quicker mates score higher
+        if best_move.is_none() {
+            return if in_check || self.is_in_check() { -MATE + ply } else { 0 };
+        }
+
+        if ply == 0 {
+            self.best_move = best_move;
+        }
 
+        if config.transposition_table {
+            // the bounds are converted along with the score so the node type stays the same
+            transposition_table.store_in_table(self, best_move, depth_left, score_to_tt(best_value, ply), score_to_tt(original_alpha, ply), score_to_tt(beta, ply));
+        }
+
+        best_value
     }
-    
-    fn alpha_beta_min(&mut self, minimizing: bool, alpha: i32, mut beta: i32, depth_left: i32) -> i32 {
 
-        if depth_left == 0 {
-            return match self.turn {
-                Turn::White => NNUE.evaluate(&self.white_accumulator, &self.black_accumulator),
-                Turn::Black => -NNUE.evaluate(&self.black_accumulator, &self.white_accumulator),
-            };
+    // Cutoffs decided before any move is searched, at nodes outside the principal variation
+    // and not in check. Returns the score of the node when it can be pruned.
+    #[allow(clippy::too_many_arguments)]
+    fn prune_node(&mut self, transposition_table: &TranspositionTable, search_info: &mut SearchInfo, alpha: i32, beta: i32, depth_left: i32, ply: i32, static_eval: i32) -> Option<i32> {
+        let config = search_info.config;
+
+        // reverse futility, the position is so good that the opponent can't catch up in the remaining plies
+        if config.reverse_futility_pruning
+            && depth_left <= REVERSE_FUTILITY_MAX_DEPTH
+            && beta.abs() < MATE_IN_MAX_PLY
+            && static_eval - REVERSE_FUTILITY_MARGIN * depth_left >= beta
+        {
+            return Some(static_eval);
         }
 
-        let mut best_value = i32::MAX;
-        let moves: Vec<Move> = self.generate_legal_moves();
+        // razoring, hopeless positions only get a look at their captures
+        if config.razoring
+            && depth_left < RAZORING_MARGINS.len() as i32
+            && alpha.abs() < MATE_IN_MAX_PLY
+            && static_eval + RAZORING_MARGINS[depth_left as usize] < alpha
+        {
+            let score = self.negamax(transposition_table, search_info, alpha, alpha + 1, 0, ply);
+            if score <= alpha {
+                return Some(score);
+            }
+        }
 
-        for current_move in moves {
-            self.make_move(current_move);
-            let mut score = self.alpha_beta_max(false, alpha, beta, depth_left-1);
-            if self.checkmate {
-                self.undo_move();
-                if minimizing {self.best_move = Some(current_move);}
-                match self.turn {
-                    Turn::White => return i32::MAX,
-                    Turn::Black => return i32::MIN,
-                };
+        // null move, if passing still fails high a real move will too.
+        // Not right after another null move, and not without pieces where zugzwang is likely
+        if config.null_move_pruning
+            && depth_left >= NULL_MOVE_MIN_DEPTH
+            && !search_info.null_move[ply as usize]
+            && beta.abs() < MATE_IN_MAX_PLY
+            && static_eval >= beta
+            && self.has_non_pawn_material()
+        {
+            let reduction = 3 + depth_left / 6;
+            self.make_null_move();
+            search_info.null_move[ply as usize + 1] = true;
+            let score = -self.negamax(transposition_table, search_info, -beta, -beta + 1, (depth_left - 1 - reduction).max(0), ply + 1);
+            search_info.null_move[ply as usize + 1] = false;
+            self.undo_null_move();
+
+            if search_info.stopped {
+                return Some(0);
             }
-            else if self.draw || self.stalemate {
-                score = 0;
-                if score < best_value {
-                    if minimizing {self.best_move = Some(current_move)}
-                }
+            // a mate found after passing isn't a real mate
+            if score >= beta {
+                return Some(if score >= MATE_IN_MAX_PLY { beta } else { score });
             }
-            self.undo_move();
+        }
 
-            if score < best_value {
-                best_value = score;
-                if minimizing {
-                    self.best_move = Some(current_move);
-                }
-                if score < beta {
-                    beta = score;
+        None
+    }
+
+    // Searches captures and promotions only until the position is quiet, so the
+    // static evaluation is never taken in the middle of an exchange.
+    // The side to move may stand pat on the static evaluation unless it is in check.
+    fn quiescence(&mut self, search_info: &mut SearchInfo, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+        search_info.nodes += 1;
+        if search_info.should_stop() {
+            return 0;
+        }
+        search_info.seldepth = search_info.seldepth.max(ply);
+        search_info.pv_table[ply as usize].clear();
+
+        if ply >= MAX_PLY {
+            return self.static_eval(search_info);
+        }
+
+        let in_check = search_info.config.quiescence_evasions && self.is_in_check();
+        let mut best_value = -INFINITY;
+        let mut moves = MoveList::new();
+        let mut scores = [0; MAX_MOVES];
+        let pinned = self.pinned();
+        if in_check {
+            self.generate_evasions(&mut moves);
+            moves.retain(|evasion| self.is_legal(*evasion, pinned));
+            if moves.is_empty() {
+                return -MATE + ply;
+            }
+        } else {
+            best_value = self.static_eval(search_info);
+            if search_info.config.alpha_beta {
+                if best_value >= beta {
+                    return best_value;
                 }
+                alpha = alpha.max(best_value);
             }
-            if score <= alpha {
-                if minimizing {
-                    self.best_move = Some(current_move);
+
+            // most valuable exchanges first, losing ones are not worth a look
+            self.generate_noisy(&mut moves);
+            moves.retain(|capture| self.is_legal(*capture, pinned));
+            let mut kept = 0;
+            for index in 0..moves.len() {
+                let see = self.see(moves[index]);
+                if !search_info.config.see_pruning || see >= 0 {
+                    moves[kept] = moves[index];
+                    scores[kept] = see;
+                    kept += 1;
                 }
-                return score;
             }
+            moves.truncate(kept);
         }
-        return best_value;
-    }
 
+        for index in 0..moves.len() {
+            // a selection sort, a cutoff makes sorting the rest pointless. Equal scores keep their generation order
+            let best_index = (index..moves.len()).rev().max_by_key(|best| scores[*best]).unwrap_or(index);
+            moves.swap(index, best_index);
+            scores.swap(index, best_index);
+            let current_move = moves[index];
 
-    fn alpha_beta_max_tt(&mut self, transposition_table: &mut TranspositionTable, maximizing: bool, mut alpha: i32, mut beta: i32, depth_left: i32) -> i32 {
+            self.make_search_move(search_info, current_move);
+            search_info.pv_table[ply as usize + 1].clear();
+            let score = if self.is_draw(ply + 1) {
+                0
+            } else {
+                -self.quiescence(search_info, -beta, -alpha, ply + 1)
+            };
+            self.undo_search_move(search_info);
 
-        if let Some(entry) = transposition_table.retrieve_from_table(self) {
-            
-            if entry.depth >= depth_left {
-                match entry.node_type {
-                    Node::Exact => {
-                        if maximizing {
-                            self.best_move = entry.best_move;
-                        }
-                        return entry.score;
-                    },
-                    Node::LowerBound => {
-                        if entry.score > alpha {
-                            alpha = entry.score;
-                        }
-                    }
-                    Node::UpperBound => {
-                        if entry.score < beta {
-                            beta = entry.score;
-                        }
-                    }
-                }
+            if search_info.stopped {
+                return 0;
+            }
+
+            if score > best_value {
+                best_value = score;
+                search_info.update_pv(ply as usize, current_move);
+            }
+
+            if search_info.config.alpha_beta {
+                alpha = alpha.max(score);
                 if alpha >= beta {
-                    if maximizing {
-                        self.best_move = entry.best_move;
-                    }                    
-                    return entry.score;
+                    break;
                 }
+            }
+        }
 
-            }     
+        best_value
+    }
 
-        }
+    // a static evaluation must never be mistaken for a mate
+    fn static_eval(&self, search_info: &mut SearchInfo) -> i32 {
+        search_info.evaluator.evaluate(self).clamp(-MATE_IN_MAX_PLY + 1, MATE_IN_MAX_PLY - 1)
+    }
+
+    fn iterative_deepening(&mut self, transposition_table: &TranspositionTable, search_info: &mut SearchInfo, max_depth: i32) -> i32 {
+        let mut best_score: i32 = 0;
 
-        if depth_left == 0 {
-            // Always evaluate from White's perspective for consistency
-            return match self.turn {
-                Turn::White => NNUE.evaluate(&self.white_accumulator, &self.black_accumulator),
-                Turn::Black => -NNUE.evaluate(&self.black_accumulator, &self.white_accumulator),
+        for depth in 1..=max_depth {
+            search_info.seldepth = 0;
+            // a window around a mate score would only fail, the mate has to be searched in full
+            let score = if !search_info.config.aspiration_windows || depth == 1 || best_score.abs() >= MATE_IN_MAX_PLY {
+                self.negamax(transposition_table, search_info, -INFINITY, INFINITY, depth, 0)
+            } else {
+                self.aspiration_search(transposition_table, search_info, best_score, depth)
             };
+
+            // an unfinished iteration is thrown away, the best move of the last one stands
+            if search_info.stopped {
+                break;
+            }
+            best_score = score;
+            search_info.completed_depth = depth;
+            search_info.pv = search_info.pv_table[0].clone();
+            Self::report_iteration(transposition_table, search_info, depth, score);
+
+            // another iteration would most likely not finish in time
+            if !search_info.pondering() && search_info.time_manager.soft_limit_reached() {
+                break;
+            }
         }
 
-        let mut best_value = i32::MIN;
-        let moves: Vec<Move> = self.generate_legal_moves();
+        best_score
+    }
 
-        for current_move in moves {
+    fn aspiration_search(&mut self, transposition_table: &TranspositionTable, search_info: &mut SearchInfo, guess: i32, depth: i32) -> i32 {
+        let mut delta = search_info.config.aspiration_delta;
+        let mut alpha = guess.saturating_sub(delta).max(-INFINITY);
+        let mut beta = guess.saturating_add(delta).min(INFINITY);
 
-            self.make_move(current_move);
-            let mut score: i32 = self.alpha_beta_min_tt(transposition_table, false, alpha, beta, depth_left - 1);
-            if self.checkmate {
-                self.undo_move();
-                if maximizing {self.best_move = Some(current_move);}
-                match self.turn {
-                    Turn::White => return i32::MAX,
-                    Turn::Black => return i32::MIN,
-                };
-            }
-            else if self.draw || self.stalemate {
-                score = 0;
-                if score > best_value {
-                    if maximizing {self.best_move = Some(current_move)}
-                }
-            }
-            self.undo_move();
-            
-            if score > best_value {
-                best_value = score;
-                if maximizing {
-                    self.best_move = Some(current_move);
-                }
-                if score > alpha {
-                    alpha = score;
-                }
+        loop {
+            let score = self.negamax(transposition_table, search_info, alpha, beta, depth, 0);
+            if search_info.stopped {
+                return score;
             }
-            if score >= beta {
-                if maximizing {
-                    self.best_move = Some(current_move);
-                    transposition_table.store_in_table(self, self.best_move, depth_left, best_value, alpha, beta);
-                }
+            if score <= alpha && alpha > -INFINITY {
+                alpha = alpha.saturating_sub(delta).max(-INFINITY); // fail low, widen window low side
+            } else if score >= beta && beta < INFINITY {
+                beta = beta.saturating_add(delta).min(INFINITY); // fail high, widen window high side
+            } else { // success!
                 return score;
             }
+            delta = delta.saturating_mul(2);
         }
+    }
+
+    // uci info line of a finished iteration
+    fn report_iteration(transposition_table: &TranspositionTable, search_info: &SearchInfo, depth: i32, score: i32) {
+        if !search_info.report {
+            return;
+        }
+        let elapsed = search_info.time_manager.elapsed().as_millis() as u64;
+        let nps = search_info.nodes * 1000 / elapsed.max(1);
+        let pv: Vec<String> = search_info.pv.iter().map(|pv_move| pv_move.to_string()).collect();
+        println!(
+            "info depth {} seldepth {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+            depth,
+            search_info.seldepth,
+            uci_score(score),
+            search_info.nodes,
+            nps,
+            elapsed,
+            transposition_table.hashfull(),
+            pv.join(" ")
+        );
+    }
 
-        transposition_table.store_in_table(self, self.best_move, depth_left, best_value, alpha, beta);
+}
 
-        return best_value;
+// Plies to take off a late quiet move, grows with both the depth and the move number
+fn late_move_reduction(depth_left: i32, moves_searched: usize, pv_node: bool) -> i32 {
+    let reduction = (0.75 + (depth_left as f64).ln() * (moves_searched as f64).ln() / 2.25) as i32;
+    // the principal variation is reduced less, and a reduced search never drops straight to the horizon
+    (reduction - pv_node as i32).clamp(0, depth_left - 2)
+}
 
+// "cp x" or "mate n", n counts moves (not plies) and is negative when the side to move gets mated
+fn uci_score(score: i32) -> String {
+    if score >= MATE_IN_MAX_PLY {
+        format!("mate {}", (MATE - score + 1) / 2)
+    } else if score <= -MATE_IN_MAX_PLY {
+        format!("mate {}", -(MATE + score) / 2)
+    } else {
+        format!("cp {}", score)
     }
+}
 
-    
-    fn alpha_beta_min_tt(&mut self,  transposition_table: &mut TranspositionTable, minimizing: bool, mut alpha: i32, mut beta: i32, depth_left: i32) -> i32 {
-        
-        if let Some(entry) = transposition_table.retrieve_from_table(self) {
-    
-            if entry.depth >= depth_left {
-                match entry.node_type {
-                    Node::Exact => {
-                        if minimizing {
-                            self.best_move = entry.best_move;
-                        }
-                        return entry.score;
-                    },
-                    Node::LowerBound => {
-                        if entry.score > alpha {
-                            alpha = entry.score;
-                        }
-                    }
-                    Node::UpperBound => {
-                        if entry.score < beta {
-                            beta = entry.score;
-                        }
-                    }
-                }
-                if alpha >= beta {
-                    if minimizing {
-                        self.best_move = entry.best_move;
-                    }                    
-                    return entry.score;
-                }
-            }         
+#[cfg(test)]
+mod alphabeta {
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::board::{Board, Turn};
+    use crate::evaluator::EvalType;
+    use crate::time_management::{SearchLimits, TimeManager};
+    use crate::transposition::TranspositionTable;
+    use crate::zobrist::ZOBRIST;
+
+    use super::{uci_score, SearchConfig, SearchInfo, MATE, MAX_DEPTH};
+
+    #[test]
+    fn configurations_agree_on_score() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0";
+        // the minimax tree with a quiescence search at every leaf is far too big for this position
+        let base = SearchConfig {
+            transposition_table: false,
+            quiescence: false,
+            iterative_deepening: false,
+            aspiration_windows: false,
+            principal_variation_search: false,
+            ..SearchConfig::default()
+        };
+        let minimax = SearchConfig { alpha_beta: false, ..base };
+        let configs = [
+            base,
+            SearchConfig { transposition_table: true, ..base },
+            SearchConfig { iterative_deepening: true, ..base },
+            SearchConfig { iterative_deepening: true, aspiration_windows: true, aspiration_delta: 10, ..base },
+            SearchConfig { principal_variation_search: true, ..base },
+            SearchConfig { principal_variation_search: true, iterative_deepening: true, aspiration_windows: true, aspiration_delta: 10, ..base },
+        ];
+
+        let mut board = Board::from_fen(fen.to_string());
+        let (_, expected) = board.search(&TranspositionTable::init(), &mut SearchInfo::new(minimax, EvalType::Nnue.create()), 3);
+
+        for config in configs {
+            let mut board = Board::from_fen(fen.to_string());
+            let (_, score) = board.search(&TranspositionTable::init(), &mut SearchInfo::new(config, EvalType::Nnue.create()), 3);
+            assert_eq!(score, expected, "{:?}", config);
         }
-        
-        if depth_left == 0 {
-            return match self.turn {
-                Turn::White => NNUE.evaluate(&self.white_accumulator, &self.black_accumulator),
-                Turn::Black => -NNUE.evaluate(&self.black_accumulator, &self.white_accumulator),
-            };
+    }
+
+    #[test]
+    fn quiescence_agrees_with_minimax() {
+        let fen = "4k3/2n5/3p4/4p3/3P4/2N5/8/4QK2 w - - 0 1";
+        let minimax = SearchConfig { alpha_beta: false, transposition_table: false, ..SearchConfig::default() };
+
+        let mut board = Board::from_fen(fen.to_string());
+        let (_, expected) = board.search(&TranspositionTable::init(), &mut SearchInfo::new(minimax, EvalType::Nnue.create()), 3);
+        let (_, score) = board.search(&TranspositionTable::init(), &mut SearchInfo::new(SearchConfig::default(), EvalType::Nnue.create()), 3);
+        assert_eq!(score, expected);
+    }
+
+    #[test]
+    fn quiescence_sees_the_recapture() {
+        // Qxe5+ wins a pawn at depth 1 unless the search notices dxe5
+        let mut board = Board::from_fen("4k3/8/3p4/4p3/8/8/8/4QK2 w - - 0 1".to_string());
+        let horizon = SearchConfig { quiescence: false, ..SearchConfig::default() };
+
+        let (best_move, _) = board.search(&TranspositionTable::init(), &mut SearchInfo::new(horizon, EvalType::Hce.create()), 1);
+        assert_eq!(best_move.to_string(), "e1e5");
+        let (best_move, _) = board.search(&TranspositionTable::init(), &mut SearchInfo::new(SearchConfig::default(), EvalType::Hce.create()), 1);
+        assert_ne!(best_move.to_string(), "e1e5");
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1".to_string());
+        for eval_type in EvalType::ALL {
+            let mut search_info = SearchInfo::new(SearchConfig::default(), eval_type.create());
+            let (best_move, _) = board.search(&TranspositionTable::init(), &mut search_info, 2);
+            assert_eq!(best_move.to_string(), "a1a8", "{}", eval_type.name());
         }
+    }
 
-        let mut best_value = i32::MAX;
-        let moves: Vec<Move> = self.generate_legal_moves();
+    #[test]
+    fn movetime_stops_the_search() {
+        let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0".to_string());
+        let mut search_info = SearchInfo::new(SearchConfig::default(), EvalType::Nnue.create());
+        search_info.time_manager = TimeManager::new(&SearchLimits { movetime: Some(200), ..SearchLimits::default() }, board.turn);
 
-        for current_move in moves {
+        let (best_move, _) = board.search(&TranspositionTable::init(), &mut search_info, MAX_DEPTH);
 
-            self.make_move(current_move);
-            let mut score = self.alpha_beta_max_tt(transposition_table, false, alpha, beta, depth_left-1);
-            if self.checkmate {
-                self.undo_move();
-                if minimizing {self.best_move = Some(current_move);}
-                match self.turn {
-                    Turn::White => return i32::MAX,
-                    Turn::Black => return i32::MIN,
-                };
-            }
-            else if self.draw || self.stalemate {
-                score = 0;
-                if score < best_value {
-                    if minimizing {self.best_move = Some(current_move)}
-                }
-            }
-            self.undo_move();
+        assert!(search_info.time_manager.elapsed().as_millis() < 1000);
+        assert!(search_info.completed_depth >= 1 && search_info.completed_depth < MAX_DEPTH);
+        assert!(board.generate_legal_moves().iter().any(|legal| legal.to_string() == best_move.to_string()));
+    }
 
-            if score < best_value {
-                best_value = score;
-                if minimizing {
-                    self.best_move = Some(current_move);
-                }
-                if score < beta {
-                    beta = score;
-                }
-            }
-            if score <= alpha {
-                if minimizing {
-                    self.best_move = Some(current_move);
-                    transposition_table.store_in_table(self, self.best_move, depth_left, best_value, alpha, beta);
-                }
-                return score;
-            }
+    #[test]
+    fn stop_signal_returns_best_move_so_far() {
+        let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0".to_string());
+        let mut search_info = SearchInfo::new(SearchConfig::default(), EvalType::Nnue.create());
+        let signals = search_info.signals.clone();
+
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            signals.stop.store(true, Ordering::Relaxed);
+        });
+        // a fixed depth search that would run for ages without the stop
+        let (best_move, _) = board.search(&TranspositionTable::init(), &mut search_info, MAX_DEPTH);
+        stopper.join().unwrap();
+
+        assert!(search_info.stopped);
+        assert!(board.generate_legal_moves().iter().any(|legal| legal.to_string() == best_move.to_string()));
+    }
+
+    #[test]
+    fn pv_is_a_playable_line() {
+        let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0".to_string());
+        let config = SearchConfig::default();
+        let mut search_info = SearchInfo::new(config, EvalType::Nnue.create());
+        let (best_move, _) = board.search(&TranspositionTable::init(), &mut search_info, 3);
+
+        assert!(!search_info.pv.is_empty());
+        assert_eq!(search_info.pv[0].to_string(), best_move.to_string());
+        for pv_move in search_info.pv.clone() {
+            let legal = board.generate_legal_moves().into_iter().find(|legal| legal.to_string() == pv_move.to_string());
+            board.make_move(legal.expect("pv move is not legal"));
         }
+    }
 
-        transposition_table.store_in_table(self, self.best_move, depth_left, best_value, alpha, beta);
-        
-        return best_value;
+    #[test]
+    fn mate_scores_count_moves() {
+        let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1".to_string());
+        let mut search_info = SearchInfo::new(SearchConfig::default(), EvalType::Nnue.create());
+        let (_, score) = board.search(&TranspositionTable::init(), &mut search_info, 2);
+        assert_eq!(score, MATE - 1);
+        assert_eq!(uci_score(score), "mate 1");
+
+        assert_eq!(uci_score(-MATE + 4), "mate -2");
+        assert_eq!(uci_score(35), "cp 35");
     }
 
-    fn iterative_deepening(&mut self, transposition_table: &mut TranspositionTable, maximizing: bool, max_depth: i32) -> i32 {
-        
-        let mut best_score = 0;
-        let mut guess = 0;
-        let mut delta = 100;
-        
-        for depth in 1..=max_depth{
-            let mut alpha = guess - delta;
-            let mut beta = guess + delta;
+    #[test]
+    fn draws_score_zero() {
+        // taking the queen leaves a lone bishop, everything else loses it
+        let mut board = Board::from_fen("7k/8/8/8/8/8/1q6/B5K1 w - - 0 1".to_string());
+        let mut search_info = SearchInfo::new(SearchConfig::default(), EvalType::Hce.create());
+        let (best_move, score) = board.search(&TranspositionTable::init(), &mut search_info, 3);
+        assert_eq!(best_move.to_string(), "a1b2");
+        assert_eq!(score, 0);
+    }
 
-            loop{
-                best_score = if maximizing{
-                    self.alpha_beta_max_tt(transposition_table, true, alpha, beta, depth)
-                }else{
-                    self.alpha_beta_min_tt(transposition_table, true, alpha, beta, depth)
-                };
-                if best_score <= alpha{
-                    alpha = alpha -delta; //fail low, widen window low side
-                } else if best_score >= beta{
-                    beta = beta + delta; // fail high, widen window high side
-                }else{ // success!
-                    guess = best_score; //want to figure out if it should be zeroed out every new depth or not
-                    break;
-                }
-            delta = delta *2;}
+    #[test]
+    fn mate_distance_survives_the_transposition_table() {
+        // Rb7 Kg8 Ra8# (or Ra7 Kg8 Rb8#), nothing mates in one
+        let fen = "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1";
+        let configs = [
+            SearchConfig { transposition_table: false, ..SearchConfig::default() },
+            SearchConfig::default(),
+        ];
+        for config in configs {
+            let mut board = Board::from_fen(fen.to_string());
+            let mut search_info = SearchInfo::new(config, EvalType::Hce.create());
+            let (best_move, score) = board.search(&TranspositionTable::init(), &mut search_info, 4);
+            assert!(["b1b7", "a2a7"].contains(&best_move.to_string().as_str()), "{:?}", config);
+            assert_eq!(score, MATE - 3, "{:?}", config);
+            assert_eq!(uci_score(score), "mate 2");
         }
-        
-        best_score
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn move_ordering_reduces_nodes() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 0",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+        let unordered = SearchConfig { move_ordering: false, ..SearchConfig::default() };
+        let (mut ordered_nodes, mut unordered_nodes) = (0, 0);
+
+        for fen in fens {
+            let mut board = Board::from_fen(fen.to_string());
+            let mut search_info = SearchInfo::new(unordered, EvalType::Nnue.create());
+            let (_, expected) = board.search(&TranspositionTable::init(), &mut search_info, 3);
+            unordered_nodes += search_info.nodes;
+
+            // the order changes how much is searched, never the score of a full window search
+            let mut search_info = SearchInfo::new(SearchConfig::default(), EvalType::Nnue.create());
+            let (_, score) = board.search(&TranspositionTable::init(), &mut search_info, 3);
+            ordered_nodes += search_info.nodes;
+            assert_eq!(score, expected, "{}", fen);
+        }
+
+        assert!(ordered_nodes * 4 < unordered_nodes, "{} ordered vs {} unordered nodes", ordered_nodes, unordered_nodes);
+    }
+
+    // every pruning, reduction and extension switched on
+    fn selective(config: SearchConfig) -> SearchConfig {
+        SearchConfig {
+            null_move_pruning: true,
+            late_move_reductions: true,
+            futility_pruning: true,
+            reverse_futility_pruning: true,
+            razoring: true,
+            check_extensions: true,
+            ..config
+        }
+    }
+
+    #[test]
+    fn selective_search_keeps_the_mate() {
+        let fen = "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1";
+        let config = selective(SearchConfig::default());
+        let mut board = Board::from_fen(fen.to_string());
+        let (best_move, score) = board.search(&TranspositionTable::init(), &mut SearchInfo::new(config, EvalType::Hce.create()), 4);
+        assert!(["b1b7", "a2a7"].contains(&best_move.to_string().as_str()));
+        assert_eq!(score, MATE - 3);
+    }
+
+    #[test]
+    fn selective_search_reduces_nodes() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+        let full = SearchConfig::default();
+        let (mut full_nodes, mut selective_nodes) = (0, 0);
+
+        for fen in fens {
+            let mut board = Board::from_fen(fen.to_string());
+            let mut search_info = SearchInfo::new(full, EvalType::Nnue.create());
+            board.search(&TranspositionTable::init(), &mut search_info, 5);
+            full_nodes += search_info.nodes;
+
+            let mut search_info = SearchInfo::new(selective(full), EvalType::Nnue.create());
+            let (best_move, _) = board.search(&TranspositionTable::init(), &mut search_info, 5);
+            selective_nodes += search_info.nodes;
+            assert!(board.generate_legal_moves().contains(&best_move), "{}", fen);
+        }
+
+        assert!(selective_nodes * 2 < full_nodes, "{} selective vs {} full nodes", selective_nodes, full_nodes);
+    }
+
+    #[test]
+    fn null_move_is_undone() {
+        let mut board = Board::from_fen("4k3/8/8/3pP3/8/8/8/R3K3 w - d6 0 1".to_string());
+        let key = board.zobrist_key;
+
+        board.make_null_move();
+        assert!(board.turn == Turn::Black && board.en_passant_square.is_none());
+        assert_eq!(board.zobrist_key, ZOBRIST.zobrist_hash(&board));
+        // only pawns and a king left, the guard against zugzwang
+        assert!(!board.has_non_pawn_material());
+
+        board.undo_null_move();
+        assert!(board.turn == Turn::White && board.en_passant_square.is_some());
+        assert_eq!(board.zobrist_key, key);
+        assert!(board.has_non_pawn_material());
+    }
+
+    #[test]
+    fn helper_threads_share_the_table() {
+        let fen = "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1";
+        let config = SearchConfig { threads: 4, ..SearchConfig::default() };
+        let mut board = Board::from_fen(fen.to_string());
+        let (best_move, score) = board.search(&TranspositionTable::init(), &mut SearchInfo::new(config, EvalType::Hce.create()), 4);
+        assert!(["b1b7", "a2a7"].contains(&best_move.to_string().as_str()));
+        assert_eq!(score, MATE - 3);
+    }
+
+    #[test]
+    fn helper_threads_stop_with_the_main_search() {
+        let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0".to_string());
+        let config = SearchConfig { threads: 4, ..SearchConfig::default() };
+        let mut search_info = SearchInfo::new(config, EvalType::Nnue.create());
+        search_info.time_manager = TimeManager::new(&SearchLimits { movetime: Some(200), ..SearchLimits::default() }, board.turn);
+
+        let (best_move, _) = board.search(&TranspositionTable::init(), &mut search_info, MAX_DEPTH);
+
+        assert!(search_info.time_manager.elapsed().as_millis() < 1000);
+        assert!(board.generate_legal_moves().contains(&best_move));
+    }
+
+    #[test]
+    fn helper_threads_fill_the_table_the_main_search_reads() {
+        let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0".to_string());
+        // switched off in the config but the threads still have to share the table
+        let config = SearchConfig { transposition_table: false, threads: 2, ..SearchConfig::default() };
+        let transposition_table = TranspositionTable::init();
+        let mut search_info = SearchInfo::new(config, EvalType::Nnue.create());
+        let (best_move, _) = board.search(&transposition_table, &mut search_info, 4);
+        assert!(!search_info.config.transposition_table, "the search changed the caller's config");
+
+        let entry = transposition_table.retrieve_from_table(&board).expect("the search left no root entry");
+        assert!(entry.depth >= 4);
+        assert!(board.generate_legal_moves().contains(&entry.best_move.unwrap()));
+        assert!(board.generate_legal_moves().contains(&best_move));
+
+        // a single threaded search on the filled table reads what the threads wrote
+        let mut cold_info = SearchInfo::new(SearchConfig::default(), EvalType::Nnue.create());
+        board.search(&TranspositionTable::init(), &mut cold_info, 4);
+        let mut warm_info = SearchInfo::new(SearchConfig::default(), EvalType::Nnue.create());
+        board.search(&transposition_table, &mut warm_info, 4);
+        assert!(warm_info.nodes < cold_info.nodes, "{} nodes on the filled table vs {} on an empty one", warm_info.nodes, cold_info.nodes);
+    }
+
+    #[test]
+    fn eval_timing_counts_every_thread() {
+        let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0".to_string());
+        let config = SearchConfig { threads: 2, ..SearchConfig::default() };
+        let mut search_info = SearchInfo::new(config, EvalType::Kan.create());
+        board.search(&TranspositionTable::init(), &mut search_info, 4);
+
+        let main_thread = search_info.evaluator.timing().unwrap();
+        let all_threads = search_info.eval_timing.unwrap();
+        assert!(all_threads.evaluations > main_thread.evaluations);
+        assert!(all_threads.time >= main_thread.time);
+    }
+}
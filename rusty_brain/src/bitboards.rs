@@ -1,4 +1,3 @@
-use std::hash::{DefaultHasher, Hash, Hasher};
 use std::u64;
 use std::collections::HashMap;
 use crate::board::Turn;
@@ -151,25 +150,6 @@ impl Bitboards {
         }
     }
 
-    pub fn hash_board(&self) -> u64{
-        let mut hasher = DefaultHasher::new();
-        self.white_bishops.hash(&mut hasher);
-        self.white_king.hash(&mut hasher);
-        self.white_knights.hash(&mut hasher);
-        self.white_pawns.hash(&mut hasher);
-        self.white_queens.hash(&mut hasher);
-        self.white_rooks.hash(&mut hasher);
-
-        self.black_bishops.hash(&mut hasher);
-        self.black_king.hash(&mut hasher);
-        self.black_knights.hash(&mut hasher);
-        self.black_pawns.hash(&mut hasher);
-        self.black_queens.hash(&mut hasher);
-        self.black_rooks.hash(&mut hasher);
-       
-        hasher.finish()
-    }
-    
     pub fn get_empty_squares(&self) -> u64 {
         !(self.get_ally_pieces(Turn::White) | self.get_enemy_pieces(Turn::White))
     }
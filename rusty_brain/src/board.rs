@@ -1,12 +1,11 @@
 use core::panic;
-use std::collections::HashMap;
 use crate::bitboards::Bitboards;
 use crate::castling::CastlingRights;
-use crate::magic::Magic;
+use crate::movegen::MoveList;
 use crate::movement::Move;
 use crate::piece::Piece;
 use crate::square::Square;
-use crate::nnue::{Accumulator, Network, NNUE};
+use crate::zobrist::ZOBRIST;
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum Turn {
@@ -14,26 +13,51 @@ pub enum Turn {
    Black, 
 }
 
+impl Turn {
+    pub fn opposite(&self) -> Turn {
+        match self {
+            Turn::White => Turn::Black,
+            Turn::Black => Turn::White,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Board{
     pub bitboards: Bitboards,
     pub turn: Turn,
-    pub rook_attacks: [Vec<u64>; 64],
-    pub bishop_attacks: [Vec<u64>; 64],
-    pub move_log: Vec<Move>,
     pub castling_rights: CastlingRights,
     pub checkmate: bool,
-    pub board_hashes: HashMap<u64, u8>,
     pub stalemate: bool,
     pub draw: bool,
     pub half_move_clock: u8,
-    pub capture_log: Vec<Piece>,
-    pub castling_rights_log: Vec<CastlingRights>,
+    // starts at 1 and goes up after every black move
+    pub full_move_number: u16,
     pub en_passant_square: Option<Square>,
     pub best_move: Option<Move>,
-    pub white_accumulator: Accumulator,
-    pub black_accumulator: Accumulator,
-    pub accumulator_log: Vec<(Accumulator, Accumulator)>,
+    pub zobrist_key: u64,
+    // enemy pieces giving check to the side to move
+    pub checkers: u64,
+    // moves made since the position was set up, the NNUE accumulator of the position is found by it
+    pub accumulator_index: usize,
+    // one entry per move made on the board, the only part of a clone that isn't a plain copy
+    pub state_stack: Vec<StateInfo>,
+}
+
+// What undo_move can't work out from the position after a move: the state of the position
+// the move was made from and the piece it captured. make_move pushes one, undo_move pops it
+#[derive(Clone, Copy)]
+pub struct StateInfo {
+    // None for a null move
+    pub played_move: Option<Move>,
+    pub captured_piece: Option<Piece>,
+    pub castling_rights: CastlingRights,
+    pub en_passant_square: Option<Square>,
+    pub half_move_clock: u8,
+    pub full_move_number: u16,
+    pub zobrist_key: u64,
+    pub checkers: u64,
+    pub accumulator_index: usize,
 }
 
 impl Board {
@@ -41,24 +65,20 @@ impl Board {
         let mut board = Board{
             bitboards: Bitboards::new(),
             turn: Turn::White,
-            rook_attacks: Magic::piece_attacks(true),
-            bishop_attacks: Magic::piece_attacks(false),
-            move_log: Vec::new(),
             castling_rights: CastlingRights::new(),
             checkmate: false,
-            board_hashes: HashMap::new(),
             stalemate: false,
             draw: false,
             half_move_clock: 0,
-            capture_log: Vec::new(),
-            castling_rights_log: Vec::new(),
+            full_move_number: 1,
             en_passant_square: None,
             best_move: None,
-            white_accumulator: Accumulator::new(&NNUE),
-            black_accumulator: Accumulator::new(&NNUE),
-            accumulator_log: Vec::new(),
+            zobrist_key: 0,
+            checkers: 0,
+            accumulator_index: 0,
+            state_stack: Vec::new(),
         };
-        board.init_feature_indices();
+        board.update_position_state();
         board
     }
     
@@ -66,275 +86,153 @@ impl Board {
         let mut board = Board {
             bitboards: Bitboards::empty(),
             turn: Turn::White,
-            rook_attacks: Magic::piece_attacks(true),
-            bishop_attacks: Magic::piece_attacks(false),
-            move_log: Vec::new(),
             castling_rights: CastlingRights::empty(),
             checkmate: false,
-            board_hashes: HashMap::new(),
             stalemate: false,
             draw: false,
             half_move_clock:0,
-            capture_log: Vec::new(),
-            castling_rights_log: Vec::new(),
+            full_move_number: 1,
             en_passant_square: None,
             best_move: None,
-            white_accumulator: Accumulator::new(&NNUE),
-            black_accumulator: Accumulator::new(&NNUE),
-            accumulator_log: Vec::new(),
+            zobrist_key: 0,
+            checkers: 0,
+            accumulator_index: 0,
+            state_stack: Vec::new(),
         };
-        board.init_feature_indices();
+        board.update_position_state();
         board
     }
     
+    // Panics on a malformed FEN, input that isn't trusted goes through try_from_fen
     pub fn from_fen(fen: String) -> Self {
-        let fen_vec: Vec<&str> = fen.split_whitespace().collect();
-        
-        let turn = match fen_vec[1] {
-            "w" => Turn::White,
-            _ => Turn::Black,
-        };
-        
-        let mut castling_rights = CastlingRights::empty();
-        
-        for right in fen_vec[2].chars() {
-            match right {
-                'K' => castling_rights.white_king_side = true,
-                'Q' => castling_rights.white_queen_side = true,
-                'k' => castling_rights.black_king_side = true,
-                'q' => castling_rights.black_queen_side = true,
-                _ => ()
-            }
-        }
-        
-
-        let en_passant_square = match fen_vec[3] {
-            "-" => None,
-            _ => {
-                Some(Square::from(fen_vec[3]))
-            }
-        };
-        
-        let mut board = Board {
-            bitboards: Bitboards::from_fen(fen_vec[0]),
-            turn,
-            rook_attacks: Magic::piece_attacks(true),
-            bishop_attacks: Magic::piece_attacks(false),
-            move_log: Vec::new(),
-            castling_rights,
-            checkmate: false,
-            board_hashes: HashMap::new(),
-            stalemate: false,
-            draw: false,
-            half_move_clock: fen_vec[4].parse().unwrap(),
-            capture_log: Vec::new(),
-            castling_rights_log: Vec::new(),
-            en_passant_square,
-            best_move: None,
-            white_accumulator: Accumulator::new(&NNUE),
-            black_accumulator: Accumulator::new(&NNUE),
-            accumulator_log: Vec::new(),
-        };
-        board.init_feature_indices();
-        board
+        Board::try_from_fen(&fen).unwrap_or_else(|error| panic!("invalid FEN \"{}\": {}", fen.trim(), error))
     }
 
-    // Initialize feature indices for all pieces on the board
-    fn init_feature_indices(&mut self) {
-        // White pieces
-        self.add_piece_features(self.bitboards.white_pawns, Piece::Pawn, Turn::White);
-        self.add_piece_features(self.bitboards.white_knights, Piece::Knight, Turn::White);
-        self.add_piece_features(self.bitboards.white_bishops, Piece::Bishop, Turn::White);
-        self.add_piece_features(self.bitboards.white_rooks, Piece::Rook, Turn::White);
-        self.add_piece_features(self.bitboards.white_queens, Piece::Queen, Turn::White);
-        self.add_piece_features(self.bitboards.white_king, Piece::King, Turn::White);
-        
-        // Black pieces
-        self.add_piece_features(self.bitboards.black_pawns, Piece::Pawn, Turn::Black);
-        self.add_piece_features(self.bitboards.black_knights, Piece::Knight, Turn::Black);
-        self.add_piece_features(self.bitboards.black_bishops, Piece::Bishop, Turn::Black);
-        self.add_piece_features(self.bitboards.black_rooks, Piece::Rook, Turn::Black);
-        self.add_piece_features(self.bitboards.black_queens, Piece::Queen, Turn::Black);
-        self.add_piece_features(self.bitboards.black_king, Piece::King, Turn::Black);
+    // Recompute the key and the checkers from scratch, needed after editing the bitboards directly
+    pub fn update_position_state(&mut self) {
+        self.zobrist_key = ZOBRIST.zobrist_hash(self);
+        self.checkers = self.find_checkers();
     }
 
-    // Compute feature index for a piece at a square
-    fn get_feature_index(&self, piece: Piece, color: Turn, square: u8) -> usize {
-        let piece_base = match (piece, color) {
-            (Piece::Pawn, Turn::White) => 0,
-            (Piece::Knight, Turn::White) => 64,
-            (Piece::Bishop, Turn::White) => 128,
-            (Piece::Rook, Turn::White) => 192,
-            (Piece::Queen, Turn::White) => 256,
-            (Piece::King, Turn::White) => 320,
-            (Piece::Pawn, Turn::Black) => 384,
-            (Piece::Knight, Turn::Black) => 448,
-            (Piece::Bishop, Turn::Black) => 512,
-            (Piece::Rook, Turn::Black) => 576,
-            (Piece::Queen, Turn::Black) => 640,
-            (Piece::King, Turn::Black) => 704,
-        };
-        
-        piece_base + square as usize
+    // The move that led to the position, None at the start or after a null move
+    pub fn last_move(&self) -> Option<Move> {
+        self.state_stack.last().and_then(|state| state.played_move)
     }
 
-    // Add features for all pieces of a certain type on a bitboard
-    // Add features for all pieces of a certain type on a bitboard
-    fn add_piece_features(&mut self, bitboard: u64, piece: Piece, color: Turn) {
-        let mut pieces = bitboard;
-        while pieces != 0 {
-            let square = pieces.trailing_zeros() as u8;
-            
-            // White accumulator - use original piece color and square
-            let feature_idx_white = self.get_feature_index(piece, color, square);
-            
-            // Black accumulator - flip both square AND color
-            let flipped_square = square ^ 56;
-            let flipped_color = match color {
-                Turn::White => Turn::Black,
-                Turn::Black => Turn::White,
-            };
-            let feature_idx_black = self.get_feature_index(piece, flipped_color, flipped_square);
-            
-            // println!("active white {}, active black {}" , feature_idx_white, feature_idx_black);
-            self.white_accumulator.add_feature(feature_idx_white, &NNUE);
-            self.black_accumulator.add_feature(feature_idx_black, &NNUE);
-            pieces &= pieces - 1; // Clear the least significant bit
+    fn save_state(&self, played_move: Option<Move>, captured_piece: Option<Piece>) -> StateInfo {
+        StateInfo {
+            played_move,
+            captured_piece,
+            castling_rights: self.castling_rights,
+            en_passant_square: self.en_passant_square,
+            half_move_clock: self.half_move_clock,
+            full_move_number: self.full_move_number,
+            zobrist_key: self.zobrist_key,
+            checkers: self.checkers,
+            accumulator_index: self.accumulator_index,
         }
     }
 
-    // Update accumulators when a piece moves
-    fn update_piece_feature(&mut self, from: u8, to: u8, piece: Piece, color: Turn) {
-        // White accumulator - use original color and square
-        let old_feature_white = self.get_feature_index(piece, color, from);
-        let new_feature_white = self.get_feature_index(piece, color, to);
-        
-        // Black accumulator - flip both square AND color
-        let flipped_color = match color {
-            Turn::White => Turn::Black,
-            Turn::Black => Turn::White,
-        };
-        let old_feature_black = self.get_feature_index(piece, flipped_color, from ^ 56);
-        let new_feature_black = self.get_feature_index(piece, flipped_color, to ^ 56);
-        
-        self.white_accumulator.remove_feature(old_feature_white, &NNUE);
-        self.black_accumulator.remove_feature(old_feature_black, &NNUE);
-        
-        self.white_accumulator.add_feature(new_feature_white, &NNUE);
-        self.black_accumulator.add_feature(new_feature_black, &NNUE);
+    fn restore_state(&mut self, state: &StateInfo) {
+        self.castling_rights = state.castling_rights;
+        self.en_passant_square = state.en_passant_square;
+        self.half_move_clock = state.half_move_clock;
+        self.full_move_number = state.full_move_number;
+        self.zobrist_key = state.zobrist_key;
+        self.checkers = state.checkers;
+        self.accumulator_index = state.accumulator_index;
     }
 
-    // Remove a piece's feature (for captures)
-    fn remove_piece_feature(&mut self, square: u8, piece: Piece, color: Turn) {
-        // White accumulator - use original color and square
-        let feature_idx_white = self.get_feature_index(piece, color, square);
-        
-        // Black accumulator - flip both square AND color
-        let flipped_color = match color {
-            Turn::White => Turn::Black,
-            Turn::Black => Turn::White,
+    // The key changes of a move that are known before it is made, the pieces moving,
+    // the side to move and the old castling rights and en passant square.
+    // make_move adds the new castling rights and en passant square once they are set
+    fn zobrist_move_key(&self, move_to_make: &Move) -> u64 {
+        let from = move_to_make.get_from();
+        let to = move_to_make.get_to();
+        let flag = move_to_make.get_flags();
+        let turn = self.turn;
+
+        let mut key = ZOBRIST.black_to_move()
+            ^ ZOBRIST.castling_rights(&self.castling_rights)
+            ^ ZOBRIST.en_passant(self.en_passant_square);
+
+        let Some((piece, _)) = self.piece_at(from) else {
+            return key;
         };
-        let feature_idx_black = self.get_feature_index(piece, flipped_color, square ^ 56);
-        
-        self.white_accumulator.remove_feature(feature_idx_white, &NNUE);
-        self.black_accumulator.remove_feature(feature_idx_black, &NNUE);
+        let placed_piece = move_to_make.get_promotion_piece().unwrap_or(piece);
+        key ^= ZOBRIST.piece(piece, turn, from) ^ ZOBRIST.piece(placed_piece, turn, to);
+
+        match flag {
+            Move::EP_CAPTURE => {
+                let captured_square = match turn {
+                    Turn::White => to - 8,
+                    Turn::Black => to + 8,
+                };
+                key ^= ZOBRIST.piece(Piece::Pawn, turn.opposite(), captured_square);
+            },
+            Move::KING_CASTLE => key ^= ZOBRIST.piece(Piece::Rook, turn, from + 3) ^ ZOBRIST.piece(Piece::Rook, turn, from + 1),
+            Move::QUEEN_CASTLE => key ^= ZOBRIST.piece(Piece::Rook, turn, from - 4) ^ ZOBRIST.piece(Piece::Rook, turn, from - 1),
+            _ => {
+                if let Some((captured_piece, color)) = self.piece_at(to) {
+                    key ^= ZOBRIST.piece(captured_piece, color, to);
+                }
+            },
+        }
+
+        key
     }
-        
-    pub fn make_move(&mut self, move_to_make: Move) {
-        // Store previous state for potential undo
-        let prev_white_acc = self.white_accumulator;
-        let prev_black_acc = self.black_accumulator;
 
-        // Store accumulators in history
-        self.accumulator_log.push((prev_white_acc, prev_black_acc));
+    // Piece and its colour standing on a square, None if the square is empty
+    pub fn piece_at(&self, square: u8) -> Option<(Piece, Turn)> {
+        let square_bit = 1u64 << square;
+        let pieces = [
+            (self.bitboards.white_pawns, Piece::Pawn, Turn::White),
+            (self.bitboards.white_knights, Piece::Knight, Turn::White),
+            (self.bitboards.white_bishops, Piece::Bishop, Turn::White),
+            (self.bitboards.white_rooks, Piece::Rook, Turn::White),
+            (self.bitboards.white_queens, Piece::Queen, Turn::White),
+            (self.bitboards.white_king, Piece::King, Turn::White),
+            (self.bitboards.black_pawns, Piece::Pawn, Turn::Black),
+            (self.bitboards.black_knights, Piece::Knight, Turn::Black),
+            (self.bitboards.black_bishops, Piece::Bishop, Turn::Black),
+            (self.bitboards.black_rooks, Piece::Rook, Turn::Black),
+            (self.bitboards.black_queens, Piece::Queen, Turn::Black),
+            (self.bitboards.black_king, Piece::King, Turn::Black),
+        ];
+
+        pieces.iter()
+            .find(|(bitboard, _, _)| bitboard & square_bit != 0)
+            .map(|&(_, piece, color)| (piece, color))
+    }
 
+    pub fn make_move(&mut self, move_to_make: Move) {
         self.checkmate = false;
         let start_position = 1 << move_to_make.get_from();
         let end_position = 1 << move_to_make.get_to();
         let not_starting_position = !start_position;
         let flag = move_to_make.get_flags();
 
-        self.move_log.push(move_to_make);
-        self.castling_rights_log.push(self.castling_rights);
-        self.en_passant_square = None;
+        let zobrist_key = self.zobrist_key ^ self.zobrist_move_key(&move_to_make);
 
-        // Get source and destination squares
-        let from = move_to_make.get_from();
-        let to = move_to_make.get_to();
-        
-        // Determine piece being moved
-        let piece = self.get_piece_at_square(from);
-        let color = self.turn;
-        
-        // Handle captures separately to update accumulator
-        if flag == Move::CAPTURE || flag == Move::EP_CAPTURE || 
-        (flag >= Move::QUEEN_PROMO_CAPTURE && flag <= Move::BISHOP_PROMO_CAPTURE) {
-            // Remove captured piece from accumulator
-            let captured_piece = self.get_captured_piece(to, flag);
-            let captured_color = if self.turn == Turn::White { Turn::Black } else { Turn::White };
-            self.remove_piece_feature(to, captured_piece, captured_color);
-        }
-        
-        // Handle regular piece movement
-        if flag == Move::EP_CAPTURE {
-            // Special handling for en passant capture
-            let ep_square = if self.turn == Turn::White { to - 8 } else { to + 8 };
-            self.remove_piece_feature(ep_square, Piece::Pawn, if self.turn == Turn::White { Turn::Black } else { Turn::White });
-        }
-        
-        // Handle promotions
-        if flag >= Move::QUEEN_PROMOTION && flag <= Move::BISHOP_PROMO_CAPTURE {
-            // Remove pawn being promoted
-            self.remove_piece_feature(from, Piece::Pawn, color);
-            
-            // Add new promoted piece
-            let promoted_piece = match flag {
-                Move::QUEEN_PROMOTION | Move::QUEEN_PROMO_CAPTURE => Piece::Queen,
-                Move::KNIGHT_PROMOTION | Move::KNIGHT_PROMO_CAPTURE => Piece::Knight,
-                Move::ROOK_PROMOTION | Move::ROOK_PROMO_CAPTURE => Piece::Rook,
-                Move::BISHOP_PROMOTION | Move::BISHOP_PROMO_CAPTURE => Piece::Bishop,
-                _ => panic!("Invalid promotion flag"),
-            };
-            
-            // White accumulator
-            let feature_idx_white = self.get_feature_index(promoted_piece, color, to);
-            
-            // Black accumulator - flip both square AND color
-            let flipped_color = match color {
-                Turn::White => Turn::Black,
-                Turn::Black => Turn::White,
-            };
-            let feature_idx_black = self.get_feature_index(promoted_piece, flipped_color, to ^ 56);
-            
-            self.white_accumulator.add_feature(feature_idx_white, &NNUE);
-            self.black_accumulator.add_feature(feature_idx_black, &NNUE);
-        } else if flag == Move::KING_CASTLE {
-            // Update king features for kingside castling
-            self.update_piece_feature(from, to, Piece::King, color);
-            
-            // Update rook features for kingside castling
-            let rook_from = if color == Turn::White { 7 } else { 63 };
-            let rook_to = if color == Turn::White { 5 } else { 61 };
-            self.update_piece_feature(rook_from, rook_to, Piece::Rook, color);
-        } else if flag == Move::QUEEN_CASTLE {
-            // Update king features for queenside castling
-            self.update_piece_feature(from, to, Piece::King, color);
-            
-            // Update rook features for queenside castling
-            let rook_from = if color == Turn::White { 0 } else { 56 };
-            let rook_to = if color == Turn::White { 3 } else { 59 };
-            self.update_piece_feature(rook_from, rook_to, Piece::Rook, color);
-        } else if flag < Move::QUEEN_PROMOTION || flag > Move::BISHOP_PROMO_CAPTURE {
-            // Normal piece movement (not castling, not promotion)
-            self.update_piece_feature(from, to, piece, color);
+        let captured_piece = match flag {
+            Move::EP_CAPTURE => Some(Piece::Pawn),
+            _ if move_to_make.is_capture() => self.piece_at(move_to_make.get_to()).map(|(piece, _)| piece),
+            _ => None,
+        };
+        self.state_stack.push(self.save_state(Some(move_to_make), captured_piece));
+        self.accumulator_index += 1;
+        self.en_passant_square = None;
+        // captures and pawn moves set it back to 0
+        self.half_move_clock = self.half_move_clock.saturating_add(1);
+        if self.turn == Turn::Black {
+            self.full_move_number = self.full_move_number.saturating_add(1);
         }
 
         match flag {
             Move::CAPTURE | Move::QUEEN_PROMO_CAPTURE | Move::KNIGHT_PROMO_CAPTURE |
             Move::ROOK_PROMO_CAPTURE | Move::BISHOP_PROMO_CAPTURE 
              => {               
-                self.board_hashes = HashMap::new();
-                self.half_move_clock =0;
+                self.half_move_clock = 0;
                 self.make_capture(&move_to_make)
              },
             _ => (),
@@ -375,8 +273,7 @@ impl Board {
                             self.bitboards.white_pawns |= end_position;
                         },
                     }
-                    self.board_hashes = HashMap::new();
-                    self.half_move_clock =0;
+                    self.half_move_clock = 0;
                 }else if start_position & self.bitboards.white_knights != 0 {
                     self.bitboards.white_knights &= not_starting_position;
                     self.bitboards.white_knights |= end_position;
@@ -441,8 +338,7 @@ impl Board {
                             self.bitboards.black_pawns |= end_position;
                         },
                     }
-                    self.board_hashes = HashMap::new();
-                    self.half_move_clock =0;
+                    self.half_move_clock = 0;
                 }else if start_position & self.bitboards.black_knights != 0 {
                     self.bitboards.black_knights &= not_starting_position;
                     self.bitboards.black_knights |= end_position;
@@ -471,72 +367,96 @@ impl Board {
                     self.bitboards.black_king |= end_position;
 
                 }
-                self.half_move_clock +=1;
                 self.turn = Turn::White;
             }
         }
 
-        let count = self.board_hashes.entry(self::Bitboards::hash_board(&self.bitboards)).or_insert(0);
-        *count +=1; 
-        if *count == 3 {
-            self.draw = true; // should probably be turned into a function to stop game
-        }        
+        self.zobrist_key = zobrist_key
+            ^ ZOBRIST.castling_rights(&self.castling_rights)
+            ^ ZOBRIST.en_passant(self.en_passant_square);
+        debug_assert_eq!(self.zobrist_key, ZOBRIST.zobrist_hash(self), "incremental zobrist key out of sync after {}", move_to_make);
+        self.checkers = self.find_checkers();
     }
 
-    // Helper to get piece type at a square
-    fn get_piece_at_square(&self, square: u8) -> Piece {
-        let square_bit = 1u64 << square;
-        
-        if self.turn == Turn::White {
-            if self.bitboards.white_pawns & square_bit != 0 { return Piece::Pawn; }
-            if self.bitboards.white_knights & square_bit != 0 { return Piece::Knight; }
-            if self.bitboards.white_bishops & square_bit != 0 { return Piece::Bishop; }
-            if self.bitboards.white_rooks & square_bit != 0 { return Piece::Rook; }
-            if self.bitboards.white_queens & square_bit != 0 { return Piece::Queen; }
-            if self.bitboards.white_king & square_bit != 0 { return Piece::King; }
-        } else {
-            if self.bitboards.black_pawns & square_bit != 0 { return Piece::Pawn; }
-            if self.bitboards.black_knights & square_bit != 0 { return Piece::Knight; }
-            if self.bitboards.black_bishops & square_bit != 0 { return Piece::Bishop; }
-            if self.bitboards.black_rooks & square_bit != 0 { return Piece::Rook; }
-            if self.bitboards.black_queens & square_bit != 0 { return Piece::Queen; }
-            if self.bitboards.black_king & square_bit != 0 { return Piece::King; }
-        }
-        
-        panic!("No piece found at square {}", square);
+    // Passes the turn without moving a piece, for null move pruning.
+    // Nothing but the side to move, the en passant square and the key change
+    pub fn make_null_move(&mut self) {
+        self.state_stack.push(self.save_state(None, None));
+        self.zobrist_key ^= ZOBRIST.black_to_move() ^ ZOBRIST.en_passant(self.en_passant_square);
+        self.en_passant_square = None;
+        self.turn = self.turn.opposite();
+        self.checkers = self.find_checkers();
+    }
+
+    pub fn undo_null_move(&mut self) {
+        let state = self.state_stack.pop().expect("undo_null_move without a move to undo");
+        debug_assert!(state.played_move.is_none(), "undo_null_move after a real move");
+        self.restore_state(&state);
+        self.turn = self.turn.opposite();
+    }
+
+    // Positions the search scores as a draw without looking any further.
+    // ply is the distance from the search root: a position that already came up after the root
+    // is a draw the second time, the side repeating it could keep doing so. One that was only
+    // on the board before the root, in the game, has to come up a third time
+    pub fn is_draw(&self, ply: i32) -> bool {
+        self.is_fifty_move_draw() || self.has_insufficient_material() || self.is_repetition(ply)
     }
 
-    // Helper to determine captured piece
-    fn get_captured_piece(&self, square: u8, flag: u8) -> Piece {
-        if flag == Move::EP_CAPTURE {
-            return Piece::Pawn;
+    // A hundred plies without a capture or pawn move, unless the last of them mated
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.half_move_clock >= 100 && (self.checkers == 0 || self.has_legal_move())
+    }
+
+    // Neither side has the material to mate: bare kings, a single minor piece,
+    // or only bishops that all stand on squares of the same colour
+    pub fn has_insufficient_material(&self) -> bool {
+        const LIGHT_SQUARES: u64 = 0x55AA55AA55AA55AA;
+        let bitboards = &self.bitboards;
+        let heavy_or_pawns = bitboards.white_pawns | bitboards.black_pawns
+            | bitboards.white_rooks | bitboards.black_rooks
+            | bitboards.white_queens | bitboards.black_queens;
+        if heavy_or_pawns != 0 {
+            return false;
         }
-        
-        let square_bit = 1u64 << square;
-        let enemy_color = if self.turn == Turn::White { Turn::Black } else { Turn::White };
-        
-        match enemy_color {
-            Turn::White => {
-                if self.bitboards.white_pawns & square_bit != 0 { return Piece::Pawn; }
-                if self.bitboards.white_knights & square_bit != 0 { return Piece::Knight; }
-                if self.bitboards.white_bishops & square_bit != 0 { return Piece::Bishop; }
-                if self.bitboards.white_rooks & square_bit != 0 { return Piece::Rook; }
-                if self.bitboards.white_queens & square_bit != 0 { return Piece::Queen; }
-                // King can't be captured
-            },
-            Turn::Black => {
-                if self.bitboards.black_pawns & square_bit != 0 { return Piece::Pawn; }
-                if self.bitboards.black_knights & square_bit != 0 { return Piece::Knight; }
-                if self.bitboards.black_bishops & square_bit != 0 { return Piece::Bishop; }
-                if self.bitboards.black_rooks & square_bit != 0 { return Piece::Rook; }
-                if self.bitboards.black_queens & square_bit != 0 { return Piece::Queen; }
-                // King can't be captured
+
+        let knights = bitboards.white_knights | bitboards.black_knights;
+        let bishops = bitboards.white_bishops | bitboards.black_bishops;
+        (knights | bishops).count_ones() <= 1
+            || (knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0))
+    }
+
+    fn is_repetition(&self, ply: i32) -> bool {
+        // only the positions since the last capture or pawn move can come back,
+        // and only every other one has the same side to move
+        let reversible_plies = (self.half_move_clock as usize).min(self.state_stack.len());
+        let mut repetitions = 0;
+        for distance in 1..=reversible_plies {
+            let state = &self.state_stack[self.state_stack.len() - distance];
+            // a null move isn't a move of the game, nothing before it counts
+            if state.played_move.is_none() {
+                return false;
+            }
+            if distance % 2 == 0 && state.zobrist_key == self.zobrist_key {
+                repetitions += 1;
+                if (distance as i32) < ply || repetitions == 2 {
+                    return true;
+                }
             }
         }
-        
-        panic!("No piece found at capture square {}", square);
+        false
     }
-    
+
+    // Knights, bishops, rooks or queens of the side to move, without them zugzwang is likely
+    // and passing the turn is no longer a safe lower bound
+    pub fn has_non_pawn_material(&self) -> bool {
+        let pieces = match self.turn {
+            Turn::White => self.bitboards.white_knights | self.bitboards.white_bishops | self.bitboards.white_rooks | self.bitboards.white_queens,
+            Turn::Black => self.bitboards.black_knights | self.bitboards.black_bishops | self.bitboards.black_rooks | self.bitboards.black_queens,
+        };
+        pieces != 0
+    }
+
     fn make_en_passant(&mut self, end_position: u64) {
         match self.turn {
             Turn::White => {
@@ -723,58 +643,43 @@ impl Board {
             Turn::White => {
                 if self.bitboards.black_pawns & square_captured != 0 {
                     self.bitboards.black_pawns &= !square_captured;
-                    self.capture_log.push(Piece::Pawn);
                 }else if self.bitboards.black_knights & square_captured != 0 {
                     self.bitboards.black_knights &= !square_captured;
-                    self.capture_log.push(Piece::Knight);
                 }else if self.bitboards.black_bishops & square_captured != 0 {
                     self.bitboards.black_bishops &= !square_captured;
-                    self.capture_log.push(Piece::Bishop);
                 }else if self.bitboards.black_queens & square_captured != 0 {
                     self.bitboards.black_queens &= !square_captured;
-                    self.capture_log.push(Piece::Queen);
                 }else if self.bitboards.black_rooks & square_captured != 0 {
                     self.bitboards.black_rooks &= !square_captured;
-                    self.capture_log.push(Piece::Rook);
-                    self.check_captured_rook(move_to_make, self.bitboards.black_rooks);
+                    self.check_captured_rook(move_to_make);
                 }
             },
             Turn::Black => {
                 if self.bitboards.white_pawns & square_captured != 0 {
                     self.bitboards.white_pawns &= !square_captured;
-                    self.capture_log.push(Piece::Pawn);
                 }else if self.bitboards.white_knights & square_captured != 0 {
                     self.bitboards.white_knights &= !square_captured;
-                    self.capture_log.push(Piece::Knight);
                 }else if self.bitboards.white_bishops & square_captured != 0 {
                     self.bitboards.white_bishops &= !square_captured;
-                    self.capture_log.push(Piece::Bishop);
                 }else if self.bitboards.white_queens & square_captured != 0 {
                     self.bitboards.white_queens &= !square_captured;
-                    self.capture_log.push(Piece::Queen);
                 }else if self.bitboards.white_rooks & square_captured != 0 {
                     self.bitboards.white_rooks &= !square_captured;
-                    self.capture_log.push(Piece::Rook);
-                    self.check_captured_rook(move_to_make, self.bitboards.white_rooks);
+                    self.check_captured_rook(move_to_make);
                 }
             }
         }
     }
     
-    fn check_captured_rook(&mut self, move_to_make: &Move, rook_bitboard: u64) {
-        let end_square = move_to_make.get_to();
-        let square_captured = !(1 << end_square);
-        
-        if square_captured & rook_bitboard != 0 {
-            match Square::from(end_square) {
-                Square::A1 => self.castling_rights.white_queen_side = false,
-                Square::H1 => self.castling_rights.white_king_side = false,
-                Square::A8 => self.castling_rights.black_queen_side = false,
-                Square::H8 => self.castling_rights.black_king_side = false,
-                _ => ()
-            }
+    // a rook captured on its corner takes the castling right of that side with it
+    fn check_captured_rook(&mut self, move_to_make: &Move) {
+        match Square::from(move_to_make.get_to()) {
+            Square::A1 => self.castling_rights.white_queen_side = false,
+            Square::H1 => self.castling_rights.white_king_side = false,
+            Square::A8 => self.castling_rights.black_queen_side = false,
+            Square::H8 => self.castling_rights.black_king_side = false,
+            _ => ()
         }
-        
     }
 
     fn check_rook(&mut self, move_to_make: &Move) {
@@ -824,27 +729,23 @@ impl Board {
     }
     
     pub fn undo_move(&mut self) {
-        let last_move = self.move_log.pop().unwrap();
+        let state = self.state_stack.pop().expect("undo_move without a move to undo");
+        let last_move = state.played_move.expect("undo_move after a null move");
+        self.restore_state(&state);
+        let captured_piece = state.captured_piece;
         let end_position = 1 << last_move.get_to();
         let start_position = 1 << last_move.get_from();
         let flag = last_move.get_flags();
 
-        // Restore previous accumulator state
-        if let Some((prev_white_acc, prev_black_acc)) = self.accumulator_log.pop() {
-            self.white_accumulator = prev_white_acc;
-            self.black_accumulator = prev_black_acc;
-        }
-        
         match flag {
-            Move::CAPTURE => self.undo_capture(end_position), 
+            Move::CAPTURE => self.undo_capture(end_position, captured_piece),
             Move::EP_CAPTURE => self.undo_en_passant(end_position),
             Move::KING_CASTLE => self.undo_king_side_castling(start_position, end_position),
             Move::QUEEN_CASTLE => self.undo_queen_side_castling(start_position, end_position),
             Move::BISHOP_PROMOTION | Move::ROOK_PROMOTION | Move::QUEEN_PROMOTION | Move::KNIGHT_PROMOTION | 
             Move::ROOK_PROMO_CAPTURE | Move::BISHOP_PROMO_CAPTURE | Move::KNIGHT_PROMO_CAPTURE | Move::QUEEN_PROMO_CAPTURE 
             => {
-                self.undo_promotion(start_position, end_position, flag);
-                self.castling_rights = self.castling_rights_log.pop().unwrap();
+                self.undo_promotion(start_position, end_position, flag, captured_piece);
                 return; //ignore the rest of the code
             },
             _ => ()
@@ -910,16 +811,15 @@ impl Board {
         self.checkmate = false;
         self.stalemate = false;
         self.draw = false;
-        self.castling_rights = self.castling_rights_log.pop().unwrap();
     }
 
-    fn undo_promotion(&mut self, start_position: u64, end_position: u64, flag: u8) {
+    fn undo_promotion(&mut self, start_position: u64, end_position: u64, flag: u8, captured_piece: Option<Piece>) {
         
         //handle promo captures
         match flag {
             Move::ROOK_PROMO_CAPTURE | Move::BISHOP_PROMO_CAPTURE | 
             Move::KNIGHT_PROMO_CAPTURE | Move::QUEEN_PROMO_CAPTURE =>
-             self.undo_capture(end_position),
+             self.undo_capture(end_position, captured_piece),
             _ => ()
         }
         //reset board, remove the promoted piece and revert the pawns
@@ -973,10 +873,13 @@ impl Board {
 
     }
 
-    fn undo_capture(&mut self, end_position: u64) {
+    fn undo_capture(&mut self, end_position: u64, captured_piece: Option<Piece>) {
+        let Some(captured_piece) = captured_piece else {
+            return;
+        };
         match self.turn {
             Turn::White => {
-                match self.capture_log.pop().unwrap() {
+                match captured_piece {
                     Piece::Pawn => self.bitboards.white_pawns |= end_position,
                     Piece::Knight => self.bitboards.white_knights |= end_position,
                     Piece::Bishop => self.bitboards.white_bishops |= end_position,
@@ -986,7 +889,7 @@ impl Board {
                 }
             },
             Turn::Black => {
-                match self.capture_log.pop().unwrap() {
+                match captured_piece {
                     Piece::Pawn => self.bitboards.black_pawns |= end_position,
                     Piece::Knight => self.bitboards.black_knights |= end_position,
                     Piece::Bishop => self.bitboards.black_bishops |= end_position,
@@ -999,7 +902,6 @@ impl Board {
     }
     
     fn undo_en_passant(&mut self, end_position: u64) {
-        self.en_passant_square = Some(Square::from(end_position.trailing_zeros() as u8));
         match self.turn {
             Turn::White => self.bitboards.white_pawns |= end_position << 8,
             Turn::Black => self.bitboards.black_pawns |= end_position >> 8,
@@ -1041,369 +943,28 @@ impl Board {
     }
     
     pub fn generate_legal_moves(&mut self) -> Vec<Move> {
-        let (checks, pins) = self.checks_and_pins();
-        let moves;
-        if checks.len() == 1 { // you have to block the check or capture the piece checking, keeping pins in mind
-            moves = self.generate_moves(&pins, checks[0]);
-            if moves.len() == 0{
-                self.checkmate = true
-            }
-        }else if checks.len() == 2 { // double check, have to move the king
-            moves = self.king_moves();
-            if moves.len() == 0{
-                self.checkmate =true
-            }
-        }else { // there is no check, you just have to take care of pins
-            moves = self.generate_moves(&pins, !0);
-            if moves.len() == 0{
-                self.stalemate =true;
+        let in_check = self.checkers != 0;
+        let mut moves = MoveList::new();
+        self.generate_pseudo_legal(&mut moves);
+        let pinned = self.pinned();
+        moves.retain(|pseudo_legal| self.is_legal(*pseudo_legal, pinned));
+
+        if moves.is_empty() {
+            if in_check {
+                self.checkmate = true;
+            } else {
+                self.stalemate = true;
                 self.draw = true;
-            }    
-        }
-        moves
-    }
-    
-    pub fn generate_moves(&mut self, pins: &Vec<u8>, check_bitboard: u64) -> Vec<Move> {
-        let mut moves = Vec::new();
-
-        let mut pawn_moves = self.pawn_moves(pins, check_bitboard);
-        moves.append(&mut pawn_moves);
-
-        let mut queen_moves = self.queen_moves(pins, check_bitboard);
-        moves.append(&mut queen_moves);
-
-        let mut rooks_moves = self.rook_moves(pins, check_bitboard);
-        moves.append(&mut rooks_moves);
-
-        let mut bishop_moves = self.bishop_moves(pins, check_bitboard);
-        moves.append(&mut bishop_moves);
-
-        let mut knight_moves = self.knight_moves(pins, check_bitboard);
-        moves.append(&mut knight_moves);
-
-        let mut king_moves = self.king_moves();
-        moves.append(&mut king_moves);
-
-        moves
-    }
-    
-    pub fn checks_and_pins(&self) -> (Vec<u64>, Vec<u8>) {
-        let (king_bitboard, rooks_bitboard, bishops_bitboard, queen_bitboard, knight_bitboard, pawn_bitboard) = match self.turn {
-            Turn::White => (self.bitboards.white_king, self.bitboards.black_rooks, self.bitboards.black_bishops, self.bitboards.black_queens, self.bitboards.black_knights, self.bitboards.black_pawns),
-            Turn::Black => (self.bitboards.black_king, self.bitboards.white_rooks, self.bitboards.white_bishops, self.bitboards.white_queens, self.bitboards.white_knights, self.bitboards.white_pawns)
-        };
-
-        let ally_bitboard = self.bitboards.get_ally_pieces(self.turn);
-
-        let mut checks = Vec::new();
-        let mut pins = Vec::new();
-
-        let orth_directions = [Bitboards::move_north, Bitboards::move_east, Bitboards::move_south, Bitboards::move_west];
-        let diag_directions = [Bitboards::move_north_west, Bitboards::move_north_east, Bitboards::move_south_east, Bitboards::move_south_west]; 
-        
-        let orth_bitboard = rooks_bitboard | queen_bitboard;
-        let diag_bitboard = bishops_bitboard | queen_bitboard;
-        
-        for direction in orth_directions {
-            self.get_checks_and_pins(&mut checks, &mut pins, king_bitboard, orth_bitboard, ally_bitboard, direction);
-        }
-
-        for direction in diag_directions {
-            self.get_checks_and_pins(&mut checks, &mut pins, king_bitboard, diag_bitboard, ally_bitboard, direction);
-        }
-        
-        // handle pawn checks
-        let opp_pawn_square = match self.turn {
-            Turn::Black => {
-                let pawn_checks_bitboard = Bitboards::move_south_east(king_bitboard) | Bitboards::move_south_west(king_bitboard);
-                pawn_checks_bitboard & pawn_bitboard 
-            },
-            Turn::White => {
-                let pawn_checks_bitboard = Bitboards::move_north_east(king_bitboard) | Bitboards::move_north_west(king_bitboard);
-                pawn_checks_bitboard & pawn_bitboard 
-            }
-        };
-        
-        if opp_pawn_square != 0 {
-            checks.push(opp_pawn_square);
-        }
-        
-        let king_as_knight = self.get_knight_attacked_squares(king_bitboard);
-        let opp_knight_square = knight_bitboard & king_as_knight;
-        
-        if opp_knight_square != 0 {
-            checks.push(opp_knight_square);
-        }
-
-        (checks, pins)
-    } 
-    
-    fn get_checks_and_pins(&self, checks: &mut Vec<u64>, pins: &mut Vec<u8>, king_bitboard: u64, enemy_bitboard: u64, ally_bitboard: u64, move_fn: fn(u64) -> u64) {
-        let non_attacking_enemy = self.bitboards.get_enemy_pieces(self.turn);
-        let mut next_bitboard = move_fn(king_bitboard);
-        let mut possible_check = next_bitboard;
-        let mut pins_ctr = 0 ;
-        let mut flag = false ;
-        while next_bitboard != 0 {
-            if next_bitboard & ally_bitboard != 0 {
-                flag = true;
-                if pins_ctr == 0 {
-                    pins.push(next_bitboard.trailing_zeros() as u8);
-                    pins_ctr +=1;
-                }
-                else {
-                    break;
-                }
-            }else if next_bitboard & enemy_bitboard != 0 {
-                flag = false;
-                if pins_ctr == 0 {
-                    checks.push(possible_check);
-                }
-                break;
-            }else if next_bitboard & non_attacking_enemy != 0 {
-                break;
-            }
-            next_bitboard = move_fn(next_bitboard);
-            possible_check |= next_bitboard;
-        }
-        if flag{
-            pins.pop();
-        }
-    }
-
-    fn get_attacked_squares(&self) -> u64 {
-        match self.turn {
-            Turn::White => {
-                self.get_pawns_attacked_squares() |
-                self.get_knight_attacked_squares(self.bitboards.black_knights) |
-                self.get_bishop_attacked_squares(&self.bitboards.black_bishops) |
-                self.get_rook_attacked_squares(&self.bitboards.black_rooks) |
-                self.get_queen_attacked_squares(&self.bitboards.black_queens) |
-                self.get_king_attacked_squares(self.bitboards.black_king)
-            },
-            Turn::Black => {
-                self.get_pawns_attacked_squares() |
-                self.get_knight_attacked_squares(self.bitboards.white_knights) |
-                self.get_bishop_attacked_squares(&self.bitboards.white_bishops) |
-                self.get_rook_attacked_squares(&self.bitboards.white_rooks) |
-                self.get_queen_attacked_squares(&self.bitboards.white_queens) |
-                self.get_king_attacked_squares(self.bitboards.white_king)
             }
         }
+        moves.to_vec()
     }
     
-    pub fn pawn_moves(&mut self, pins: &Vec<u8>, check_bitboard: u64) -> Vec<Move> {
-        let mut moves = Vec::new(); 
-
-        let not_a_file : u64 = 0xfefefefefefefefe;
-        let not_h_file : u64 = 0x7f7f7f7f7f7f7f7f;
-
-        let empty_bitboard = self.bitboards.get_empty_squares();
-
-        // AND with checkbitboard
-        let enemy_bitboard = self.bitboards.get_enemy_pieces(self.turn) & check_bitboard;
-
-        let (mut single_push_bitboard, mut double_push_bitboard, push_direction, right_capture_mask, left_capture_mask, mut right_captures_bitboard, mut left_captures_bitboard) =
-        match self.turn {
-            Turn::White => {
-                let rank = 0x00000000FF000000;
-                let single_push_bitboard = (self.bitboards.white_pawns << 8) & empty_bitboard; 
-                (
-                    single_push_bitboard & check_bitboard,
-                    (single_push_bitboard << 8) & empty_bitboard & rank & check_bitboard,
-                    -1,
-                    9,
-                    7,
-                    (self.bitboards.white_pawns << 9) & not_a_file,
-                    (self.bitboards.white_pawns << 7) & not_h_file        
-                )
-            },
-            Turn::Black => {
-                let rank = 0x000000FF00000000; 
-                let single_push_bitboard = (self.bitboards.black_pawns >> 8) & empty_bitboard; 
-                (
-                    single_push_bitboard & check_bitboard,
-                    (single_push_bitboard >> 8) & empty_bitboard & rank & check_bitboard,
-                    1,
-                    7,
-                    9,
-                    (self.bitboards.black_pawns >> 7) & not_a_file,
-                    (self.bitboards.black_pawns >> 9) & not_h_file
-                )
-            }
-        };
-        
-        let promotion_rank:u64 = match push_direction {
-            1 => 0x00000000000000FF, // black, so promotion is 1st rank
-            _ => 0xFF00000000000000, // -1 is white so promotion is 8th rank
-        };
-        
-        Self::get_push_moves(self, &mut moves, &mut single_push_bitboard, push_direction, pins, promotion_rank); 
-        Self::get_double_push_moves(self, &mut moves, &mut double_push_bitboard, push_direction, pins); 
-
-        if let Some(en_passant_capture) = self.en_passant_square {
-            Self::get_en_passant_moves(self, &mut moves, en_passant_capture, &mut right_captures_bitboard, right_capture_mask, push_direction, check_bitboard, pins);
-            Self::get_en_passant_moves(self, &mut moves, en_passant_capture, &mut left_captures_bitboard, left_capture_mask, push_direction, check_bitboard, pins);
-        }
-        
-        right_captures_bitboard &= enemy_bitboard;
-        left_captures_bitboard &= enemy_bitboard;
-
-        Self::get_capture_moves(self,&mut moves , &mut right_captures_bitboard, right_capture_mask, push_direction, pins); 
-        Self::get_capture_moves(self,&mut moves , &mut left_captures_bitboard, left_capture_mask, push_direction, pins); 
-        moves
-    }
-
-    fn get_pawns_attacked_squares(&self) -> u64 {
-        let not_a_file : u64 = 0xfefefefefefefefe;
-        let not_h_file : u64 = 0x7f7f7f7f7f7f7f7f;
-        
-        match self.turn {
-            Turn::Black => {
-                let right_capture = (self.bitboards.white_pawns << 9) & not_a_file;
-                let left_capture = (self.bitboards.white_pawns << 7) & not_h_file;                
-                right_capture | left_capture
-            },
-            Turn::White => {
-                let right_capture = (self.bitboards.black_pawns >> 7) & not_a_file;
-                let left_capture = (self.bitboards.black_pawns >> 9) & not_h_file;                
-                right_capture | left_capture
-            }
-        }
-
-    }
-
-    fn get_push_moves(&self, moves: &mut Vec<Move>, push_bitboard: &mut u64, push_direction: i32, pins: &Vec<u8>, promotion_rank: u64) {
-        while *push_bitboard != 0
-        {
-            let end_square = push_bitboard.trailing_zeros() as i32; 
-            let start_square = end_square + (8*push_direction); 
-            let curr_rank: u64 = Bitboards::rank_mask_to_end(end_square as u8);
-            let valid_position = *push_bitboard & (!*push_bitboard + 1); 
-            let legal_position = Self::get_legal_bitboard(self, &(start_square as u8), pins, &valid_position);
-            
-            if legal_position != 0 {
-                //promotion
-                if curr_rank == promotion_rank {
-                    moves.push(Move::encode(start_square as u8, end_square as u8, Move::QUEEN_PROMOTION));            
-                    moves.push(Move::encode(start_square as u8, end_square as u8, Move::KNIGHT_PROMOTION));            
-                    moves.push(Move::encode(start_square as u8, end_square as u8, Move::ROOK_PROMOTION));            
-                    moves.push(Move::encode(start_square as u8, end_square as u8, Move::BISHOP_PROMOTION));            
-
-                }else {
-                    moves.push(Move::encode(start_square as u8, end_square as u8, 0));            
-                }
-            }
-
-            *push_bitboard &= *push_bitboard - 1;
-        }
-    }
-
-    fn get_double_push_moves(&self, moves: &mut Vec<Move>, push_bitboard: &mut u64, push_direction: i32, pins: &Vec<u8>) {
-        while *push_bitboard != 0 {
-            let end_square = push_bitboard.trailing_zeros() as i32; 
-            let start_square = end_square + (2*8*push_direction); 
-            let valid_position = *push_bitboard & (!*push_bitboard + 1); 
-            let legal_position = Self::get_legal_bitboard(self, &(start_square as u8), pins, &valid_position);
-            
-            if legal_position != 0 {
-                moves.push(Move::encode(start_square as u8, end_square as u8, Move::DOUBLE_PAWN_PUSH));            
-            }
-
-            *push_bitboard &= *push_bitboard - 1;
-        }
-    }
-
-    fn get_capture_moves(&mut self, moves: &mut Vec<Move>, capture_bitboard: &mut u64, capture_mask: i32, push_direction: i32, pins: &Vec<u8>) {
-        let promotion_rank = match push_direction {
-            1 => 0x00000000000000FF, // black, so promotion is 1st rank
-            _ => 0xFF00000000000000, // -1 is white so promotion is 8th rank
-        };
-
-        while *capture_bitboard != 0 {
-            let end_square = capture_bitboard.trailing_zeros() as i32;
-            let start_square = end_square + (capture_mask*push_direction);
-            let curr_rank: u64 = Bitboards::rank_mask_to_end(end_square as u8);
-            let valid_position = *capture_bitboard & (!*capture_bitboard + 1); 
-            let legal_position = Self::get_legal_bitboard(self, &(start_square as u8), pins, &valid_position);
-            //promotion with capture
-            if legal_position != 0 {
-                //promotion
-                if curr_rank == promotion_rank {
-                    moves.push(Move::encode(start_square as u8, end_square as u8, Move::QUEEN_PROMO_CAPTURE));            
-                    moves.push(Move::encode(start_square as u8, end_square as u8, Move::KNIGHT_PROMO_CAPTURE));            
-                    moves.push(Move::encode(start_square as u8, end_square as u8, Move::ROOK_PROMO_CAPTURE));            
-                    moves.push(Move::encode(start_square as u8, end_square as u8, Move::BISHOP_PROMO_CAPTURE));            
-
-                }else {
-                    moves.push(Move::encode(start_square as u8, end_square as u8, Move::CAPTURE));            
-                }
-            }
-            *capture_bitboard &= *capture_bitboard - 1;
-        }
-    }
-
-    fn get_en_passant_moves(&mut self, moves: &mut Vec<Move>, en_passant_capture: Square, capture_bitboard: &mut u64, capture_mask: i32, push_direction: i32, check_bitboard: u64, pins: &Vec<u8>) {
-        
-        let en_passant_position = 1 << en_passant_capture as u8;
-        
-        if en_passant_position & *capture_bitboard != 0 {
-            let start_square = en_passant_capture as i32 + (capture_mask*push_direction);
-            let king_position;
-            let ally_pawn_position;
-            let enemy_pawn_position;
-            let check_bitboard = match self.turn {
-                Turn::White=>{
-                    king_position = self.bitboards.white_king;
-                    ally_pawn_position =  1 << start_square;
-                    enemy_pawn_position = en_passant_position >> 8;
-                    check_bitboard << 8
-                }
-                Turn::Black=>{
-                    king_position = self.bitboards.black_king;
-                    ally_pawn_position =  1 << start_square;
-                    enemy_pawn_position = en_passant_position << 8;
-                    check_bitboard >> 8
-                }
-            };
-
-            let valid_position = en_passant_position & (!en_passant_position + 1); 
-            let legal_position = check_bitboard & Self::get_legal_bitboard(self, &(start_square as u8), pins, &valid_position);
-
-            if legal_position != 0 {
-                if self.get_en_passant_check(&king_position, &ally_pawn_position, &enemy_pawn_position , en_passant_position) == false{
-                    let en_passant_move = Move::encode(start_square as u8, en_passant_capture as u8, Move::EP_CAPTURE); 
-                    moves.push(en_passant_move);
-                }
-            }
-        }
-    }
-
-    pub fn knight_moves(&self, pins: &Vec<u8>, check_bitboard: u64) -> Vec<Move> {
-        let mut moves = Vec::new();
-
-        let ally_bitboard = self.bitboards.get_ally_pieces(self.turn);
-        let piece_bitboard = match self.turn {
-            Turn::White => self.bitboards.white_knights,
-            Turn::Black => self.bitboards.black_knights,
-        };
-
-        let all_piece_positions = Self::get_piece_positions_from(&piece_bitboard);
-        for piece_position in all_piece_positions {
-            Self::get_knight_moves(self, &mut moves, piece_position, ally_bitboard ,pins, check_bitboard);
-        }
-        moves 
+    pub fn is_in_check(&self) -> bool {
+        self.checkers != 0
     }
 
-    fn get_knight_moves(&self, moves: &mut Vec<Move>, piece_position: u64, ally_bitboard: u64, pins: &Vec<u8>, check_bitboard: u64) {
-        let mut valid_bitboard = self.get_knight_attacked_squares(piece_position);
-        valid_bitboard &= !ally_bitboard;
-        let start_square = piece_position.trailing_zeros() as u8;
-        let mut legal_bitboard = Self::get_legal_bitboard(self, &start_square, pins, &valid_bitboard) & check_bitboard;
-        Self::construct_moves_squares(self, moves, start_square, &mut legal_bitboard); 
-    }
-    
-    fn get_knight_attacked_squares(&self, piece_position: u64) -> u64 {
+    pub fn get_knight_attacked_squares(&self, piece_position: u64) -> u64 {
         let not_ab_file = 0xFCFCFCFCFCFCFCFC;
         let not_a_file = 0xfefefefefefefefe;
         let not_gh_file = 0x3F3F3F3F3F3F3F3F;
@@ -1421,179 +982,6 @@ impl Board {
         valid_bitboard
     }
     
-    pub fn bishop_moves(&self, pins: &Vec<u8>, check_bitboard: u64) -> Vec<Move> {
-        let mut moves= Vec::new();
-    
-        let enemy_bitboard= self.bitboards.get_enemy_pieces(self.turn);
-        let ally_bitboard = self.bitboards.get_ally_pieces(self.turn);
-
-        let piece_bitboard   = match self.turn {
-            Turn::White => self.bitboards.white_bishops,
-            Turn::Black=>self.bitboards.black_bishops
-        };
-        
-        let all_piece_positions = Self::get_piece_positions_from(&piece_bitboard);
-        for piece_position in all_piece_positions {
-            Self::get_bishop_moves(&self, &mut moves, piece_position, enemy_bitboard, ally_bitboard, pins, check_bitboard);
-        }
-        
-        return moves;
-    }
-
-    fn get_bishop_moves(&self, moves: &mut Vec<Move>, piece_position: u64, enemy_bitboard: u64, ally_bitboard: u64 , pins: &Vec<u8>, check_bitboard: u64) {
-        let start_square = piece_position.trailing_zeros() as u8;
-        let bishop_mask = Bitboards::bishop_mask_ex(start_square);
-        let all_pieces = enemy_bitboard | ally_bitboard;
-
-        let blocker = all_pieces & bishop_mask; 
-        let key = (blocker.wrapping_mul(Magic::BISHOP_MAGICS[start_square as usize])) >> Magic::BISHOP_SHIFTS[start_square as usize];
-
-        let mut valid_bitboard = self.bishop_attacks[start_square as usize][key as usize];
-        valid_bitboard &= !ally_bitboard;
-        let mut legal_bitboard = Self::get_legal_bitboard(self, &start_square, pins, &valid_bitboard) & check_bitboard;
-
-        Self::construct_moves_squares(&self, moves, start_square, &mut legal_bitboard); 
-    }
-    
-    fn get_bishop_attacked_squares(&self, piece_bitboard: &u64) -> u64 {
-        let mut moves_bitboard = 0;
-        let all_pieces = !self.bitboards.get_empty_squares();
-        let king_bitboard = match self.turn {
-            Turn::White => self.bitboards.white_king,
-            Turn::Black => self.bitboards.black_king
-        };
-
-        let all_piece_positions = Self::get_piece_positions_from(&piece_bitboard);
-        for piece_position in all_piece_positions {
-            let start_square = piece_position.trailing_zeros() as u8;
-            let bishop_mask = Bitboards::bishop_mask_ex(start_square);
-
-            let blocker = all_pieces & bishop_mask & !king_bitboard; 
-            let key = (blocker.wrapping_mul(Magic::BISHOP_MAGICS[start_square as usize])) >> Magic::BISHOP_SHIFTS[start_square as usize];
-
-            moves_bitboard |= self.bishop_attacks[start_square as usize][key as usize];
-        }
-        
-        moves_bitboard       
-    }
-    
-    pub fn rook_moves(&self, pins: &Vec<u8>, check_bitboard: u64) -> Vec<Move> {
-        let mut moves = Vec::new();
-    
-        let enemy_bitboard= self.bitboards.get_enemy_pieces(self.turn);
-        let ally_bitboard = self.bitboards.get_ally_pieces(self.turn);
-
-        let piece_bitboard   = match self.turn {
-            Turn::White => self.bitboards.white_rooks,
-            Turn::Black => self.bitboards.black_rooks
-        };
-
-        let all_piece_positions = Self::get_piece_positions_from(&piece_bitboard);
-        for piece_position in all_piece_positions {
-            Self::get_rook_moves(&self, &mut moves, piece_position, enemy_bitboard, ally_bitboard, pins, check_bitboard);
-        }
-        return  moves;
-    }
-
-    fn get_rook_moves(&self, moves: &mut Vec<Move>, piece_position: u64 ,enemy_bitboard: u64, ally_bitboard: u64, pins: &Vec<u8>, check_bitboard: u64) {
-        let start_square = piece_position.trailing_zeros() as u8;
-        let rook_mask = Bitboards::rook_mask_ex(start_square);
-        let all_pieces = enemy_bitboard | ally_bitboard;
-
-        let blocker = all_pieces & rook_mask; 
-        let key = (blocker.wrapping_mul(Magic::ROOK_MAGICS[start_square as usize])) >> Magic::ROOK_SHIFTS[start_square as usize];
-        let mut valid_bitboard = self.rook_attacks[start_square as usize][key as usize];
-        valid_bitboard &= !ally_bitboard;
-
-        let mut legal_bitboard = Self::get_legal_bitboard(self, &start_square, pins, &valid_bitboard) & check_bitboard;
-        Self::construct_moves_squares(self, moves, start_square, &mut legal_bitboard); 
-    }
-
-    fn get_rook_attacked_squares(&self, piece_bitboard: &u64) -> u64 {
-        let mut moves_bitboard = 0;
-        let all_pieces = !self.bitboards.get_empty_squares();
-        let king_bitboard = match self.turn {
-            Turn::White => self.bitboards.white_king,
-            Turn::Black => self.bitboards.black_king
-        };
-
-        let all_piece_positions = Self::get_piece_positions_from(&piece_bitboard);
-        for piece_position in all_piece_positions {
-            let start_square = piece_position.trailing_zeros() as u8;
-            let rook_mask = Bitboards::rook_mask_ex(start_square);
-
-            let blocker = all_pieces & rook_mask & !king_bitboard; 
-            let key = (blocker.wrapping_mul(Magic::ROOK_MAGICS[start_square as usize])) >> Magic::ROOK_SHIFTS[start_square as usize];
-
-            moves_bitboard |= self.rook_attacks[start_square as usize][key as usize];
-            
-        }
-        
-        moves_bitboard       
-    }
-
-    pub fn queen_moves(&self, pins: &Vec<u8>, check_bitboard: u64) -> Vec<Move> {
-        let mut moves = Vec::new();
-    
-        let enemy_bitboard= self.bitboards.get_enemy_pieces(self.turn);
-        let ally_bitboard = self.bitboards.get_ally_pieces(self.turn);
-
-        let piece_bitboard   = match self.turn {
-            Turn::White => self.bitboards.white_queens,
-            Turn::Black => self.bitboards.black_queens
-        };
-
-        // The queen moves is Combination of bishop and rook moves
-        let all_piece_positions = Self::get_piece_positions_from(&piece_bitboard);
-        for piece_position in all_piece_positions {
-            Self::get_rook_moves(&self, &mut moves, piece_position, enemy_bitboard, ally_bitboard, pins, check_bitboard);
-            Self::get_bishop_moves(&self, &mut moves, piece_position, enemy_bitboard, ally_bitboard, pins, check_bitboard);
-        }
-
-        return moves;
-    }
-    
-    fn get_queen_attacked_squares(&self, piece_bitboard: &u64) -> u64 {
-        self.get_bishop_attacked_squares(piece_bitboard) | self.get_rook_attacked_squares(piece_bitboard)
-    }
-
-    fn is_pined_square(sqaure : &u8 , pins :&Vec<u8>)-> bool{
-        for pinned_sqaure in pins {
-            if *sqaure == *pinned_sqaure {
-                return true;
-            }
-        }
-        return false;
-    }
-    pub fn get_direction_mask_ex_using (first_square : &u8 , second_sqaure : &u8)->u64{
-        let res = (*first_square as i16 - *second_sqaure as i16).abs();
-        if res < 7{
-            return Bitboards::rank_mask_to_end_ex(*first_square)
-        }
-        else if res % 8 == 0{
-            return Bitboards::file_mask_to_end_ex(*first_square)
-        }
-        else if res % 9 == 0{
-            return Bitboards::diagonal_mask_ex(*first_square)
-        }
-        else if res % 7 == 0 { 
-            return Bitboards::anti_diagonal_mask_ex(*first_square)
-        }
-        panic!("Impossible")
-    }
-    fn get_legal_bitboard(&self, start_square: &u8, pins: &Vec<u8>, valid_bitboard: &u64) -> u64 {
-        let mut legal_bitboard = *valid_bitboard;
-        let king_square = match self.turn {
-            Turn::White=> self.bitboards.white_king.trailing_zeros() as u8,
-            Turn::Black=> self.bitboards.black_king.trailing_zeros() as u8
-        };
-        if Self::is_pined_square(&start_square, pins) {
-            let direction_mask = Self::get_direction_mask_ex_using(&start_square, &king_square);
-            legal_bitboard &= direction_mask;
-        }
-
-        return legal_bitboard;
-    }
     // get the bit board of valid positions that the piece can move to (in specific direction)
     // the move_fn is a function that determines movement direction 
     pub fn get_sliding_bitboard(current_position: u64, occupied_bitboard: u64, enemy_bitboard: u64, move_fn: fn(u64) -> u64) -> u64 {
@@ -1611,69 +999,7 @@ impl Board {
         bitboard
     }
     
-    pub fn king_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::new();
-        let ally_bitboard = self.bitboards.get_ally_pieces(self.turn);
-
-        let piece_bitboard = match self.turn { 
-            Turn::White => self.bitboards.white_king,
-            Turn::Black => self.bitboards.black_king,
-        };
-
-        Self::get_king_moves(self, &mut moves, piece_bitboard, ally_bitboard);
-        Self::get_castling_moves(self ,&mut moves ,&piece_bitboard);    
-        moves
-    }
-
-    fn get_king_moves(&self, moves: &mut Vec<Move>, piece_position: u64, ally_bitboard: u64){
-        let mut king_bitboard= piece_position;
-        
-        let mut valid_bitboard = Bitboards::move_east(king_bitboard) | Bitboards::move_west(king_bitboard);
-        king_bitboard |= valid_bitboard;
-        valid_bitboard |= Bitboards::move_north(king_bitboard) | Bitboards::move_south(king_bitboard);
-
-        valid_bitboard &= !ally_bitboard;
-        valid_bitboard &= !self.get_attacked_squares();
-
-        let start_square = piece_position.trailing_zeros() as u8;
-        Self::construct_moves_squares(self, moves, start_square, &mut valid_bitboard);
-    }
-
-    pub fn get_castling_moves(&self, moves : &mut Vec<Move> , king_position: &u64) {
-        let occupied_bitboard = self.bitboards.get_ally_pieces(self.turn) | self.bitboards.get_enemy_pieces(self.turn);
-
-        if self.castling_rights.check_king_side(self.turn){ 
-            Self::get_king_side_move(self, moves, &king_position, &occupied_bitboard);
-        }
-
-        if self.castling_rights.check_queen_side(self.turn){
-            Self::get_queen_side_move(self, moves, &king_position, &occupied_bitboard);
-        }
-    }
-
-    fn get_king_side_move(&self, moves: &mut Vec<Move>, king_position: &u64, occupied_bitboard: &u64) {
-        let square_between = king_position <<1 | king_position << 2;
-        let castling_squares = (king_position | square_between) & self.get_attacked_squares();
-        let can_castle = square_between & occupied_bitboard == 0;
-
-        if can_castle && castling_squares == 0{
-            let start_square = king_position.trailing_zeros() as u8;
-            moves.push(Move::encode(start_square, start_square + 2, Move::KING_CASTLE));
-        }
-    }
-
-    fn get_queen_side_move(&self, moves: &mut Vec<Move>, king_position: &u64, occupied_bitboard: &u64) {
-        let square_between = king_position >> 1 | king_position >> 2 | king_position >> 3;
-        let castling_squares = (king_position >> 1 | king_position >> 2 | king_position) & self.get_attacked_squares();
-        let can_castle = square_between & occupied_bitboard == 0;
-
-        if can_castle && castling_squares == 0{
-            let start_square = king_position.trailing_zeros() as u8;
-            moves.push(Move::encode(start_square, start_square - 2, Move::QUEEN_CASTLE));
-        }
-    }
-    
-    fn get_king_attacked_squares(&self, piece_bitboard: u64) -> u64 {
+    pub fn get_king_attacked_squares(&self, piece_bitboard: u64) -> u64 {
         let mut king_bitboard= piece_bitboard;
         
         let mut valid_bitboard = Bitboards::move_east(king_bitboard) | Bitboards::move_west(king_bitboard);
@@ -1683,41 +1009,6 @@ impl Board {
         valid_bitboard
     }
 
-    // get the position for each piece from the piece bitboard
-    // eg : if the piece bitboard is 0000000000000000000000100000000000000000000100000000000000000001
-    //      so the positions will contain : 
-    //        -- 0000000000000000000000000000000000000000000000000000000000000001
-    //        -- 0000000000000000000000000000000000000000000100000000000000000000
-    //        -- 0000000000000000000000100000000000000000000000000000000000000000
-    fn get_piece_positions_from(piece_bitboard: &u64) -> Vec<u64> {
-        let mut bitboard  =  *piece_bitboard; 
-        let mut positions = Vec::new();
-
-        while bitboard != 0 {
-            let position = bitboard & (!bitboard + 1); 
-            positions.push(position);
-            bitboard &= bitboard - 1;
-        }
-
-        positions
-    }
-    
-    fn construct_moves_squares(&self, moves: &mut Vec<Move>, start_square: u8, valid_bitboard: &mut u64) {
-        let enemy_bitboard = self.bitboards.get_enemy_pieces(self.turn);
-
-        while *valid_bitboard != 0 {
-            let end_squares = valid_bitboard.trailing_zeros() as u8;
-            let end_bitboard = 1 << end_squares;
-            let flag = match end_bitboard & enemy_bitboard {
-                0 => 0,
-                _ => Move::CAPTURE,
-            };
-
-            moves.push(Move::encode(start_square, end_squares, flag));        
-            *valid_bitboard &= *valid_bitboard - 1;
-        }
-    }
-
     pub fn print_board(&mut self) {
         println!("\nWhite:♚ - Black:♔\n");
 
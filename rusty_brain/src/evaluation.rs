@@ -2,9 +2,33 @@ use std::process::id;
 use crate::piece::Piece;
 
 use crate::{bitboards::{self, Bitboards}, board::{Board, Turn}, square::{self, Rank, Square}};
+use crate::evaluator::Evaluator;
+
+// The hand crafted evaluation as a search evaluator
+pub struct HceEvaluator;
+
+impl Evaluator for HceEvaluator {
+    fn name(&self) -> &'static str {
+        "HCE"
+    }
+
+    fn new_instance(&self) -> Box<dyn Evaluator> {
+        Box::new(HceEvaluator)
+    }
+
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        // the hand crafted terms are in stockfish's internal units from white's point of view,
+        // an endgame pawn (206) is worth a hundred centipawns
+        let eval = board.evaluate() * 100 / 206;
+        match board.turn {
+            Turn::White => eval,
+            Turn::Black => -eval,
+        }
+    }
+}
 
 impl Board {
-    pub fn evaluate(&mut self) -> i32 {
+    pub fn evaluate(&self) -> i32 {
         let mg = self.middle_game_evaluation(true);
         mg
     }
@@ -711,12 +735,11 @@ impl Board {
     fn rook_on_queen_file(&self) -> i32 {
         let mut sum =0;
         let mut rook_bitboard = self.bitboards.white_rooks;
-        let queen = self.bitboards.white_queens.trailing_zeros() as u8;
-        let queen_file = Square::from(queen).file();
+        // a rook counts when any queen stands on its file, there might be none at all
+        let queens = self.bitboards.white_queens | self.bitboards.black_queens;
         while rook_bitboard != 0 {
             let rook = rook_bitboard.trailing_zeros() as u8;
-            let rook_file = Square::from(rook).file();
-            if queen_file == rook_file{
+            if Bitboards::file_mask_to_end(rook) & queens != 0 {
                 sum +=1
             }
             rook_bitboard &= rook_bitboard -1;
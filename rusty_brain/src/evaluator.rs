@@ -0,0 +1,102 @@
+use std::ops::AddAssign;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::board::Board;
+use crate::evaluation::HceEvaluator;
+use crate::kan_eval::{KanEvaluator, UNTRAINED};
+use crate::movement::Move;
+use crate::nnue::{NnueEvaluator, NNUE};
+
+// Common interface of the evaluation functions the search can use.
+// Scores are in centipawns from the side to move's point of view.
+//
+// Evaluators that keep incremental state (like the NNUE accumulators) follow
+// the search through the hooks: on_make is called with the position *before*
+// the move is made and on_undo after the move has been taken back.
+pub trait Evaluator: Send {
+    fn name(&self) -> &'static str;
+
+    fn evaluate(&mut self, board: &Board) -> i32;
+
+    // An evaluator of the same kind with its own state, for the helper threads of a search
+    fn new_instance(&self) -> Box<dyn Evaluator>;
+
+    // Rebuild any incremental state from scratch, called at the root of every search
+    fn reset(&mut self, _board: &Board) {}
+
+    fn on_make(&mut self, _board: &Board, _move_to_make: Move) {}
+
+    fn on_undo(&mut self, _board: &Board) {}
+
+    // Evaluations done since the last reset and the time they took, for evaluators that measure it
+    fn timing(&self) -> Option<EvalTiming> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EvalTiming {
+    pub evaluations: u64,
+    pub time: Duration,
+}
+
+impl EvalTiming {
+    pub fn per_evaluation(&self) -> Duration {
+        Duration::from_nanos((self.time.as_nanos() / u128::from(self.evaluations.max(1))) as u64)
+    }
+}
+
+impl AddAssign for EvalTiming {
+    fn add_assign(&mut self, other: EvalTiming) {
+        self.evaluations += other.evaluations;
+        self.time += other.time;
+    }
+}
+
+// The evaluation functions that can be picked through the EvalType uci option
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvalType {
+    Hce,
+    Nnue,
+    Kan,
+}
+
+impl EvalType {
+    pub const ALL: [EvalType; 3] = [EvalType::Hce, EvalType::Nnue, EvalType::Kan];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EvalType::Hce => "HCE",
+            EvalType::Nnue => "NNUE",
+            EvalType::Kan => "KAN",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|eval_type| eval_type.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn create(&self) -> Box<dyn Evaluator> {
+        match self {
+            EvalType::Hce => Box::new(HceEvaluator),
+            EvalType::Nnue => Box::new(NnueEvaluator::new(Arc::clone(&NNUE))),
+            EvalType::Kan => Box::new(KanEvaluator::new(Arc::clone(&UNTRAINED))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod evaluator {
+    use std::time::Duration;
+
+    use super::EvalTiming;
+
+    #[test]
+    fn per_evaluation_counts_past_u32() {
+        let timing = EvalTiming { evaluations: 1 << 32, time: Duration::from_secs(1 << 32) };
+        assert_eq!(timing.per_evaluation(), Duration::from_secs(1));
+
+        let timing = EvalTiming { evaluations: 0, time: Duration::from_nanos(700) };
+        assert_eq!(timing.per_evaluation(), Duration::from_nanos(700));
+    }
+}
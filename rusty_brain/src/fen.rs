@@ -0,0 +1,368 @@
+use std::fmt;
+use crate::bitboards::Bitboards;
+use crate::board::{Board, Turn};
+use crate::castling::CastlingRights;
+use crate::square::Square;
+
+// What is wrong with a FEN that Board::try_from_fen rejects
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenError {
+    MissingField(&'static str),
+    WrongRankCount(usize),
+    // rank number as written on the board, 8 for the first rank of the FEN
+    BadRank { rank: usize, reason: String },
+    KingCount { color: &'static str, count: u32 },
+    PawnOnBackRank,
+    InvalidSideToMove(String),
+    InvalidCastlingRights(String),
+    InvalidEnPassantSquare(String),
+    InvalidClock(String),
+    // the side that just moved left its own king in check
+    OpponentInCheck,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing the {} field", field),
+            FenError::WrongRankCount(count) => write!(f, "the piece placement has {} ranks instead of 8", count),
+            FenError::BadRank { rank, reason } => write!(f, "rank {}: {}", rank, reason),
+            FenError::KingCount { color, count } => write!(f, "{} has {} kings, expected exactly one", color, count),
+            FenError::PawnOnBackRank => write!(f, "pawns can't stand on the first or the last rank"),
+            FenError::InvalidSideToMove(side) => write!(f, "side to move \"{}\" isn't w or b", side),
+            FenError::InvalidCastlingRights(reason) => write!(f, "castling rights: {}", reason),
+            FenError::InvalidEnPassantSquare(reason) => write!(f, "en passant square: {}", reason),
+            FenError::InvalidClock(clock) => write!(f, "move counter \"{}\" isn't a number", clock),
+            FenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+fn piece_bitboard(bitboards: &mut Bitboards, c: char) -> Option<&mut u64> {
+    let bitboard = match c {
+        'P' => &mut bitboards.white_pawns,
+        'N' => &mut bitboards.white_knights,
+        'B' => &mut bitboards.white_bishops,
+        'R' => &mut bitboards.white_rooks,
+        'Q' => &mut bitboards.white_queens,
+        'K' => &mut bitboards.white_king,
+        'p' => &mut bitboards.black_pawns,
+        'n' => &mut bitboards.black_knights,
+        'b' => &mut bitboards.black_bishops,
+        'r' => &mut bitboards.black_rooks,
+        'q' => &mut bitboards.black_queens,
+        'k' => &mut bitboards.black_king,
+        _ => return None,
+    };
+    Some(bitboard)
+}
+
+fn parse_placement(placement: &str) -> Result<Bitboards, FenError> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::WrongRankCount(ranks.len()));
+    }
+
+    let mut bitboards = Bitboards::empty();
+    for (index, rank) in ranks.iter().enumerate() {
+        let rank_number = 8 - index;
+        let bad_rank = |reason: String| FenError::BadRank { rank: rank_number, reason };
+        let mut file = 0;
+        for c in rank.chars() {
+            if let Some(empty_squares) = c.to_digit(10).filter(|digit| (1..=8).contains(digit)) {
+                file += empty_squares as usize;
+                continue;
+            }
+            if file >= 8 {
+                return Err(bad_rank(format!("\"{}\" describes more than 8 squares", rank)));
+            }
+            let square = (rank_number - 1) * 8 + file;
+            let bitboard = piece_bitboard(&mut bitboards, c).ok_or_else(|| bad_rank(format!("'{}' isn't a piece", c)))?;
+            *bitboard |= 1 << square;
+            file += 1;
+        }
+        if file != 8 {
+            return Err(bad_rank(format!("\"{}\" describes {} squares instead of 8", rank, file)));
+        }
+    }
+
+    let back_ranks = 0xFF000000000000FF;
+    if (bitboards.white_pawns | bitboards.black_pawns) & back_ranks != 0 {
+        return Err(FenError::PawnOnBackRank);
+    }
+    for (color, king) in [("white", bitboards.white_king), ("black", bitboards.black_king)] {
+        if king.count_ones() != 1 {
+            return Err(FenError::KingCount { color, count: king.count_ones() });
+        }
+    }
+    Ok(bitboards)
+}
+
+fn parse_castling_rights(field: &str, bitboards: &Bitboards) -> Result<CastlingRights, FenError> {
+    let mut castling_rights = CastlingRights::empty();
+    if field == "-" {
+        return Ok(castling_rights);
+    }
+
+    for right in field.chars() {
+        // the king and the rook a right belongs to have to be on their starting squares
+        let (granted, king, rook, king_square, rook_square) = match right {
+            'K' => (&mut castling_rights.white_king_side, bitboards.white_king, bitboards.white_rooks, Square::E1, Square::H1),
+            'Q' => (&mut castling_rights.white_queen_side, bitboards.white_king, bitboards.white_rooks, Square::E1, Square::A1),
+            'k' => (&mut castling_rights.black_king_side, bitboards.black_king, bitboards.black_rooks, Square::E8, Square::H8),
+            'q' => (&mut castling_rights.black_queen_side, bitboards.black_king, bitboards.black_rooks, Square::E8, Square::A8),
+            _ => return Err(FenError::InvalidCastlingRights(format!("'{}' isn't one of KQkq", right))),
+        };
+        if *granted {
+            return Err(FenError::InvalidCastlingRights(format!("'{}' is given twice", right)));
+        }
+        if king & (1 << king_square as u8) == 0 || rook & (1 << rook_square as u8) == 0 {
+            return Err(FenError::InvalidCastlingRights(format!("'{}' without the king on {} and the rook on {}", right, king_square, rook_square)));
+        }
+        *granted = true;
+    }
+    Ok(castling_rights)
+}
+
+// The square has to be the one a pawn of the side not to move just skipped with a double push
+fn parse_en_passant_square(field: &str, bitboards: &Bitboards, turn: Turn) -> Result<Option<Square>, FenError> {
+    if field == "-" {
+        return Ok(None);
+    }
+    let square = Square::parse(field).ok_or_else(|| FenError::InvalidEnPassantSquare(format!("\"{}\" isn't a square", field)))?;
+
+    let index = square as u8;
+    let expected_rank = match turn {
+        Turn::White => 6,
+        Turn::Black => 3,
+    };
+    if index / 8 + 1 != expected_rank {
+        return Err(FenError::InvalidEnPassantSquare(format!("{} isn't on rank {}", field, expected_rank)));
+    }
+    let (pushed_pawn, start_square, enemy_pawns) = match turn {
+        Turn::White => (index - 8, index + 8, bitboards.black_pawns),
+        Turn::Black => (index + 8, index - 8, bitboards.white_pawns),
+    };
+    let empty = bitboards.get_empty_squares();
+    if empty & (1 << index) == 0 || empty & (1 << start_square) == 0 {
+        return Err(FenError::InvalidEnPassantSquare(format!("{} or the square the pawn came from is occupied", field)));
+    }
+    if enemy_pawns & (1 << pushed_pawn) == 0 {
+        return Err(FenError::InvalidEnPassantSquare(format!("no pawn on {} that could have been pushed past {}", Square::from(pushed_pawn), field)));
+    }
+    Ok(Some(square))
+}
+
+impl Board {
+    // Piece placement, side to move, castling rights and en passant square are required,
+    // the half move clock and the full move number default to 0 and 1 when they are left out
+    pub fn try_from_fen(fen: &str) -> Result<Board, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let field = |index: usize, name: &'static str| fields.get(index).copied().ok_or(FenError::MissingField(name));
+
+        let bitboards = parse_placement(field(0, "piece placement")?)?;
+        let turn = match field(1, "side to move")? {
+            "w" => Turn::White,
+            "b" => Turn::Black,
+            side => return Err(FenError::InvalidSideToMove(side.to_string())),
+        };
+        let castling_rights = parse_castling_rights(field(2, "castling rights")?, &bitboards)?;
+        let en_passant_square = parse_en_passant_square(field(3, "en passant square")?, &bitboards, turn)?;
+        let half_move_clock = match fields.get(4) {
+            Some(clock) => clock.parse().map_err(|_| FenError::InvalidClock(clock.to_string()))?,
+            None => 0,
+        };
+        let full_move_number = match fields.get(5) {
+            Some(number) => number.parse().map_err(|_| FenError::InvalidClock(number.to_string()))?,
+            None => 1,
+        };
+
+        let mut board = Board::empty();
+        board.bitboards = bitboards;
+        board.turn = turn;
+        board.castling_rights = castling_rights;
+        board.en_passant_square = en_passant_square;
+        board.half_move_clock = half_move_clock;
+        board.full_move_number = full_move_number;
+        board.update_position_state();
+
+        let enemy_king = board.bitboards.get_enemy_pieces(turn) & (board.bitboards.white_king | board.bitboards.black_king);
+        let occupied = !board.bitboards.get_empty_squares();
+        if board.attackers_to(enemy_king.trailing_zeros() as u8, occupied) & board.bitboards.get_ally_pieces(turn) != 0 {
+            return Err(FenError::OpponentInCheck);
+        }
+        Ok(board)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty_squares = 0;
+            for file in 0..8 {
+                match self.piece_at(rank * 8 + file) {
+                    Some((piece, color)) => {
+                        if empty_squares > 0 {
+                            fen.push_str(&empty_squares.to_string());
+                            empty_squares = 0;
+                        }
+                        fen.push(match color {
+                            Turn::White => piece.to_char(),
+                            Turn::Black => piece.to_char().to_ascii_lowercase(),
+                        });
+                    }
+                    None => empty_squares += 1,
+                }
+            }
+            if empty_squares > 0 {
+                fen.push_str(&empty_squares.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.turn {
+            Turn::White => " w ",
+            Turn::Black => " b ",
+        });
+
+        let rights = [
+            (self.castling_rights.white_king_side, 'K'),
+            (self.castling_rights.white_queen_side, 'Q'),
+            (self.castling_rights.black_king_side, 'k'),
+            (self.castling_rights.black_queen_side, 'q'),
+        ];
+        let castling: String = rights.iter().filter(|(granted, _)| *granted).map(|(_, c)| c).collect();
+        fen.push_str(if castling.is_empty() { "-" } else { &castling });
+
+        match self.en_passant_square {
+            Some(square) => fen.push_str(&format!(" {}", square.to_string().to_lowercase())),
+            None => fen.push_str(" -"),
+        }
+        fen.push_str(&format!(" {} {}", self.half_move_clock, self.full_move_number));
+        fen
+    }
+}
+
+#[cfg(test)]
+mod fen {
+    use crate::board::Board;
+
+    use super::FenError;
+
+    const PERFT_FENS: [&str; 7] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 0",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ];
+
+    // every position on the way is written out, read back and has to come out the same
+    fn round_trip(board: &mut Board, depth: i32) {
+        let fen = board.to_fen();
+        let parsed = Board::try_from_fen(&fen).unwrap_or_else(|error| panic!("{}: {}", fen, error));
+        assert_eq!(parsed.to_fen(), fen);
+        assert_eq!(parsed.zobrist_key, board.zobrist_key, "{}", fen);
+        assert_eq!(parsed.checkers, board.checkers, "{}", fen);
+
+        if depth == 0 {
+            return;
+        }
+        for current_move in board.generate_legal_moves() {
+            board.make_move(current_move);
+            round_trip(board, depth - 1);
+            board.undo_move();
+        }
+    }
+
+    #[test]
+    fn perft_positions_round_trip() {
+        for fen in PERFT_FENS {
+            let mut board = Board::try_from_fen(fen).unwrap();
+            assert_eq!(board.to_fen(), fen);
+            round_trip(&mut board, 2);
+            assert_eq!(board.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn captured_rooks_take_their_rights_along() {
+        // the last rook of the side with the right is taken on its corner
+        let cases = [
+            ("4k2r/8/8/8/8/8/1B6/4K3 w k - 0 1", "b2h8", "4k2B/8/8/8/8/8/8/4K3 b - - 0 1"),
+            ("r3k3/8/8/8/8/8/6B1/4K3 w q - 0 1", "g2a8", "B3k3/8/8/8/8/8/8/4K3 b - - 0 1"),
+        ];
+        for (fen, capture, after) in cases {
+            let mut board = Board::try_from_fen(fen).unwrap();
+            round_trip(&mut board, 2);
+
+            let current_move = board.generate_legal_moves().into_iter().find(|m| m.to_string() == capture).unwrap();
+            board.make_move(current_move);
+            assert_eq!(board.to_fen(), after);
+        }
+    }
+
+    #[test]
+    fn counters_are_optional_and_counted() {
+        let mut board = Board::try_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -").unwrap();
+        assert_eq!(board.to_fen(), Board::new().to_fen());
+
+        for uci_move in ["g1f3", "g8f6", "e2e4"] {
+            let current_move = board.generate_legal_moves().into_iter().find(|m| m.to_string() == uci_move).unwrap();
+            board.make_move(current_move);
+        }
+        assert_eq!(board.to_fen(), "rnbqkb1r/pppppppp/5n2/8/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq e3 0 2");
+    }
+
+    #[test]
+    fn malformed_fens_are_rejected() {
+        let cases = [
+            ("", FenError::MissingField("piece placement")),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", FenError::WrongRankCount(7)),
+            ("4k3/8/8/8/8/8/8/4K3", FenError::MissingField("side to move")),
+            ("4k3/8/8/8/8/8/8/4K3 x - - 0 1", FenError::InvalidSideToMove("x".to_string())),
+            ("4k3/8/8/8/8/8/8/4KK2 w - - 0 1", FenError::KingCount { color: "white", count: 2 }),
+            ("8/8/8/8/8/8/8/4K3 w - - 0 1", FenError::KingCount { color: "black", count: 0 }),
+            ("4k2P/8/8/8/8/8/8/4K3 w - - 0 1", FenError::PawnOnBackRank),
+            ("4k3/8/8/8/8/8/8/4K3 w - - x 1", FenError::InvalidClock("x".to_string())),
+            ("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1", FenError::OpponentInCheck),
+        ];
+        for (fen, error) in cases {
+            assert_eq!(Board::try_from_fen(fen).err(), Some(error), "{}", fen);
+        }
+
+        let rank_errors = [
+            "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4X3 w - - 0 1",
+        ];
+        for fen in rank_errors {
+            assert!(matches!(Board::try_from_fen(fen), Err(FenError::BadRank { rank: 1, .. })), "{}", fen);
+        }
+
+        let castling_errors = [
+            "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+            "4k3/8/8/8/8/8/8/4K2R w KK - 0 1",
+            "4k3/8/8/8/8/8/8/4K2R w X - 0 1",
+        ];
+        for fen in castling_errors {
+            assert!(matches!(Board::try_from_fen(fen), Err(FenError::InvalidCastlingRights(_))), "{}", fen);
+        }
+
+        let en_passant_errors = [
+            "4k3/8/8/8/4Pp2/8/8/4K3 b - e9 0 1",
+            "4k3/8/8/8/4Pp2/8/8/4K3 w - e3 0 1",
+            "4k3/8/8/8/4Pp2/8/8/4K3 b - d3 0 1",
+            "4k3/8/8/8/4Pp2/4N3/8/4K3 b - e3 0 1",
+        ];
+        for fen in en_passant_errors {
+            assert!(matches!(Board::try_from_fen(fen), Err(FenError::InvalidEnPassantSquare(_))), "{}", fen);
+        }
+        assert!(Board::try_from_fen("4k3/8/8/8/4Pp2/8/8/4K3 b - e3 0 1").is_ok());
+    }
+}
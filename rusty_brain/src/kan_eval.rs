@@ -0,0 +1,505 @@
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
+use crate::alphabeta::MATE_IN_MAX_PLY;
+use crate::board::Board;
+use crate::evaluator::{EvalTiming, Evaluator};
+use crate::nnue::{Architecture, NnueError};
+
+/// The same piece-square inputs as the NNUE, seen from the side to move.
+const INPUT_SIZE: usize = 768;
+
+/// Files start with a header giving the shape of the network, the weights follow as
+/// little endian `f32`s, layer after layer, each as its `burn-efficient-kan` parameters:
+///
+/// * `grid`, `inputs x (grid_size + 2 * spline_order + 1)` knots
+/// * `base_weight`, `outputs x inputs`
+/// * `spline_weight`, `outputs x inputs x (grid_size + spline_order)`
+/// * `spline_scaler`, `outputs x inputs`, only with the standalone spline scale flag
+///
+/// `rough_guard::export::write_rbkn` writes them from KANs trained with `burn-efficient-kan`.
+const HEADER_MAGIC: &[u8; 4] = b"RBKN";
+const HEADER_VERSION: u32 = 1;
+/// Magic, version, number of layers, grid size, spline order, flags and output scale,
+/// followed by the width of every layer boundary.
+const HEADER_FIXED_SIZE: usize = 28;
+const FLAG_SPLINE_SCALER: u32 = 1;
+const MAX_LAYERS: usize = 16;
+
+/// Shape of the network used until a trained one is loaded, with efficient-kan's default grid.
+const DEFAULT_HIDDEN_SIZE: usize = 32;
+const DEFAULT_GRID_SIZE: usize = 5;
+const DEFAULT_SPLINE_ORDER: usize = 3;
+/// Centipawns of an output of 1, the scale of the sigmoid the evaluations are trained against.
+const DEFAULT_SCALE: f32 = 400.0;
+
+/// A network with every weight at zero, it evaluates every position as equal.
+/// The KAN evaluators use it until the `KanFile` option loads a trained one.
+pub static UNTRAINED: LazyLock<Arc<KanNetwork>> = LazyLock::new(|| {
+    Arc::new(KanNetwork::zeroed(&[INPUT_SIZE, DEFAULT_HIDDEN_SIZE, 1], DEFAULT_GRID_SIZE, DEFAULT_SPLINE_ORDER))
+});
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// SiLU, the base activation of `efficient-kan`.
+fn silu(x: f32) -> f32 {
+    x / (1.0 + (-x).exp())
+}
+
+/// One layer of a Kolmogorov-Arnold network: every input goes through its own
+/// learned function towards every output, a SiLU base plus a B-spline.
+struct KanLayer {
+    inputs: usize,
+    outputs: usize,
+    /// Knots of the spline of every input, `inputs x (grid_size + 2 * spline_order + 1)`.
+    grid: Vec<f32>,
+    /// `outputs x inputs`
+    base_weight: Vec<f32>,
+    /// `outputs x inputs x (grid_size + spline_order)`, already multiplied
+    /// by the standalone spline scaler if the network has one.
+    spline_weight: Vec<f32>,
+}
+
+/// A Kolmogorov-Arnold network evaluating positions in `f32`.
+pub struct KanNetwork {
+    layers: Vec<KanLayer>,
+    grid_size: usize,
+    spline_order: usize,
+    /// Centipawns of an output of 1.
+    scale: f32,
+    /// The first layer only ever sees inputs of 0 and 1, so its outputs are sums of the
+    /// responses of its inputs to those two values. This is the sum for all inputs at 0...
+    first_layer_bias: Vec<f32>,
+    /// ...and what setting each input to 1 adds to it, `inputs x outputs`.
+    first_layer_deltas: Vec<f32>,
+}
+
+impl KanNetwork {
+    /// A network of the given layer widths with efficient-kan's initial grid,
+    /// evenly spaced knots over `[-1, 1]`, and all weights at zero.
+    pub fn zeroed(widths: &[usize], grid_size: usize, spline_order: usize) -> KanNetwork {
+        let knots = grid_size + 2 * spline_order + 1;
+        let step = 2.0 / grid_size as f32;
+        let layers = widths
+            .windows(2)
+            .map(|pair| {
+                let (inputs, outputs) = (pair[0], pair[1]);
+                let grid = (0..inputs)
+                    .flat_map(|_| (0..knots).map(|knot| (knot as f32 - spline_order as f32) * step - 1.0))
+                    .collect();
+                KanLayer {
+                    inputs,
+                    outputs,
+                    grid,
+                    base_weight: vec![0.0; outputs * inputs],
+                    spline_weight: vec![0.0; outputs * inputs * (grid_size + spline_order)],
+                }
+            })
+            .collect();
+        KanNetwork::new(layers, grid_size, spline_order, DEFAULT_SCALE)
+    }
+
+    fn new(layers: Vec<KanLayer>, grid_size: usize, spline_order: usize, scale: f32) -> KanNetwork {
+        let mut network = KanNetwork {
+            layers,
+            grid_size,
+            spline_order,
+            scale,
+            first_layer_bias: Vec::new(),
+            first_layer_deltas: Vec::new(),
+        };
+
+        let first = &network.layers[0];
+        let mut responses = [vec![0.0; first.outputs], vec![0.0; first.outputs]];
+        network.first_layer_bias = vec![0.0; first.outputs];
+        network.first_layer_deltas = vec![0.0; first.inputs * first.outputs];
+        let mut bases = vec![0.0; grid_size + 2 * spline_order];
+        for input in 0..first.inputs {
+            for (value, response) in responses.iter_mut().enumerate() {
+                response.fill(0.0);
+                network.input_response(0, input, value as f32, &mut bases, response);
+            }
+            let deltas = &mut network.first_layer_deltas[input * first.outputs..(input + 1) * first.outputs];
+            for (output, (at_zero, at_one)) in responses[0].iter().zip(&responses[1]).enumerate() {
+                network.first_layer_bias[output] += at_zero;
+                deltas[output] = at_one - at_zero;
+            }
+        }
+        network
+    }
+
+    /// Reads a network from a file, see `KanNetwork::from_bytes`.
+    pub fn load(path: impl AsRef<Path>) -> Result<KanNetwork, NnueError> {
+        KanNetwork::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Builds a network from its header and `f32` weights. The first layer has to take the
+    /// 768 inputs of the position and the last one has to give a single output.
+    pub fn from_bytes(bytes: &[u8]) -> Result<KanNetwork, NnueError> {
+        let invalid = |reason: String| Err(NnueError::InvalidArchitecture(reason));
+        if bytes.len() < HEADER_FIXED_SIZE || !bytes.starts_with(HEADER_MAGIC) {
+            return invalid("missing KAN header".to_string());
+        }
+        let version = read_u32(bytes, 4);
+        if version != HEADER_VERSION {
+            return Err(NnueError::UnsupportedVersion(version));
+        }
+        let layer_count = read_u32(bytes, 8) as usize;
+        let grid_size = read_u32(bytes, 12) as usize;
+        let spline_order = read_u32(bytes, 16) as usize;
+        let flags = read_u32(bytes, 20);
+        let scale = f32::from_le_bytes(bytes[24..28].try_into().unwrap());
+        if !(1..=MAX_LAYERS).contains(&layer_count) {
+            return invalid(format!("{} layers", layer_count));
+        }
+        if grid_size == 0 || grid_size > 1024 || spline_order > 16 || !scale.is_finite() {
+            return invalid(format!("grid size {}, spline order {} and scale {}", grid_size, spline_order, scale));
+        }
+
+        let header_size = HEADER_FIXED_SIZE + 4 * (layer_count + 1);
+        if bytes.len() < header_size {
+            return Err(NnueError::WrongSize { expected: header_size, found: bytes.len() });
+        }
+        let widths: Vec<usize> = (0..=layer_count).map(|i| read_u32(bytes, HEADER_FIXED_SIZE + 4 * i) as usize).collect();
+        if widths[0] != INPUT_SIZE || widths[layer_count] != 1 || widths.iter().any(|&width| width == 0 || width > 1 << 16) {
+            return invalid(format!("layer widths {:?}, expected {} inputs and 1 output", widths, INPUT_SIZE));
+        }
+
+        let knots = grid_size + 2 * spline_order + 1;
+        let coefficients = grid_size + spline_order;
+        let scaled = flags & FLAG_SPLINE_SCALER != 0;
+        let expected: usize = widths
+            .windows(2)
+            .map(|pair| pair[0] * knots + pair[1] * pair[0] * (1 + coefficients + scaled as usize))
+            .sum::<usize>()
+            * 4;
+        let weights = &bytes[header_size..];
+        if weights.len() != expected {
+            return Err(NnueError::WrongSize { expected, found: weights.len() });
+        }
+
+        let mut values = weights.chunks_exact(4).map(|value| f32::from_le_bytes(value.try_into().unwrap()));
+        let mut take = |count: usize| values.by_ref().take(count).collect::<Vec<f32>>();
+        let mut layers = Vec::with_capacity(layer_count);
+        for pair in widths.windows(2) {
+            let (inputs, outputs) = (pair[0], pair[1]);
+            let grid = take(inputs * knots);
+            let base_weight = take(outputs * inputs);
+            let mut spline_weight = take(outputs * inputs * coefficients);
+            if scaled {
+                for (weights, scaler) in spline_weight.chunks_exact_mut(coefficients).zip(take(outputs * inputs)) {
+                    weights.iter_mut().for_each(|weight| *weight *= scaler);
+                }
+            }
+
+            // the recursion of the splines divides by the distance between knots
+            if grid.chunks_exact(knots).any(|knots| knots.windows(2).any(|pair| pair[0] >= pair[1])) {
+                return invalid("grid knots that don't increase".to_string());
+            }
+            layers.push(KanLayer { inputs, outputs, grid, base_weight, spline_weight });
+        }
+        Ok(KanNetwork::new(layers, grid_size, spline_order, scale))
+    }
+
+    /// The network in the format `KanNetwork::from_bytes` reads, with the
+    /// spline scaler folded into the spline weights.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = HEADER_MAGIC.to_vec();
+        let fields = [HEADER_VERSION, self.layers.len() as u32, self.grid_size as u32, self.spline_order as u32, 0];
+        for field in fields {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend(self.scale.to_le_bytes());
+        bytes.extend((self.layers[0].inputs as u32).to_le_bytes());
+        for layer in &self.layers {
+            bytes.extend((layer.outputs as u32).to_le_bytes());
+        }
+        for layer in &self.layers {
+            for value in layer.grid.iter().chain(&layer.base_weight).chain(&layer.spline_weight) {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Values of the B-spline basis functions of an input at `x`, with the
+    /// Cox-de Boor recursion as `efficient-kan` runs it. Outside of the grid they are all 0.
+    /// `bases` needs room for the `grid_size + 2 * spline_order` order 0 bases, the
+    /// first `grid_size + spline_order` hold the result.
+    fn b_splines(&self, layer: usize, input: usize, x: f32, bases: &mut [f32]) {
+        let knots = self.grid_size + 2 * self.spline_order + 1;
+        let grid = &self.layers[layer].grid[input * knots..(input + 1) * knots];
+        for (basis, pair) in bases.iter_mut().zip(grid.windows(2)) {
+            *basis = if x >= pair[0] && x < pair[1] { 1.0 } else { 0.0 };
+        }
+        for order in 1..=self.spline_order {
+            for j in 0..knots - 1 - order {
+                let left = (x - grid[j]) / (grid[j + order] - grid[j]) * bases[j];
+                let right = (grid[j + order + 1] - x) / (grid[j + order + 1] - grid[j + 1]) * bases[j + 1];
+                bases[j] = left + right;
+            }
+        }
+    }
+
+    /// Adds what the input `input` of a layer at `x` contributes to each of the layer's outputs.
+    fn input_response(&self, layer: usize, input: usize, x: f32, bases: &mut [f32], outputs: &mut [f32]) {
+        self.b_splines(layer, input, x, bases);
+        let layer_weights = &self.layers[layer];
+        let coefficients = self.grid_size + self.spline_order;
+        let base = silu(x);
+        for (output, value) in outputs.iter_mut().enumerate() {
+            let weight_index = output * layer_weights.inputs + input;
+            let spline_weights = &layer_weights.spline_weight[weight_index * coefficients..(weight_index + 1) * coefficients];
+            let spline: f32 = spline_weights.iter().zip(&bases[..coefficients]).map(|(weight, basis)| weight * basis).sum();
+            *value += layer_weights.base_weight[weight_index] * base + spline;
+        }
+    }
+
+    /// Runs one layer on any inputs.
+    fn forward_layer(&self, layer: usize, inputs: &[f32], outputs: &mut Vec<f32>, bases: &mut [f32]) {
+        outputs.clear();
+        outputs.resize(self.layers[layer].outputs, 0.0);
+        for (input, &x) in inputs.iter().enumerate() {
+            self.input_response(layer, input, x, bases, outputs);
+        }
+    }
+
+    /// The full forward pass on any inputs, every layer evaluating its splines.
+    pub fn forward(&self, inputs: &[f32]) -> f32 {
+        assert_eq!(inputs.len(), INPUT_SIZE);
+        let mut buffers = KanBuffers::new(self);
+        buffers.current.extend_from_slice(inputs);
+        for layer in 0..self.layers.len() {
+            self.forward_layer(layer, &buffers.current, &mut buffers.next, &mut buffers.bases);
+            std::mem::swap(&mut buffers.current, &mut buffers.next);
+        }
+        buffers.current[0]
+    }
+
+    /// Evaluation of a position in centipawns, from the side to move's point of view.
+    fn evaluate(&self, board: &Board, buffers: &mut KanBuffers) -> i32 {
+        let first = &self.layers[0];
+        buffers.current.clear();
+        buffers.current.extend_from_slice(&self.first_layer_bias);
+        let architecture = Architecture::default();
+        for square in 0..64 {
+            if let Some((piece, color)) = board.piece_at(square) {
+                let input = architecture.feature_index(piece, color, square, board.turn, 0);
+                let deltas = &self.first_layer_deltas[input * first.outputs..(input + 1) * first.outputs];
+                buffers.current.iter_mut().zip(deltas).for_each(|(value, delta)| *value += delta);
+            }
+        }
+
+        for layer in 1..self.layers.len() {
+            self.forward_layer(layer, &buffers.current, &mut buffers.next, &mut buffers.bases);
+            std::mem::swap(&mut buffers.current, &mut buffers.next);
+        }
+        // a score past the mate bounds would be read as a mate by the search
+        ((buffers.current[0] * self.scale).round() as i32).clamp(-MATE_IN_MAX_PLY + 1, MATE_IN_MAX_PLY - 1)
+    }
+}
+
+/// Scratch space of a forward pass, kept by the evaluator so evaluating doesn't allocate.
+struct KanBuffers {
+    current: Vec<f32>,
+    next: Vec<f32>,
+    bases: Vec<f32>,
+}
+
+impl KanBuffers {
+    fn new(net: &KanNetwork) -> Self {
+        let widest = net.layers.iter().map(|layer| layer.inputs.max(layer.outputs)).max().unwrap_or(0);
+        KanBuffers {
+            current: Vec::with_capacity(widest),
+            next: Vec::with_capacity(widest),
+            bases: vec![0.0; net.grid_size + 2 * net.spline_order],
+        }
+    }
+}
+
+/// Evaluates positions with a KAN, counting the evaluations and the time spent
+/// in them so its cost per node can be compared with the other evaluators.
+pub struct KanEvaluator {
+    net: Arc<KanNetwork>,
+    buffers: KanBuffers,
+    timing: EvalTiming,
+}
+
+impl KanEvaluator {
+    pub fn new(net: Arc<KanNetwork>) -> Self {
+        KanEvaluator {
+            buffers: KanBuffers::new(&net),
+            net,
+            timing: EvalTiming::default(),
+        }
+    }
+}
+
+impl Evaluator for KanEvaluator {
+    fn name(&self) -> &'static str {
+        "KAN"
+    }
+
+    fn new_instance(&self) -> Box<dyn Evaluator> {
+        Box::new(KanEvaluator::new(Arc::clone(&self.net)))
+    }
+
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        let start = Instant::now();
+        let score = self.net.evaluate(board, &mut self.buffers);
+        self.timing.evaluations += 1;
+        self.timing.time += start.elapsed();
+        score
+    }
+
+    fn reset(&mut self, _board: &Board) {
+        self.timing = EvalTiming::default();
+    }
+
+    fn timing(&self) -> Option<EvalTiming> {
+        Some(self.timing)
+    }
+}
+
+/// Inputs of the full forward pass for a position, 1 for every piece on its square.
+pub fn inputs(board: &Board) -> Vec<f32> {
+    let mut inputs = vec![0.0; INPUT_SIZE];
+    let architecture = Architecture::default();
+    for square in 0..64 {
+        if let Some((piece, color)) = board.piece_at(square) {
+            inputs[architecture.feature_index(piece, color, square, board.turn, 0)] = 1.0;
+        }
+    }
+    inputs
+}
+
+#[cfg(test)]
+mod kan_eval {
+    use std::sync::Arc;
+    use crate::alphabeta::MATE_IN_MAX_PLY;
+    use crate::board::Board;
+    use crate::evaluator::Evaluator;
+    use crate::nnue::NnueError;
+
+    use super::{inputs, KanEvaluator, KanNetwork, HEADER_MAGIC, INPUT_SIZE, UNTRAINED};
+
+    // a made up network with small pseudo random weights and grids shifted off the default
+    fn random_network() -> KanNetwork {
+        let mut net = KanNetwork::zeroed(&[INPUT_SIZE, 8, 4, 1], 5, 3);
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 2001) as f32 / 1000.0 - 1.0
+        };
+        for layer in &mut net.layers {
+            layer.base_weight.iter_mut().for_each(|weight| *weight = random() * 0.1);
+            layer.spline_weight.iter_mut().for_each(|weight| *weight = random() * 0.1);
+            for knots in layer.grid.chunks_exact_mut(12) {
+                let shift = random() * 0.05;
+                knots.iter_mut().for_each(|knot| *knot += shift);
+            }
+        }
+        KanNetwork::new(net.layers, net.grid_size, net.spline_order, net.scale)
+    }
+
+    #[test]
+    fn b_splines_sum_to_one_inside_the_grid() {
+        let net = &UNTRAINED;
+        let mut bases = vec![0.0; 11];
+        for step in 0..200 {
+            let x = -1.0 + step as f32 / 100.0;
+            net.b_splines(0, 0, x, &mut bases);
+            let sum: f32 = bases[..8].iter().sum();
+            assert!((sum - 1.0).abs() < 1e-5, "{} at {}", sum, x);
+        }
+
+        net.b_splines(0, 0, 5.0, &mut bases);
+        assert!(bases[..8].iter().all(|&basis| basis == 0.0));
+    }
+
+    #[test]
+    fn folded_first_layer_matches_the_forward_pass() {
+        let net = Arc::new(random_network());
+        let mut evaluator = KanEvaluator::new(Arc::clone(&net));
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen.to_string());
+            let expected = net.forward(&inputs(&board)) * net.scale;
+            let score = evaluator.evaluate(&board);
+            assert!((score as f32 - expected).abs() <= 1.0, "{} vs {} in {}", score, expected, fen);
+        }
+    }
+
+    #[test]
+    fn scores_stay_below_mate() {
+        let mut net = random_network();
+        net.scale = 1e9;
+        let board = Board::new();
+        let sign = net.forward(&inputs(&board)).signum() as i32;
+        let mut evaluator = KanEvaluator::new(Arc::new(net));
+        assert_eq!(evaluator.evaluate(&board), sign * (MATE_IN_MAX_PLY - 1));
+    }
+
+    #[test]
+    fn networks_round_trip_and_bad_files_are_rejected() {
+        let net = random_network();
+        let bytes = net.to_bytes();
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1".to_string());
+        let loaded = KanNetwork::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.forward(&inputs(&board)), net.forward(&inputs(&board)));
+
+        // a standalone spline scaler is folded into the spline weights
+        let header_size = 28 + 4 * (net.layers.len() + 1);
+        let mut scaled = bytes[..header_size].to_vec();
+        scaled[20] = 1;
+        for layer in &net.layers {
+            let halved_spline_weight = layer.spline_weight.iter().map(|weight| weight / 2.0);
+            let scalers = std::iter::repeat_n(2.0, layer.inputs * layer.outputs);
+            for value in layer.grid.iter().copied().chain(layer.base_weight.iter().copied()).chain(halved_spline_weight).chain(scalers) {
+                scaled.extend(value.to_le_bytes());
+            }
+        }
+        assert_eq!(KanNetwork::from_bytes(&scaled).unwrap().forward(&inputs(&board)), net.forward(&inputs(&board)));
+
+        let truncated = &bytes[..bytes.len() - 4];
+        assert!(matches!(KanNetwork::from_bytes(truncated), Err(NnueError::WrongSize { .. })));
+        assert!(matches!(KanNetwork::from_bytes(&bytes[..10]), Err(NnueError::InvalidArchitecture(_))));
+
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert!(matches!(KanNetwork::from_bytes(&newer), Err(NnueError::UnsupportedVersion(2))));
+
+        let mut two_outputs = bytes.clone();
+        let last_width = 28 + 4 * 3;
+        two_outputs[last_width] = 2;
+        assert!(matches!(KanNetwork::from_bytes(&two_outputs), Err(NnueError::InvalidArchitecture(_))));
+
+        let mut flat_grid = bytes.clone();
+        let grid_start = 28 + 4 * 4;
+        flat_grid.copy_within(grid_start..grid_start + 4, grid_start + 4);
+        assert!(matches!(KanNetwork::from_bytes(&flat_grid), Err(NnueError::InvalidArchitecture(_))));
+
+        assert!(bytes.starts_with(HEADER_MAGIC));
+        assert!(matches!(KanNetwork::load("no/such/network.bin"), Err(NnueError::Io(_))));
+    }
+
+    #[test]
+    fn evaluations_are_counted_until_reset() {
+        let board = Board::new();
+        let mut evaluator = KanEvaluator::new(Arc::clone(&UNTRAINED));
+        evaluator.reset(&board);
+        assert_eq!(evaluator.evaluate(&board), 0);
+        evaluator.evaluate(&board);
+        assert_eq!(evaluator.timing().unwrap().evaluations, 2);
+
+        evaluator.reset(&board);
+        assert_eq!(evaluator.timing().unwrap().evaluations, 0);
+    }
+}
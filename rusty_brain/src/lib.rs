@@ -1,15 +1,25 @@
 pub mod bitboards;
 pub mod board;
+pub mod fen;
 pub mod square;
 mod tests;
 pub mod magic;
 pub mod castling;
 pub mod movement;
+pub mod movegen;
+pub mod notation;
 pub mod piece;
 pub mod perft;
+pub mod pgn;
 pub mod alphabeta;
 pub mod evaluation;
+pub mod evaluator;
 pub mod zobrist;
 pub mod transposition;
 pub mod uci;
 pub mod nnue;
+pub mod kan_eval;
+pub mod simd;
+pub mod time_management;
+pub mod see;
+pub mod move_picker;
@@ -1,6 +1,49 @@
+use std::sync::LazyLock;
+
 use crate::{bitboards::Bitboards, board::Board};
 pub struct Magic;
 
+// The attack tables are built once, on first use, and shared by every Board and thread
+static ROOK_TABLE: LazyLock<MagicTable> = LazyLock::new(|| MagicTable::new(true));
+static BISHOP_TABLE: LazyLock<MagicTable> = LazyLock::new(|| MagicTable::new(false));
+
+// Attacks of one slider type for every square and blocker configuration,
+// all squares share one vector and each one starts at its offset
+struct MagicTable {
+    masks: [u64; 64],
+    offsets: [usize; 64],
+    attacks: Vec<u64>,
+}
+
+impl MagicTable {
+    fn new(is_rook: bool) -> Self {
+        let (magics, shifts) = match is_rook {
+            true => (&Magic::ROOK_MAGICS, &Magic::ROOK_SHIFTS),
+            false => (&Magic::BISHOP_MAGICS, &Magic::BISHOP_SHIFTS)
+        };
+
+        let mut masks = [0u64; 64];
+        let mut offsets = [0usize; 64];
+        let mut attacks = Vec::new();
+        for square in 0..64u8 {
+            masks[square as usize] = match is_rook {
+                true => Bitboards::rook_mask_ex(square),
+                false => Bitboards::bishop_mask_ex(square)
+            };
+            offsets[square as usize] = attacks.len();
+            attacks.extend(Magic::create_piece_table(square, magics[square as usize], shifts[square as usize], is_rook));
+        }
+
+        MagicTable { masks, offsets, attacks }
+    }
+
+    fn attacks(&self, square: u8, occupied: u64, magic: u64, shift: u8) -> u64 {
+        let square = square as usize;
+        let key = (occupied & self.masks[square]).wrapping_mul(magic) >> shift;
+        self.attacks[self.offsets[square] + key as usize]
+    }
+}
+
 impl Magic {
 
     pub const ROOK_SHIFTS: [u8; 64] = [ 52, 52, 52, 52, 52, 52, 52, 52, 53, 53, 53, 54, 53, 53, 54, 53, 53, 54, 54, 54, 53, 53, 54, 53, 53, 54, 53, 53, 54, 54, 54, 53, 52, 54, 53, 53, 53, 53, 54, 53, 52, 53, 54, 54, 53, 53, 54, 53, 53, 54, 54, 54, 53, 53, 54, 53, 52, 53, 53, 53, 53, 53, 53, 52];
@@ -69,17 +112,71 @@ impl Magic {
         moves_table
     }
     
-    pub fn piece_attacks(is_rook: bool) -> [Vec<u64>; 64] {
-        let mut piece_attacks = [const { Vec::new() }; 64];
+    // Squares a rook on the square attacks, the first blocker in each direction included
+    pub fn rook_attacks(square: u8, occupied: u64) -> u64 {
+        ROOK_TABLE.attacks(square, occupied, Self::ROOK_MAGICS[square as usize], Self::ROOK_SHIFTS[square as usize])
+    }
 
-        let (magics, shifts) = match is_rook {
-            true => (&Self::ROOK_MAGICS, &Self::ROOK_SHIFTS),
-            false => (&Self::BISHOP_MAGICS, &Self::BISHOP_SHIFTS)
-        };
+    pub fn bishop_attacks(square: u8, occupied: u64) -> u64 {
+        BISHOP_TABLE.attacks(square, occupied, Self::BISHOP_MAGICS[square as usize], Self::BISHOP_SHIFTS[square as usize])
+    }
+}
+#[cfg(test)]
+mod magic {
+    use crate::bitboards::Bitboards;
+    use crate::board::Board;
 
-        for i in 0..64 {
-            piece_attacks[i] = Self::create_piece_table(i as u8, magics[i], shifts[i], is_rook);
+    use super::Magic;
+
+    #[test]
+    fn lookups_match_ray_walks() {
+        // a fixed xorshift stream of sparse occupancies
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for _ in 0..1000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let occupied = state & state.rotate_left(23);
+
+            for square in 0..64u8 {
+                let position = 1u64 << square;
+                let rook = [Bitboards::move_north, Bitboards::move_east, Bitboards::move_south, Bitboards::move_west]
+                    .into_iter()
+                    .fold(0, |attacks, direction| attacks | Board::get_sliding_bitboard(position, occupied, occupied, direction));
+                let bishop = [Bitboards::move_north_east, Bitboards::move_north_west, Bitboards::move_south_east, Bitboards::move_south_west]
+                    .into_iter()
+                    .fold(0, |attacks, direction| attacks | Board::get_sliding_bitboard(position, occupied, occupied, direction));
+
+                assert_eq!(Magic::rook_attacks(square, occupied), rook);
+                assert_eq!(Magic::bishop_attacks(square, occupied), bishop);
+            }
         }
-        piece_attacks
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn boards_are_cheap_to_copy() {
+        // the attack tables are shared statics, a board is its position plus the history of moves made on it
+        assert!(std::mem::size_of::<Board>() < 256);
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(kiwipete.to_string());
+        for _ in 0..4 {
+            let legal_move = board.generate_legal_moves()[0];
+            board.make_move(legal_move);
+        }
+        let fen = board.to_fen();
+        let zobrist_key = board.zobrist_key;
+
+        // the copy takes the history along and unwinds it without touching the original
+        let mut copy = board.clone();
+        for _ in 0..4 {
+            copy.undo_move();
+        }
+        assert_eq!(copy.to_fen(), kiwipete);
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.zobrist_key, zobrist_key);
+
+        board.undo_move();
+        assert_ne!(board.zobrist_key, zobrist_key);
+    }
+}
@@ -0,0 +1,360 @@
+use crate::alphabeta::MAX_PLY;
+use crate::board::{Board, Turn};
+use crate::movegen::{MoveList, MAX_MOVES};
+use crate::movement::Move;
+use crate::piece::Piece;
+
+// History scores are halved once one of them gets this big
+const HISTORY_MAX: i32 = 1 << 20;
+
+// What the search learns about quiet moves while it runs, used to order them
+pub struct OrderingHeuristics {
+    // two quiet moves per ply that caused a beta cutoff
+    killers: Vec<[Option<Move>; 2]>,
+    // [colour][from][to], grows with depth^2 every time the move causes a cutoff
+    history: Box<[[[i32; 64]; 64]; 2]>,
+    // [from][to] of the previous move, the quiet move that refuted it
+    countermoves: Box<[[Option<Move>; 64]; 64]>,
+}
+
+impl OrderingHeuristics {
+    pub fn new() -> Self {
+        OrderingHeuristics {
+            killers: vec![[None; 2]; MAX_PLY as usize + 1],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            countermoves: Box::new([[None; 64]; 64]),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    // Called when a quiet move caused a beta cutoff
+    pub fn update(&mut self, board: &Board, cutoff_move: Move, ply: i32, depth_left: i32) {
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(cutoff_move) {
+            killers[1] = killers[0];
+            killers[0] = Some(cutoff_move);
+        }
+
+        let history = &mut self.history[Self::color_index(board.turn)];
+        let entry = &mut history[cutoff_move.get_from() as usize][cutoff_move.get_to() as usize];
+        *entry += depth_left * depth_left;
+        if *entry > HISTORY_MAX {
+            history.iter_mut().flatten().for_each(|score| *score /= 2);
+        }
+
+        if let Some(previous_move) = board.last_move() {
+            self.countermoves[previous_move.get_from() as usize][previous_move.get_to() as usize] = Some(cutoff_move);
+        }
+    }
+
+    fn history_score(&self, turn: Turn, quiet_move: Move) -> i32 {
+        self.history[Self::color_index(turn)][quiet_move.get_from() as usize][quiet_move.get_to() as usize]
+    }
+
+    fn countermove(&self, board: &Board) -> Option<Move> {
+        let previous_move = board.last_move()?;
+        self.countermoves[previous_move.get_from() as usize][previous_move.get_to() as usize]
+    }
+
+    fn color_index(turn: Turn) -> usize {
+        match turn {
+            Turn::White => 0,
+            Turn::Black => 1,
+        }
+    }
+}
+
+impl Default for OrderingHeuristics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    TtMove,
+    GoodCaptures,
+    Killers,
+    Countermove,
+    Quiets,
+    BadCaptures,
+    Unordered,
+}
+
+// Hands out the legal moves of a node in the order they are most likely to cause a cutoff:
+//  1. the transposition table move
+//  2. captures and promotions that don't lose material, most valuable victim / least valuable attacker first
+//  3. the killer moves of this ply
+//  4. the countermove of the previous move
+//  5. the remaining quiet moves by history score
+//  6. the captures static exchange evaluation says lose material
+// Quiet moves are only generated once the captures are used up (or the table move is quiet),
+// each stage only sorts as far as the search gets and legality is checked on the way out.
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    countermove: Option<Move>,
+    pinned: u64,
+    // the noisy moves followed by the quiet ones, each with its ordering score
+    moves: MoveList,
+    scores: [i32; MAX_MOVES],
+    // moves[next_noisy..noisy_end] and moves[next_quiet..] haven't been handed out yet,
+    // the losing captures are parked in moves[..bad_captures] in the order they were picked
+    next_noisy: usize,
+    noisy_end: usize,
+    next_quiet: usize,
+    quiets_generated: bool,
+    bad_captures: usize,
+    next_bad_capture: usize,
+}
+
+impl MovePicker {
+    pub fn new(board: &Board, tt_move: Option<Move>, heuristics: &OrderingHeuristics, ply: i32) -> Self {
+        let mut move_picker = Self::empty(board, Stage::TtMove);
+        move_picker.tt_move = tt_move;
+        move_picker.killers = heuristics.killers[ply as usize];
+        move_picker.countermove = heuristics.countermove(board);
+
+        // in check the evasions are split the same way, there are no other moves to generate
+        if board.checkers != 0 {
+            let mut evasions = MoveList::new();
+            board.generate_evasions(&mut evasions);
+            evasions.iter().filter(|evasion| !evasion.is_quiet()).for_each(|noisy| move_picker.moves.push(*noisy));
+            move_picker.score_noisy(board);
+            evasions.iter().filter(|evasion| evasion.is_quiet()).for_each(|quiet| move_picker.moves.push(*quiet));
+            move_picker.score_quiets(board, heuristics);
+        } else {
+            board.generate_noisy(&mut move_picker.moves);
+            move_picker.score_noisy(board);
+        }
+        move_picker
+    }
+
+    // Hands out the moves as they are generated, for comparing against the ordered search.
+    // Quiets come before captures, handing out the captures first would already be move ordering
+    pub fn unordered(board: &Board) -> Self {
+        let mut move_picker = Self::empty(board, Stage::Unordered);
+        if board.checkers != 0 {
+            board.generate_evasions(&mut move_picker.moves);
+        } else {
+            board.generate_quiets(&mut move_picker.moves);
+            board.generate_noisy(&mut move_picker.moves);
+        }
+        move_picker
+    }
+
+    fn empty(board: &Board, stage: Stage) -> Self {
+        MovePicker {
+            stage,
+            tt_move: None,
+            killers: [None; 2],
+            countermove: None,
+            pinned: board.pinned(),
+            moves: MoveList::new(),
+            scores: [0; MAX_MOVES],
+            next_noisy: 0,
+            noisy_end: 0,
+            next_quiet: 0,
+            quiets_generated: false,
+            bad_captures: 0,
+            next_bad_capture: 0,
+        }
+    }
+
+    pub fn next(&mut self, board: &Board, heuristics: &OrderingHeuristics) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GoodCaptures;
+                    // a move from the table that isn't legal here comes from a hash collision
+                    if let Some(tt_move) = self.tt_move {
+                        let found = match tt_move.is_quiet() {
+                            true => self.take_quiet(board, heuristics, tt_move),
+                            false => self.take_noisy(tt_move),
+                        };
+                        if found && board.is_legal(tt_move, self.pinned) {
+                            return Some(tt_move);
+                        }
+                    }
+                },
+                Stage::GoodCaptures => {
+                    if self.next_noisy == self.noisy_end {
+                        self.stage = Stage::Killers;
+                        continue;
+                    }
+                    let (score, capture) = self.pick_best(self.next_noisy, self.noisy_end);
+                    self.next_noisy += 1;
+                    if !board.is_legal(capture, self.pinned) {
+                        continue;
+                    }
+                    if board.see(capture) >= 0 {
+                        return Some(capture);
+                    }
+                    // the slots before next_noisy are free, the ones already handed out
+                    self.moves[self.bad_captures] = capture;
+                    self.scores[self.bad_captures] = score;
+                    self.bad_captures += 1;
+                },
+                Stage::Killers => {
+                    self.stage = Stage::Countermove;
+                    for killer in self.killers.into_iter().flatten() {
+                        if self.take_quiet(board, heuristics, killer) && board.is_legal(killer, self.pinned) {
+                            return Some(killer);
+                        }
+                    }
+                },
+                Stage::Countermove => {
+                    self.stage = Stage::Quiets;
+                    if let Some(countermove) = self.countermove {
+                        if self.take_quiet(board, heuristics, countermove) && board.is_legal(countermove, self.pinned) {
+                            return Some(countermove);
+                        }
+                    }
+                },
+                Stage::Quiets => {
+                    self.generate_quiets(board, heuristics);
+                    if self.next_quiet == self.moves.len() {
+                        self.stage = Stage::BadCaptures;
+                        continue;
+                    }
+                    let (_, quiet) = self.pick_best(self.next_quiet, self.moves.len());
+                    self.next_quiet += 1;
+                    if board.is_legal(quiet, self.pinned) {
+                        return Some(quiet);
+                    }
+                },
+                Stage::BadCaptures => {
+                    if self.next_bad_capture == self.bad_captures {
+                        return None;
+                    }
+                    self.next_bad_capture += 1;
+                    return Some(self.moves[self.next_bad_capture - 1]);
+                },
+                Stage::Unordered => {
+                    let unordered = *self.moves.get(self.next_noisy)?;
+                    self.next_noisy += 1;
+                    if board.is_legal(unordered, self.pinned) {
+                        return Some(unordered);
+                    }
+                },
+            }
+        }
+    }
+
+    fn score_noisy(&mut self, board: &Board) {
+        for index in self.noisy_end..self.moves.len() {
+            self.scores[index] = Self::mvv_lva(board, self.moves[index]);
+        }
+        self.noisy_end = self.moves.len();
+        self.next_quiet = self.noisy_end;
+    }
+
+    fn score_quiets(&mut self, board: &Board, heuristics: &OrderingHeuristics) {
+        for index in self.noisy_end..self.moves.len() {
+            self.scores[index] = heuristics.history_score(board.turn, self.moves[index]);
+        }
+        self.quiets_generated = true;
+    }
+
+    fn generate_quiets(&mut self, board: &Board, heuristics: &OrderingHeuristics) {
+        if !self.quiets_generated {
+            board.generate_quiets(&mut self.moves);
+            self.score_quiets(board, heuristics);
+        }
+    }
+
+    // Moves the table move out of the noisy moves still to come, false if it isn't one of them
+    fn take_noisy(&mut self, noisy_move: Move) -> bool {
+        let found = self.moves[self.next_noisy..self.noisy_end].iter().position(|noisy| *noisy == noisy_move);
+        if let Some(index) = found {
+            self.swap(self.next_noisy, self.next_noisy + index);
+            self.next_noisy += 1;
+        }
+        found.is_some()
+    }
+
+    // Moves a table move, killer or countermove out of the quiets still to come, false if it isn't one of them
+    fn take_quiet(&mut self, board: &Board, heuristics: &OrderingHeuristics, quiet_move: Move) -> bool {
+        self.generate_quiets(board, heuristics);
+        let found = self.moves[self.next_quiet..].iter().position(|quiet| *quiet == quiet_move);
+        if let Some(index) = found {
+            self.swap(self.next_quiet, self.next_quiet + index);
+            self.next_quiet += 1;
+        }
+        found.is_some()
+    }
+
+    // Swaps the best scored of moves[start..end] to the start and returns it
+    fn pick_best(&mut self, start: usize, end: usize) -> (i32, Move) {
+        let best_index = (start..end).max_by_key(|index| self.scores[*index]).unwrap_or(start);
+        self.swap(start, best_index);
+        (self.scores[start], self.moves[start])
+    }
+
+    fn swap(&mut self, first: usize, second: usize) {
+        self.moves.swap(first, second);
+        self.scores.swap(first, second);
+    }
+
+    // Most valuable victim first, least valuable attacker among equal victims.
+    // A promotion counts as capturing the material it gains.
+    fn mvv_lva(board: &Board, noisy_move: Move) -> i32 {
+        let victim = if noisy_move.get_flags() == Move::EP_CAPTURE {
+            Piece::Pawn.value()
+        } else if noisy_move.is_capture() {
+            board.piece_at(noisy_move.get_to()).map_or(0, |(piece, _)| piece.value())
+        } else {
+            0
+        };
+        let promotion = noisy_move.get_promotion_piece().map_or(0, |piece| piece.value() - Piece::Pawn.value());
+        let attacker = board.piece_at(noisy_move.get_from()).map_or(0, |(piece, _)| piece as i32);
+
+        (victim + promotion) * 8 - attacker
+    }
+}
+
+#[cfg(test)]
+mod move_picker {
+    use crate::board::Board;
+
+    use super::{MovePicker, OrderingHeuristics};
+
+    #[test]
+    fn every_move_is_picked_once() {
+        let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0".to_string());
+        let moves = board.generate_legal_moves();
+        let tt_move = moves[moves.len() / 2];
+
+        let mut heuristics = OrderingHeuristics::new();
+        let killer = *moves.iter().find(|legal| legal.is_quiet() && **legal != tt_move).unwrap();
+        heuristics.update(&board, killer, 0, 4);
+
+        let mut move_picker = MovePicker::new(&board, Some(tt_move), &heuristics, 0);
+        let mut picked = Vec::new();
+        while let Some(picked_move) = move_picker.next(&board, &heuristics) {
+            picked.push(picked_move);
+        }
+
+        assert_eq!(picked[0], tt_move);
+        assert_eq!(picked.len(), moves.len());
+        assert!(moves.iter().all(|legal| picked.contains(legal)));
+
+        // the killer comes right after the captures that don't lose material
+        let killer_index = picked.iter().position(|picked_move| *picked_move == killer).unwrap();
+        assert!(picked[1..killer_index].iter().all(|picked_move| !picked_move.is_quiet() && board.see(*picked_move) >= 0));
+    }
+
+    #[test]
+    fn most_valuable_victim_comes_first() {
+        // the rook takes the queen before the pawn takes the pawn, although the pawn is the cheaper attacker
+        let board = Board::from_fen("4k3/8/q7/8/8/3p4/4P3/R3K3 w - - 0 1".to_string());
+        let heuristics = OrderingHeuristics::new();
+        let mut move_picker = MovePicker::new(&board, None, &heuristics, 0);
+        assert_eq!(move_picker.next(&board, &heuristics).unwrap().to_string(), "a1a6");
+        assert_eq!(move_picker.next(&board, &heuristics).unwrap().to_string(), "e2d3");
+    }
+}
@@ -0,0 +1,556 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::LazyLock;
+
+use crate::board::{Board, Turn};
+use crate::magic::Magic;
+use crate::movement::Move;
+
+// No chess position has more legal moves than this (the record is 218)
+pub const MAX_MOVES: usize = 256;
+
+const NOT_A_FILE: u64 = 0xfefefefefefefefe;
+const NOT_H_FILE: u64 = 0x7f7f7f7f7f7f7f7f;
+
+// [from][to] the squares strictly between two squares sharing a rank, file or diagonal, 0 otherwise
+static BETWEEN: LazyLock<[[u64; 64]; 64]> = LazyLock::new(|| build_lines(false));
+// [from][to] the whole rank, file or diagonal two squares share, 0 if they share none
+static LINE: LazyLock<[[u64; 64]; 64]> = LazyLock::new(|| build_lines(true));
+
+fn build_lines(full_line: bool) -> [[u64; 64]; 64] {
+    let mut lines = [[0; 64]; 64];
+    for from in 0..64u8 {
+        for to in (0..64u8).filter(|to| *to != from) {
+            let ends = 1 << from | 1 << to;
+            let attacks: [fn(u8, u64) -> u64; 2] = [Magic::rook_attacks, Magic::bishop_attacks];
+            for attacks in attacks.into_iter().filter(|attacks| attacks(from, 0) & 1 << to != 0) {
+                lines[from as usize][to as usize] = match full_line {
+                    true => attacks(from, 0) & attacks(to, 0) | ends,
+                    false => attacks(from, 1 << to) & attacks(to, 1 << from),
+                };
+            }
+        }
+    }
+    lines
+}
+
+// A fixed capacity list of moves that lives on the stack, so generating moves doesn't allocate.
+// Derefs to the slice of moves pushed so far.
+#[derive(Clone, Copy)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            moves: [Move::from_u16(0); MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, new_move: Move) {
+        self.moves[self.len] = new_move;
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    // Keeps the moves the predicate accepts, in their original order
+    pub fn retain(&mut self, mut keep: impl FnMut(&Move) -> bool) {
+        let mut kept = 0;
+        for index in 0..self.len {
+            if keep(&self.moves[index]) {
+                self.moves[kept] = self.moves[index];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GenType {
+    Noisy,
+    Quiets,
+    Evasions,
+}
+
+// Pseudo-legal move generation: the generators below may leave the own king in check,
+// is_legal sorts those moves out one at a time, so the moves a cutoff skips are never checked.
+//  noisy: captures (en passant included) and promotions
+//  quiets: everything else, castling included
+//  evasions: the moves that capture or block a single checker, plus every king move
+// Out of check noisy and quiets together are all the moves, in check only the evasions are.
+impl Board {
+    pub fn generate_noisy(&self, moves: &mut MoveList) {
+        self.generate(moves, GenType::Noisy);
+    }
+
+    pub fn generate_quiets(&self, moves: &mut MoveList) {
+        self.generate(moves, GenType::Quiets);
+    }
+
+    pub fn generate_evasions(&self, moves: &mut MoveList) {
+        self.generate(moves, GenType::Evasions);
+    }
+
+    // Every pseudo-legal move of the position, evasions when in check
+    pub fn generate_pseudo_legal(&self, moves: &mut MoveList) {
+        if self.checkers != 0 {
+            self.generate_evasions(moves);
+        } else {
+            self.generate_noisy(moves);
+            self.generate_quiets(moves);
+        }
+    }
+
+    // All the legal moves, without the checkmate and stalemate flags generate_legal_moves sets
+    pub fn generate_legal(&self, moves: &mut MoveList) {
+        self.generate_pseudo_legal(moves);
+        let pinned = self.pinned();
+        moves.retain(|pseudo_legal| self.is_legal(*pseudo_legal, pinned));
+    }
+
+    // Whether the side to move has any legal move, legality is only checked until one is found
+    pub fn has_legal_move(&self) -> bool {
+        let mut moves = MoveList::new();
+        self.generate_pseudo_legal(&mut moves);
+        let pinned = self.pinned();
+        moves.iter().any(|pseudo_legal| self.is_legal(*pseudo_legal, pinned))
+    }
+
+    // The enemy pieces giving check, make_move keeps them in Board::checkers
+    pub fn find_checkers(&self) -> u64 {
+        let king_bitboard = self.king_bitboard();
+        if king_bitboard == 0 {
+            return 0;
+        }
+        let occupied = !self.bitboards.get_empty_squares();
+        self.attackers_to(king_bitboard.trailing_zeros() as u8, occupied) & self.bitboards.get_enemy_pieces(self.turn)
+    }
+
+    // The own pieces that stand alone between the king and an enemy slider
+    pub fn pinned(&self) -> u64 {
+        let king_bitboard = self.king_bitboard();
+        if king_bitboard == 0 {
+            return 0;
+        }
+        let king_square = king_bitboard.trailing_zeros() as u8;
+        let (rooks, bishops, queens) = match self.turn {
+            Turn::White => (self.bitboards.black_rooks, self.bitboards.black_bishops, self.bitboards.black_queens),
+            Turn::Black => (self.bitboards.white_rooks, self.bitboards.white_bishops, self.bitboards.white_queens),
+        };
+        let ally_bitboard = self.bitboards.get_ally_pieces(self.turn);
+        let occupied = !self.bitboards.get_empty_squares();
+
+        let mut snipers = (Magic::rook_attacks(king_square, 0) & (rooks | queens))
+            | (Magic::bishop_attacks(king_square, 0) & (bishops | queens));
+        let mut pinned = 0;
+        while snipers != 0 {
+            let blockers = BETWEEN[king_square as usize][snipers.trailing_zeros() as usize] & occupied;
+            if blockers.count_ones() == 1 {
+                pinned |= blockers & ally_bitboard;
+            }
+            snipers &= snipers - 1;
+        }
+        pinned
+    }
+
+    // Whether a move from the generators above leaves the own king safe, pinned are the pieces of pinned().
+    // The generators already only block or capture a checker, what is left to check is the king
+    // stepping into an attack, a pinned piece leaving its line and en passant emptying two squares at once.
+    pub fn is_legal(&self, move_to_check: Move, pinned: u64) -> bool {
+        let king_bitboard = self.king_bitboard();
+        if king_bitboard == 0 {
+            return true;
+        }
+        let from = move_to_check.get_from();
+        let to = move_to_check.get_to();
+        let flags = move_to_check.get_flags();
+        let enemy_bitboard = self.bitboards.get_enemy_pieces(self.turn);
+        let occupied = !self.bitboards.get_empty_squares();
+
+        if flags == Move::EP_CAPTURE {
+            let captured_pawn = match self.turn {
+                Turn::White => to - 8,
+                Turn::Black => to + 8,
+            };
+            let occupied = (occupied & !(1 << from) & !(1 << captured_pawn)) | 1 << to;
+            return self.attackers_to(king_bitboard.trailing_zeros() as u8, occupied) & enemy_bitboard & occupied == 0;
+        }
+
+        if 1 << from == king_bitboard {
+            // the squares the king crosses when castling were checked by the generator
+            return flags == Move::KING_CASTLE
+                || flags == Move::QUEEN_CASTLE
+                || self.attackers_to(to, occupied & !king_bitboard) & enemy_bitboard == 0;
+        }
+
+        pinned & 1 << from == 0 || LINE[from as usize][to as usize] & king_bitboard != 0
+    }
+
+    fn king_bitboard(&self) -> u64 {
+        match self.turn {
+            Turn::White => self.bitboards.white_king,
+            Turn::Black => self.bitboards.black_king,
+        }
+    }
+
+    fn generate(&self, moves: &mut MoveList, gen_type: GenType) {
+        let ally_bitboard = self.bitboards.get_ally_pieces(self.turn);
+        let enemy_bitboard = self.bitboards.get_enemy_pieces(self.turn);
+        let empty_bitboard = self.bitboards.get_empty_squares();
+        let king_bitboard = self.king_bitboard();
+
+        // the squares the pieces other than the king may move to
+        let targets = match gen_type {
+            GenType::Noisy => enemy_bitboard,
+            GenType::Quiets => empty_bitboard,
+            GenType::Evasions => {
+                let checkers = self.checkers;
+                if king_bitboard != 0 {
+                    let king_square = king_bitboard.trailing_zeros() as u8;
+                    self.add_piece_moves(moves, king_square, self.get_king_attacked_squares(king_bitboard) & !ally_bitboard);
+                }
+                // in double check only the king can move
+                if checkers.count_ones() != 1 {
+                    return;
+                }
+                let checker = checkers.trailing_zeros() as usize;
+                BETWEEN[king_bitboard.trailing_zeros() as usize][checker] | checkers
+            },
+        };
+
+        self.generate_pawn_moves(moves, gen_type, targets);
+
+        let bitboards = &self.bitboards;
+        let (knights, bishops, rooks, queens) = match self.turn {
+            Turn::White => (bitboards.white_knights, bitboards.white_bishops, bitboards.white_rooks, bitboards.white_queens),
+            Turn::Black => (bitboards.black_knights, bitboards.black_bishops, bitboards.black_rooks, bitboards.black_queens),
+        };
+        let occupied = !empty_bitboard;
+
+        let mut pieces = knights;
+        while pieces != 0 {
+            let from = pieces.trailing_zeros() as u8;
+            self.add_piece_moves(moves, from, self.get_knight_attacked_squares(1 << from) & targets);
+            pieces &= pieces - 1;
+        }
+
+        let mut pieces = bishops | queens;
+        while pieces != 0 {
+            let from = pieces.trailing_zeros() as u8;
+            self.add_piece_moves(moves, from, Magic::bishop_attacks(from, occupied) & targets);
+            pieces &= pieces - 1;
+        }
+
+        let mut pieces = rooks | queens;
+        while pieces != 0 {
+            let from = pieces.trailing_zeros() as u8;
+            self.add_piece_moves(moves, from, Magic::rook_attacks(from, occupied) & targets);
+            pieces &= pieces - 1;
+        }
+
+        if gen_type != GenType::Evasions && king_bitboard != 0 {
+            let king_square = king_bitboard.trailing_zeros() as u8;
+            self.add_piece_moves(moves, king_square, self.get_king_attacked_squares(king_bitboard) & targets);
+            if gen_type == GenType::Quiets {
+                self.generate_castling(moves, king_square, occupied);
+            }
+        }
+    }
+
+    fn generate_pawn_moves(&self, moves: &mut MoveList, gen_type: GenType, targets: u64) {
+        let enemy_bitboard = self.bitboards.get_enemy_pieces(self.turn);
+        let empty_bitboard = self.bitboards.get_empty_squares();
+
+        // offsets are added to the start square, shifting left for white and right for black
+        let (pawns, push, left_capture, right_capture, double_push_rank, promotion_rank) = match self.turn {
+            Turn::White => (self.bitboards.white_pawns, 8, 7, 9, 0x00000000FF000000u64, 0xFF00000000000000u64),
+            Turn::Black => (self.bitboards.black_pawns, -8, -9, -7, 0x000000FF00000000, 0x00000000000000FF),
+        };
+        let shift = |bitboard: u64, offset: i32| match offset > 0 {
+            true => bitboard << offset,
+            false => bitboard >> -offset,
+        };
+
+        let single_pushes = shift(pawns, push) & empty_bitboard;
+        let double_pushes = shift(single_pushes, push) & empty_bitboard & double_push_rank;
+        let captures_left = shift(pawns, left_capture) & NOT_H_FILE & enemy_bitboard;
+        let captures_right = shift(pawns, right_capture) & NOT_A_FILE & enemy_bitboard;
+
+        // pushes onto the last rank promote, which makes them noisy
+        let (push_targets, capture_targets) = match gen_type {
+            GenType::Noisy => (promotion_rank, targets),
+            GenType::Quiets => (!promotion_rank, 0),
+            GenType::Evasions => (targets, targets),
+        };
+
+        Self::add_pawn_moves(moves, single_pushes & push_targets, push, Move::QUIET_MOVE, promotion_rank);
+        if gen_type != GenType::Noisy {
+            Self::add_pawn_moves(moves, double_pushes & push_targets, 2 * push, Move::DOUBLE_PAWN_PUSH, 0);
+        }
+        Self::add_pawn_moves(moves, captures_left & capture_targets, left_capture, Move::CAPTURE, promotion_rank);
+        Self::add_pawn_moves(moves, captures_right & capture_targets, right_capture, Move::CAPTURE, promotion_rank);
+
+        // is_legal makes sure an en passant evasion really gets rid of the check
+        if let Some(en_passant_square) = self.en_passant_square.filter(|_| gen_type != GenType::Quiets) {
+            let en_passant_bitboard = 1u64 << en_passant_square as u8;
+            let attackers = (shift(en_passant_bitboard, -left_capture) & NOT_A_FILE | shift(en_passant_bitboard, -right_capture) & NOT_H_FILE) & pawns;
+            let mut attackers = attackers;
+            while attackers != 0 {
+                moves.push(Move::encode(attackers.trailing_zeros() as u8, en_passant_square as u8, Move::EP_CAPTURE));
+                attackers &= attackers - 1;
+            }
+        }
+    }
+
+    // One move for every target, the start square is offset squares behind it
+    fn add_pawn_moves(moves: &mut MoveList, mut targets: u64, offset: i32, flags: u8, promotion_rank: u64) {
+        while targets != 0 {
+            let to = targets.trailing_zeros() as u8;
+            let from = (to as i32 - offset) as u8;
+            if promotion_rank & 1 << to != 0 {
+                let capture = flags & Move::CAPTURE;
+                for promotion in [Move::QUEEN_PROMOTION, Move::KNIGHT_PROMOTION, Move::ROOK_PROMOTION, Move::BISHOP_PROMOTION] {
+                    moves.push(Move::encode(from, to, promotion | capture));
+                }
+            } else {
+                moves.push(Move::encode(from, to, flags));
+            }
+            targets &= targets - 1;
+        }
+    }
+
+    fn add_piece_moves(&self, moves: &mut MoveList, from: u8, mut targets: u64) {
+        let enemy_bitboard = self.bitboards.get_enemy_pieces(self.turn);
+        while targets != 0 {
+            let to = targets.trailing_zeros() as u8;
+            let flags = match enemy_bitboard & 1 << to {
+                0 => Move::QUIET_MOVE,
+                _ => Move::CAPTURE,
+            };
+            moves.push(Move::encode(from, to, flags));
+            targets &= targets - 1;
+        }
+    }
+
+    // The rook has to be on its corner, the squares between king and rook have to be empty
+    // and the king may not start on, cross or land on an attacked square
+    fn generate_castling(&self, moves: &mut MoveList, king_square: u8, occupied: u64) {
+        let enemy_bitboard = self.bitboards.get_enemy_pieces(self.turn);
+        let attacked = |square: u8| self.attackers_to(square, occupied) & enemy_bitboard != 0;
+        let rooks = match self.turn {
+            Turn::White => self.bitboards.white_rooks,
+            Turn::Black => self.bitboards.black_rooks,
+        };
+
+        if self.castling_rights.check_king_side(self.turn)
+            && rooks & 1 << (king_square + 3) != 0
+            && occupied & (1 << (king_square + 1) | 1 << (king_square + 2)) == 0
+            && !(king_square..=king_square + 2).any(attacked)
+        {
+            moves.push(Move::encode(king_square, king_square + 2, Move::KING_CASTLE));
+        }
+
+        if self.castling_rights.check_queen_side(self.turn)
+            && rooks & 1 << (king_square - 4) != 0
+            && occupied & (1 << (king_square - 1) | 1 << (king_square - 2) | 1 << (king_square - 3)) == 0
+            && !(king_square - 2..=king_square).any(attacked)
+        {
+            moves.push(Move::encode(king_square, king_square - 2, Move::QUEEN_CASTLE));
+        }
+    }
+}
+
+#[cfg(test)]
+mod movegen {
+    use crate::board::{Board, Turn};
+    use crate::movement::Move;
+
+    use super::MoveList;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0";
+
+    fn sorted(moves: impl IntoIterator<Item = String>) -> Vec<String> {
+        let mut moves: Vec<String> = moves.into_iter().collect();
+        moves.sort();
+        moves
+    }
+
+    // The legal moves of a position as the generators split them, noisy then quiet.
+    // In check the evasions are split by whether they are quiet
+    fn split(board: &Board) -> (Vec<Move>, Vec<Move>) {
+        let pinned = board.pinned();
+        let legal = |moves: MoveList| -> Vec<Move> { moves.iter().copied().filter(|pseudo| board.is_legal(*pseudo, pinned)).collect() };
+
+        let mut noisy = MoveList::new();
+        let mut quiets = MoveList::new();
+        if board.checkers != 0 {
+            board.generate_evasions(&mut noisy);
+            for evasion in noisy.iter().filter(|evasion| evasion.is_quiet()) {
+                quiets.push(*evasion);
+            }
+            noisy.retain(|evasion| !evasion.is_quiet());
+        } else {
+            board.generate_noisy(&mut noisy);
+            board.generate_quiets(&mut quiets);
+        }
+        (legal(noisy), legal(quiets))
+    }
+
+    #[test]
+    fn generators_split_the_legal_moves() {
+        #[derive(Default, Debug, PartialEq)]
+        struct Split {
+            nodes: u64,
+            captures: u64,
+            en_passant: u64,
+            castles: u64,
+            checks: u64,
+            checkmates: u64,
+        }
+
+        // What the moves of the last ply are in the perft tables of chessprogramming.org.
+        // None of these have promotions that deep, so the noisy moves are the captures
+        const SPLITS: [(&str, usize, Split); 4] = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4,
+                Split { nodes: 197281, captures: 1576, en_passant: 0, castles: 0, checks: 469, checkmates: 8 }),
+            (KIWIPETE, 2, Split { nodes: 2039, captures: 351, en_passant: 1, castles: 91, checks: 3, checkmates: 0 }),
+            (KIWIPETE, 3, Split { nodes: 97862, captures: 17102, en_passant: 45, castles: 3162, checks: 993, checkmates: 1 }),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 0", 4,
+                Split { nodes: 43238, captures: 3348, en_passant: 123, castles: 0, checks: 1680, checkmates: 17 }),
+        ];
+
+        fn walk(board: &mut Board, depth: usize, split_moves: &mut Split) {
+            let (noisy, quiets) = split(board);
+            if depth > 1 {
+                for legal_move in noisy.into_iter().chain(quiets) {
+                    board.make_move(legal_move);
+                    walk(board, depth - 1, split_moves);
+                    board.undo_move();
+                }
+                return;
+            }
+
+            split_moves.nodes += (noisy.len() + quiets.len()) as u64;
+            split_moves.captures += noisy.len() as u64;
+            split_moves.en_passant += noisy.iter().filter(|capture| capture.get_flags() == Move::EP_CAPTURE).count() as u64;
+            split_moves.castles += quiets.iter()
+                .filter(|quiet| quiet.get_flags() == Move::KING_CASTLE || quiet.get_flags() == Move::QUEEN_CASTLE)
+                .count() as u64;
+            for legal_move in noisy.into_iter().chain(quiets) {
+                board.make_move(legal_move);
+                if board.checkers != 0 {
+                    split_moves.checks += 1;
+                    let (noisy_evasions, quiet_evasions) = split(board);
+                    if noisy_evasions.is_empty() && quiet_evasions.is_empty() {
+                        split_moves.checkmates += 1;
+                    }
+                }
+                board.undo_move();
+            }
+        }
+
+        for (fen, depth, expected) in SPLITS {
+            let mut split_moves = Split::default();
+            walk(&mut Board::from_fen(fen.to_string()), depth, &mut split_moves);
+            assert_eq!(split_moves, expected, "{} depth {}", fen, depth);
+        }
+
+        // and move by move where the generators have to sort out promotions, evasions and castling rights:
+        // (fen, noisy, quiet)
+        let cases = [
+            ("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", vec!["e7e8b", "e7e8n", "e7e8q", "e7e8r"], vec!["e1d1", "e1d2", "e1e2", "e1f1", "e1f2"]),
+            ("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1", vec!["e1d2"], vec!["e1f1"]),
+            ("r3k2r/8/8/8/8/8/8/4K3 b kq - 0 1", vec![], vec![
+                "a8a1", "a8a2", "a8a3", "a8a4", "a8a5", "a8a6", "a8a7", "a8b8", "a8c8", "a8d8",
+                "e8c8", "e8d7", "e8d8", "e8e7", "e8f7", "e8f8", "e8g8",
+                "h8f8", "h8g8", "h8h1", "h8h2", "h8h3", "h8h4", "h8h5", "h8h6", "h8h7",
+            ]),
+        ];
+        for (fen, noisy, quiets) in cases {
+            let (noisy_moves, quiet_moves) = split(&Board::from_fen(fen.to_string()));
+            assert_eq!(sorted(noisy_moves.iter().map(|noisy| noisy.to_string())), noisy, "{}", fen);
+            assert_eq!(sorted(quiet_moves.iter().map(|quiet| quiet.to_string())), quiets, "{}", fen);
+        }
+
+        // a castling right left without its rook, which a FEN can't even describe
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1".to_string());
+        board.castling_rights.black_king_side = true;
+        board.castling_rights.black_queen_side = true;
+        let (noisy_moves, quiet_moves) = split(&board);
+        assert!(noisy_moves.is_empty());
+        assert_eq!(sorted(quiet_moves.iter().map(|quiet| quiet.to_string())), ["e8d7", "e8d8", "e8e7", "e8f7", "e8f8"]);
+    }
+
+    #[test]
+    fn castling_needs_the_rook_on_its_corner() {
+        // the bishop takes the last black rook on h8, black keeps no king side right and can't castle
+        let mut board = Board::from_fen("4k2r/8/8/8/8/8/1B6/4K3 w k - 0 1".to_string());
+        let capture = *board.generate_legal_moves().iter().find(|legal| legal.to_string() == "b2h8").unwrap();
+        board.make_move(capture);
+
+        assert!(!board.castling_rights.check_king_side(Turn::Black));
+        assert!(board.generate_legal_moves().iter().all(|legal| legal.to_string() != "e8g8"));
+        assert_eq!(board.zobrist_key, Board::from_fen("4k2B/8/8/8/8/8/8/4K3 b - - 0 1".to_string()).zobrist_key);
+
+        // a right without its rook is never castled either
+        let mut moves = MoveList::new();
+        let mut rookless = board.clone();
+        rookless.castling_rights.black_king_side = true;
+        rookless.generate_pseudo_legal(&mut moves);
+        assert!(moves.iter().all(|pseudo| pseudo.get_flags() != Move::KING_CASTLE));
+    }
+
+    #[test]
+    fn move_list_keeps_push_order() {
+        let board = Board::from_fen(KIWIPETE.to_string());
+        let mut moves = MoveList::new();
+        board.generate_pseudo_legal(&mut moves);
+        let all_moves = moves.to_vec();
+
+        moves.retain(|kept| kept.is_capture());
+        let captures: Vec<_> = all_moves.iter().copied().filter(|capture| capture.is_capture()).collect();
+        assert_eq!(&moves[..], &captures[..]);
+
+        moves.clear();
+        assert!(moves.is_empty());
+    }
+}
@@ -1,12 +1,13 @@
 use core::fmt;
 
+use crate::piece::Piece;
 use crate::square::Square;
 
 
 
 // Each move is represented in 16 bits
 // 6 bits "from" square, 6 bits "to" square, and 4 bits for flags
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Move {
     encoded_move: u16,
 }
@@ -33,6 +34,15 @@ impl Move {
         }
     }
 
+    // The 16 bit encoding, used to pack moves into transposition table entries
+    pub fn to_u16(&self) -> u16 {
+        self.encoded_move
+    }
+
+    pub fn from_u16(encoded_move: u16) -> Self {
+        Self { encoded_move }
+    }
+
     pub fn decode(encoded_move: u16) -> (u8, u8, u8) {
         ((encoded_move & 0x3F) as u8, 
          ((encoded_move >> 6) & 0x3F) as u8, 
@@ -112,6 +122,22 @@ impl Move {
     pub fn is_queen_promo_capture(&self) -> bool {
         (self.encoded_move & (Move::QUEEN_PROMO_CAPTURE as u16) << 12) != 0
     }
+
+    // Moves that don't change the material on the board, the ones ordered by history
+    pub fn is_quiet(&self) -> bool {
+        !self.is_capture() && self.get_promotion_piece().is_none()
+    }
+
+    // The piece a pawn turns into, None if the move isn't a promotion
+    pub fn get_promotion_piece(&self) -> Option<Piece> {
+        match self.get_flags() {
+            Move::KNIGHT_PROMOTION | Move::KNIGHT_PROMO_CAPTURE => Some(Piece::Knight),
+            Move::BISHOP_PROMOTION | Move::BISHOP_PROMO_CAPTURE => Some(Piece::Bishop),
+            Move::ROOK_PROMOTION | Move::ROOK_PROMO_CAPTURE => Some(Piece::Rook),
+            Move::QUEEN_PROMOTION | Move::QUEEN_PROMO_CAPTURE => Some(Piece::Queen),
+            _ => None,
+        }
+    }
 }
 
 impl  fmt::Display for Move {
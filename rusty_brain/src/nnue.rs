@@ -1,90 +1,859 @@
-const HIDDEN_SIZE: usize = 128;
-const SCALE: i32 = 400;
-const QA: i16 = 255;
-const QB: i16 = 64;
-
-/*
-This is how you would load the network in rust.
-Commented out because it will error if it can't find the file.
-*/
-pub static NNUE: Network =
-    unsafe { std::mem::transmute(*include_bytes!("../../nnue_models/simple_with_more_data-40/quantised.bin")) };
-
-#[inline]
-/// Clipped ReLU - Activation Function.
-/// Note that this takes the i16s in the accumulator to i32s.
-fn crelu(x: i16) -> i32 {
-    i32::from(x).clamp(0, i32::from(QA))
-}
-
-/// This is the quantised format that bullet outputs.
-#[repr(C)]
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use crate::board::{Board, Turn};
+use crate::evaluator::Evaluator;
+use crate::movement::Move;
+use crate::piece::Piece;
+use crate::simd;
+
+/// Piece-square inputs of one king bucket, 6 pieces of 2 colours on 64 squares.
+const INPUT_SIZE: usize = 768;
+
+/// The network compiled into the engine, used unless the `EvalFile` option loads another one.
+pub static NNUE: LazyLock<Arc<Network>> = LazyLock::new(|| {
+    let bytes = include_bytes!("../../nnue_models/simple_with_more_data-40/quantised.bin");
+    Arc::from(Network::from_bytes(bytes).expect("the embedded network doesn't match the default architecture"))
+});
+
+/// Files may start with a header describing the architecture they were trained for.
+/// Nets straight out of bullet have none and are read with the default architecture.
+const HEADER_MAGIC: &[u8; 4] = b"RBNN";
+const HEADER_VERSION: u32 = 2;
+/// Magic, version, input size and hidden size, all a version 1 header has.
+const HEADER_V1_SIZE: usize = 16;
+/// Version 2 adds the output bucket count, flags, scale, quantisation and the king bucket table.
+const HEADER_SIZE: usize = 100;
+const FLAG_MIRRORED: u32 = 1;
+const FLAG_SCRELU: u32 = 2;
+/// Bullet pads its output to a multiple of this many bytes.
+const PADDING_ALIGNMENT: usize = 64;
+/// Bullet's material output buckets split the 2 to 32 pieces on the board into at most 32 buckets.
+const MAX_OUTPUT_BUCKETS: usize = 32;
+/// Networks are trained with an eval scale of a few hundred, anything past this is a broken header
+/// and would overflow the output of the network.
+const MAX_SCALE: i32 = 1024;
+
+/// Why a network file couldn't be loaded.
+#[derive(Debug)]
+pub enum NnueError {
+    Io(io::Error),
+    /// The file doesn't have as many weights as its architecture needs.
+    WrongSize { expected: usize, found: usize },
+    UnsupportedVersion(u32),
+    /// The header describes an architecture the engine can't evaluate.
+    InvalidArchitecture(String),
+}
+
+impl fmt::Display for NnueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NnueError::Io(error) => write!(f, "{}", error),
+            NnueError::WrongSize { expected, found } => {
+                write!(f, "expected {} bytes of weights, found {}", expected, found)
+            }
+            NnueError::UnsupportedVersion(version) => write!(f, "unsupported network file version {}", version),
+            NnueError::InvalidArchitecture(reason) => write!(f, "invalid architecture: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for NnueError {}
+
+impl From<io::Error> for NnueError {
+    fn from(error: io::Error) -> Self {
+        NnueError::Io(error)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Activation applied to the accumulators before the output layer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    /// Clipped ReLU, `clamp(x, 0, QA)`.
+    CReLU,
+    /// Squared clipped ReLU, `clamp(x, 0, QA)^2`.
+    SCReLU,
+}
+
+/// The shape of a network and how it was quantised, read from the file header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Architecture {
+    pub hidden_size: usize,
+    /// Input bucket of every king square, seen from the side the accumulator belongs to.
+    /// Each bucket has its own 768 input weights.
+    pub king_buckets: [u8; 64],
+    /// Flip the board horizontally when the king stands on the e to h files,
+    /// so the buckets only have to be trained for half of the board.
+    pub mirrored: bool,
+    /// Output weights picked by the number of pieces on the board.
+    pub output_buckets: usize,
+    pub activation: Activation,
+    pub scale: i32,
+    pub qa: i32,
+    pub qb: i32,
+}
+
+impl Default for Architecture {
+    /// The architecture of the embedded net: `768 -> 128 -> 1` with a clipped ReLU.
+    fn default() -> Self {
+        Architecture {
+            hidden_size: 128,
+            king_buckets: [0; 64],
+            mirrored: false,
+            output_buckets: 1,
+            activation: Activation::CReLU,
+            scale: 400,
+            qa: 255,
+            qb: 64,
+        }
+    }
+}
+
+impl Architecture {
+    pub fn input_buckets(&self) -> usize {
+        self.king_buckets.iter().max().map_or(1, |&bucket| bucket as usize + 1)
+    }
+
+    /// Number of `i16` values in the quantised weights, in the order of the `Network` fields.
+    fn parameter_count(&self) -> usize {
+        let hidden = self.hidden_size;
+        self.input_buckets() * INPUT_SIZE * hidden + hidden + self.output_buckets * (2 * hidden + 1)
+    }
+
+    /// The header the engine expects in front of the weights of a network with this architecture.
+    pub fn header(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.mirrored {
+            flags |= FLAG_MIRRORED;
+        }
+        if self.activation == Activation::SCReLU {
+            flags |= FLAG_SCRELU;
+        }
+
+        let mut header = HEADER_MAGIC.to_vec();
+        let fields = [
+            HEADER_VERSION,
+            INPUT_SIZE as u32,
+            self.hidden_size as u32,
+            self.output_buckets as u32,
+            flags,
+            self.scale as u32,
+            self.qa as u32,
+            self.qb as u32,
+        ];
+        for field in fields {
+            header.extend(field.to_le_bytes());
+        }
+        header.extend(self.king_buckets);
+        header
+    }
+
+    /// Reads the header at the start of `bytes`, returning the architecture and the header length.
+    fn from_header(bytes: &[u8]) -> Result<(Architecture, usize), NnueError> {
+        let too_short = |header_size| NnueError::WrongSize { expected: header_size, found: bytes.len() };
+        if bytes.len() < HEADER_V1_SIZE {
+            return Err(too_short(HEADER_V1_SIZE));
+        }
+        let version = read_u32(bytes, 4);
+        let input_size = read_u32(bytes, 8) as usize;
+        let mut architecture = Architecture {
+            hidden_size: read_u32(bytes, 12) as usize,
+            ..Architecture::default()
+        };
+        let header_size = match version {
+            1 => HEADER_V1_SIZE,
+            HEADER_VERSION => {
+                if bytes.len() < HEADER_SIZE {
+                    return Err(too_short(HEADER_SIZE));
+                }
+                let flags = read_u32(bytes, 20);
+                architecture.output_buckets = read_u32(bytes, 16) as usize;
+                architecture.mirrored = flags & FLAG_MIRRORED != 0;
+                architecture.activation = if flags & FLAG_SCRELU != 0 { Activation::SCReLU } else { Activation::CReLU };
+                architecture.scale = read_u32(bytes, 24) as i32;
+                architecture.qa = read_u32(bytes, 28) as i32;
+                architecture.qb = read_u32(bytes, 32) as i32;
+                architecture.king_buckets.copy_from_slice(&bytes[36..HEADER_SIZE]);
+                HEADER_SIZE
+            }
+            _ => return Err(NnueError::UnsupportedVersion(version)),
+        };
+
+        if input_size != INPUT_SIZE {
+            return Err(NnueError::InvalidArchitecture(format!("{} inputs per king bucket instead of {}", input_size, INPUT_SIZE)));
+        }
+        architecture.validate()?;
+        Ok((architecture, header_size))
+    }
+
+    fn validate(&self) -> Result<(), NnueError> {
+        let invalid = |reason: String| Err(NnueError::InvalidArchitecture(reason));
+        if self.hidden_size == 0 {
+            return invalid("empty hidden layer".to_string());
+        }
+        if !(1..=MAX_OUTPUT_BUCKETS).contains(&self.output_buckets) {
+            return invalid(format!("{} output buckets, expected 1 to {}", self.output_buckets, MAX_OUTPUT_BUCKETS));
+        }
+        if self.qa <= 0 || self.qb <= 0 || self.qa > i16::MAX as i32 || self.qa.checked_mul(self.qb).is_none() {
+            return invalid(format!("quantisation {} and {}", self.qa, self.qb));
+        }
+        if !(1..=MAX_SCALE).contains(&self.scale) {
+            return invalid(format!("eval scale {}, expected 1 to {}", self.scale, MAX_SCALE));
+        }
+        if self.mirrored && (0..64).any(|square| self.king_buckets[square] != self.king_buckets[square ^ 7]) {
+            return invalid("mirrored king buckets that differ between the two halves of the board".to_string());
+        }
+        Ok(())
+    }
+
+    /// Squares as the accumulator of `perspective` sees them: black's perspective flips the board
+    /// vertically, so every accumulator sees its own side moving up the board.
+    fn relative(square: u8, perspective: Turn) -> u8 {
+        match perspective {
+            Turn::White => square,
+            Turn::Black => square ^ 56,
+        }
+    }
+
+    fn flips(&self, relative_king_square: u8) -> bool {
+        self.mirrored && relative_king_square % 8 >= 4
+    }
+
+    /// What a king move has to keep the same for the accumulator of its side to be updated
+    /// incrementally, otherwise every input changes and the accumulator is built again.
+    fn king_layout(&self, perspective: Turn, king_square: u8) -> (u8, bool) {
+        let king_square = Architecture::relative(king_square, perspective);
+        (self.king_buckets[king_square as usize], self.flips(king_square))
+    }
+
+    /// Index of a piece in the inputs of `perspective`, whose king stands on `king_square`.
+    /// The colours are swapped for black so the network always sees its own pieces as
+    /// the "white" ones, and the bucket of the king picks the block of 768 inputs.
+    pub fn feature_index(&self, piece: Piece, color: Turn, square: u8, perspective: Turn, king_square: u8) -> usize {
+        let color = match perspective {
+            Turn::White => color,
+            Turn::Black => color.opposite(),
+        };
+        let mut square = Architecture::relative(square, perspective);
+        let king_square = Architecture::relative(king_square, perspective);
+        if self.flips(king_square) {
+            square ^= 7;
+        }
+
+        let bucket_base = self.king_buckets[king_square as usize] as usize * INPUT_SIZE;
+        let color_base = match color {
+            Turn::White => 0,
+            Turn::Black => 384,
+        };
+        let piece_base = match piece {
+            Piece::Pawn => 0,
+            Piece::Knight => 64,
+            Piece::Bishop => 128,
+            Piece::Rook => 192,
+            Piece::Queen => 256,
+            Piece::King => 320,
+        };
+
+        bucket_base + color_base + piece_base + square as usize
+    }
+
+    /// Bullet's material output buckets: the pieces on the board beyond the two kings,
+    /// in groups of `32 / output_buckets`.
+    pub fn output_bucket(&self, board: &Board) -> usize {
+        let pieces = (!board.bitboards.get_empty_squares()).count_ones() as usize;
+        let divisor = MAX_OUTPUT_BUCKETS.div_ceil(self.output_buckets);
+        (pieces.saturating_sub(2) / divisor).min(self.output_buckets - 1)
+    }
+}
+
+/// A quantised network in the format bullet outputs.
 pub struct Network {
-    /// Column-Major `HIDDEN_SIZE x 768` matrix.
-    feature_weights: [Accumulator; 768],
-    /// Vector with dimension `HIDDEN_SIZE`.
-    feature_bias: Accumulator,
-    /// Column-Major `1 x (2 * HIDDEN_SIZE)`
-    /// matrix, we use it like this to make the
-    /// code nicer in `Network::evaluate`.
-    output_weights: [i16; 2 * HIDDEN_SIZE],
-    /// Scalar output bias.
-    output_bias: i16,
+    architecture: Architecture,
+    /// Column-Major `hidden_size x (768 * input_buckets)` matrix.
+    feature_weights: Vec<i16>,
+    /// Vector with dimension `hidden_size`.
+    feature_bias: Vec<i16>,
+    /// `2 * hidden_size` weights for each output bucket, the side to
+    /// move's half first, as `Network::evaluate` uses them.
+    output_weights: Vec<i16>,
+    /// Output bias of each output bucket.
+    output_bias: Vec<i16>,
 }
 
 impl Network {
+    /// Reads a quantised network from a file, see `Network::from_bytes`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Box<Network>, NnueError> {
+        Network::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Builds a network from the bytes of a quantised net, either as bullet writes it,
+    /// padded to a multiple of 64 bytes, or behind a header describing its architecture.
+    /// Without a header the net has to have the default architecture.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Box<Network>, NnueError> {
+        let (architecture, header_size) = if bytes.starts_with(HEADER_MAGIC) {
+            Architecture::from_header(bytes)?
+        } else {
+            (Architecture::default(), 0)
+        };
+        let weights = &bytes[header_size..];
+
+        let expected = architecture.parameter_count() * 2;
+        let padded = expected.div_ceil(PADDING_ALIGNMENT) * PADDING_ALIGNMENT;
+        if weights.len() != expected && weights.len() != padded {
+            return Err(NnueError::WrongSize { expected, found: weights.len() });
+        }
+
+        let mut values = weights[..expected].chunks_exact(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        let hidden = architecture.hidden_size;
+        let mut take = |count: usize| values.by_ref().take(count).collect::<Vec<i16>>();
+        let feature_weights = take(architecture.input_buckets() * INPUT_SIZE * hidden);
+        let feature_bias = take(hidden);
+        let output_weights = take(architecture.output_buckets * 2 * hidden);
+        let output_bias = take(architecture.output_buckets);
+
+        Ok(Box::new(Network {
+            architecture,
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+        }))
+    }
+
+    pub fn architecture(&self) -> &Architecture {
+        &self.architecture
+    }
+
+    fn feature_column(&self, feature_idx: usize) -> &[i16] {
+        let hidden = self.architecture.hidden_size;
+        &self.feature_weights[feature_idx * hidden..(feature_idx + 1) * hidden]
+    }
+
     /// Calculates the output of the network, starting from the already
     /// calculated hidden layer (done efficiently during makemoves).
-    pub fn evaluate(&self, us: &Accumulator, them: &Accumulator) -> i32 {
-        // Initialise output with bias.
-        let mut output = i32::from(self.output_bias);
+    pub fn evaluate(&self, us: &Accumulator, them: &Accumulator, output_bucket: usize) -> i32 {
+        let architecture = &self.architecture;
+        let hidden = architecture.hidden_size;
+        let weights = &self.output_weights[output_bucket * 2 * hidden..(output_bucket + 1) * 2 * hidden];
+        let qa = architecture.qa as i16;
 
-        // Side-To-Move Accumulator -> Output.
-        for (&input, &weight) in us.vals.iter().zip(&self.output_weights[..HIDDEN_SIZE]) {
-            output += crelu(input) * i32::from(weight);
-        }
+        // Side-To-Move Accumulator -> Output, then Not-Side-To-Move Accumulator -> Output.
+        let (us_weights, them_weights) = weights.split_at(hidden);
+        let mut output = match architecture.activation {
+            Activation::CReLU => simd::crelu_dot(&us.vals, us_weights, qa) + simd::crelu_dot(&them.vals, them_weights, qa),
+            // The squares are one QA too large, take it back out before adding the bias.
+            // Multiplying by the weight before the second factor keeps the sum in an i32.
+            Activation::SCReLU => {
+                (simd::screlu_dot(&us.vals, us_weights, qa) + simd::screlu_dot(&them.vals, them_weights, qa)) / i32::from(qa)
+            }
+        };
 
-        // Not-Side-To-Move Accumulator -> Output.
-        for (&input, &weight) in them.vals.iter().zip(&self.output_weights[HIDDEN_SIZE..]) {
-            output += crelu(input) * i32::from(weight);
-        }
+        // Add the bias of the bucket.
+        output += i32::from(self.output_bias[output_bucket]);
 
         // Apply eval scale.
-        output *= SCALE;
+        output *= architecture.scale;
 
         // Remove quantisation.
-        output /= i32::from(QA) * i32::from(QB);
+        output /= architecture.qa * architecture.qb;
 
         output
     }
 }
 
-/// A column of the feature-weights matrix.
-/// Note the `align(64)`.
-#[derive(Clone, Copy)]
-#[repr(C, align(64))]
+/// The hidden layer of one perspective, `hidden_size` sums of input weights.
+#[derive(Clone)]
 pub struct Accumulator {
-    vals: [i16; HIDDEN_SIZE],
+    vals: Vec<i16>,
 }
 
 impl Accumulator {
     /// Initialised with bias so we can just efficiently
     /// operate on it afterwards.
     pub fn new(net: &Network) -> Self {
-        net.feature_bias
+        Accumulator { vals: net.feature_bias.clone() }
     }
 
     /// Add a feature to an accumulator.
     pub fn add_feature(&mut self, feature_idx: usize, net: &Network) {
-        for (i, d) in self.vals.iter_mut().zip(&net.feature_weights[feature_idx].vals) {
-            *i += *d
-        }
+        simd::add(&mut self.vals, net.feature_column(feature_idx));
     }
 
     /// Remove a feature from an accumulator.
     pub fn remove_feature(&mut self, feature_idx: usize, net: &Network) {
-        for (i, d) in self.vals.iter_mut().zip(&net.feature_weights[feature_idx].vals) {
-            *i -= *d
+        simd::sub(&mut self.vals, net.feature_column(feature_idx));
+    }
+
+    /// `previous` with one feature swapped for another, the update of a quiet move.
+    pub fn sub_add(&mut self, previous: &Accumulator, removed: usize, added: usize, net: &Network) {
+        simd::sub_add(&mut self.vals, &previous.vals, net.feature_column(removed), net.feature_column(added));
+    }
+
+    /// `previous` with two features swapped for one, the update of a capture.
+    pub fn sub_sub_add(&mut self, previous: &Accumulator, removed: [usize; 2], added: usize, net: &Network) {
+        let [removed, also_removed] = removed.map(|feature_idx| net.feature_column(feature_idx));
+        simd::sub_sub_add(&mut self.vals, &previous.vals, removed, also_removed, net.feature_column(added));
+    }
+
+    /// Starts over from the bias and adds every piece of the board, as seen
+    /// by `perspective` with its king on `king_square`.
+    fn refresh(&mut self, board: &Board, perspective: Turn, king_square: u8, net: &Network) {
+        self.vals.copy_from_slice(&net.feature_bias);
+        for square in 0..64 {
+            if let Some((piece, color)) = board.piece_at(square) {
+                self.add_feature(net.architecture.feature_index(piece, color, square, perspective, king_square), net);
+            }
+        }
+    }
+
+    /// Updates the accumulator of the position before a move to the position after it,
+    /// with the king of `perspective` on `king_square` once the move is made.
+    fn update(&mut self, previous: &Accumulator, dirty: &DirtyPieces, perspective: Turn, king_square: u8, net: &Network) {
+        let index = |&(piece, color, square): &(Piece, Turn, u8)| {
+            net.architecture.feature_index(piece, color, square, perspective, king_square)
+        };
+        match (dirty.removed, dirty.added) {
+            ([Some(removed), Some(also_removed)], [Some(added), None]) => {
+                self.sub_sub_add(previous, [index(&removed), index(&also_removed)], index(&added), net);
+            }
+            // castling moves a second piece, which is updated in place
+            ([Some(removed), other_removed], [Some(added), other_added]) => {
+                self.sub_add(previous, index(&removed), index(&added), net);
+                other_removed.iter().for_each(|piece| self.remove_feature(index(piece), net));
+                other_added.iter().for_each(|piece| self.add_feature(index(piece), net));
+            }
+            _ => unreachable!("every move takes a piece off its square and puts one on another"),
+        }
+    }
+}
+
+/// The pieces a move takes off and puts on the board, as piece, colour and square.
+/// The moving piece is always the first one of each side.
+#[derive(Clone, Copy, Default)]
+struct DirtyPieces {
+    removed: [Option<(Piece, Turn, u8)>; 2],
+    added: [Option<(Piece, Turn, u8)>; 2],
+}
+
+impl DirtyPieces {
+    /// The pieces `move_to_make` changes, `board` being the position before it.
+    fn new(board: &Board, move_to_make: Move) -> Self {
+        let from = move_to_make.get_from();
+        let to = move_to_make.get_to();
+        let flag = move_to_make.get_flags();
+        let (piece, color) = board.piece_at(from).expect("no piece on the move's start square");
+        let mut dirty = DirtyPieces {
+            removed: [Some((piece, color, from)), None],
+            added: [Some((move_to_make.get_promotion_piece().unwrap_or(piece), color, to)), None],
+        };
+
+        // the en passant pawn stands behind the destination square
+        if flag == Move::EP_CAPTURE {
+            let captured_square = match color {
+                Turn::White => to - 8,
+                Turn::Black => to + 8,
+            };
+            dirty.removed[1] = Some((Piece::Pawn, color.opposite(), captured_square));
+        } else if let Some((captured_piece, captured_color)) = board.piece_at(to) {
+            dirty.removed[1] = Some((captured_piece, captured_color, to));
+        }
+
+        let rook_squares = match flag {
+            Move::KING_CASTLE => Some((from + 3, from + 1)),
+            Move::QUEEN_CASTLE => Some((from - 4, from - 1)),
+            _ => None,
+        };
+        if let Some((rook_from, rook_to)) = rook_squares {
+            dirty.removed[1] = Some((Piece::Rook, color, rook_from));
+            dirty.added[1] = Some((Piece::Rook, color, rook_to));
         }
+        dirty
+    }
+}
+
+/// Square of the king of `color`, boards set up without one use a1.
+fn king_square(board: &Board, color: Turn) -> u8 {
+    let king = match color {
+        Turn::White => board.bitboards.white_king,
+        Turn::Black => board.bitboards.black_king,
+    };
+    king.trailing_zeros() as u8 & 63
+}
+
+/// The accumulator of one perspective at one ply of the stack.
+#[derive(Clone)]
+struct StackSide {
+    accumulator: Accumulator,
+    /// Where the king of the perspective stands in the position of this ply.
+    king_square: u8,
+    /// Whether `accumulator` holds the position of this ply yet.
+    computed: bool,
+    /// The move into this ply put the king in another bucket or half of the board, so
+    /// the accumulator can't be updated from the previous one and has to be refreshed.
+    needs_refresh: bool,
+}
+
+/// One ply of the stack, the accumulators of both perspectives and the move that led to it.
+#[derive(Clone)]
+struct StackEntry {
+    white: StackSide,
+    black: StackSide,
+    /// Pieces changed by the move into this ply, unused on the root.
+    dirty: DirtyPieces,
+}
+
+impl StackEntry {
+    fn new(net: &Network, dirty: DirtyPieces) -> Self {
+        let side = StackSide {
+            accumulator: Accumulator::new(net),
+            king_square: 0,
+            computed: false,
+            needs_refresh: false,
+        };
+        StackEntry { white: side.clone(), black: side, dirty }
+    }
+
+    fn get(&self, perspective: Turn) -> &StackSide {
+        match perspective {
+            Turn::White => &self.white,
+            Turn::Black => &self.black,
+        }
+    }
+
+    fn get_mut(&mut self, perspective: Turn) -> &mut StackSide {
+        match perspective {
+            Turn::White => &mut self.white,
+            Turn::Black => &mut self.black,
+        }
+    }
+}
+
+/// Evaluates positions with a quantised network, keeping one stack
+/// entry per ply of the line the search is currently on.
+/// The entry of a position sits at its `Board::accumulator_index`, counted
+/// from the position the evaluator was reset on, so a null move shares the
+/// entry of the position it passes in.
+///
+/// Making a move only records the pieces it changes. The accumulators are
+/// brought up to date when a position is evaluated, so nodes pruned before
+/// their evaluation never pay for them.
+pub struct NnueEvaluator {
+    net: Arc<Network>,
+    /// Entries past `len` belong to lines the search has left, they are
+    /// kept so their buffers can be reused by the next moves.
+    stack: Vec<StackEntry>,
+    len: usize,
+    root_index: usize,
+}
+
+impl NnueEvaluator {
+    pub fn new(net: Arc<Network>) -> Self {
+        NnueEvaluator {
+            net,
+            stack: Vec::new(),
+            len: 0,
+            root_index: 0,
+        }
+    }
+
+    /// Position in the stack of the entry of a board.
+    fn stack_index(&self, board: &Board) -> usize {
+        board.accumulator_index - self.root_index
+    }
+
+    /// Brings the accumulator of `perspective` at `index` up to date, either by applying the
+    /// moves since the last computed ply or, when that takes more column updates than adding
+    /// every piece of `board` (the position at `index`) to the bias, by refreshing.
+    fn compute(&mut self, board: &Board, index: usize, perspective: Turn) {
+        let mut start = index;
+        let mut updates = 0;
+        while !self.stack[start].get(perspective).computed && !self.stack[start].get(perspective).needs_refresh {
+            let dirty = &self.stack[start].dirty;
+            updates += dirty.removed.iter().chain(&dirty.added).flatten().count();
+            start -= 1;
+        }
+
+        let net = &self.net;
+        let pieces = (!board.bitboards.get_empty_squares()).count_ones() as usize;
+        if !self.stack[start].get(perspective).computed || updates >= pieces {
+            let side = self.stack[index].get_mut(perspective);
+            side.accumulator.refresh(board, perspective, side.king_square, net);
+            side.computed = true;
+            return;
+        }
+
+        // every ply on the way is kept, the siblings of the moves after it start from there
+        for ply in start + 1..=index {
+            let (previous, next) = self.stack.split_at_mut(ply);
+            let (previous, next) = (previous[ply - 1].get(perspective), &mut next[0]);
+            let dirty = next.dirty;
+            let side = next.get_mut(perspective);
+            side.accumulator.update(&previous.accumulator, &dirty, perspective, side.king_square, net);
+            side.computed = true;
+        }
+    }
+}
+
+impl Evaluator for NnueEvaluator {
+    fn name(&self) -> &'static str {
+        "NNUE"
+    }
+
+    fn new_instance(&self) -> Box<dyn Evaluator> {
+        Box::new(NnueEvaluator::new(Arc::clone(&self.net)))
+    }
+
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        let index = self.stack_index(board);
+        assert!(index < self.len, "reset must be called before evaluate");
+        for perspective in [Turn::White, Turn::Black] {
+            if !self.stack[index].get(perspective).computed {
+                self.compute(board, index, perspective);
+            }
+        }
+
+        let entry = &self.stack[index];
+        let us = board.turn;
+        let output_bucket = self.net.architecture.output_bucket(board);
+        self.net.evaluate(&entry.get(us).accumulator, &entry.get(us.opposite()).accumulator, output_bucket)
+    }
+
+    fn reset(&mut self, board: &Board) {
+        self.root_index = board.accumulator_index;
+        if self.stack.is_empty() {
+            self.stack.push(StackEntry::new(&self.net, DirtyPieces::default()));
+        }
+        for perspective in [Turn::White, Turn::Black] {
+            let king_square = king_square(board, perspective);
+            let side = self.stack[0].get_mut(perspective);
+            side.accumulator.refresh(board, perspective, king_square, &self.net);
+            side.king_square = king_square;
+            side.computed = true;
+            side.needs_refresh = false;
+        }
+        self.len = 1;
+    }
+
+    fn on_make(&mut self, board: &Board, move_to_make: Move) {
+        let index = self.stack_index(board);
+        assert!(index < self.len, "reset must be called before on_make");
+        let dirty = DirtyPieces::new(board, move_to_make);
+        if self.stack.len() == index + 1 {
+            self.stack.push(StackEntry::new(&self.net, dirty));
+        }
+        let (previous, next) = self.stack.split_at_mut(index + 1);
+        let (previous, next) = (&previous[index], &mut next[0]);
+        next.dirty = dirty;
+
+        let (moving_piece, moving_color, _) = dirty.removed[0].expect("every move takes a piece off its square");
+        for perspective in [Turn::White, Turn::Black] {
+            let king_before = previous.get(perspective).king_square;
+            let king_after = match (moving_piece, moving_color) {
+                (Piece::King, color) if color == perspective => move_to_make.get_to(),
+                _ => king_before,
+            };
+
+            let architecture = &self.net.architecture;
+            let side = next.get_mut(perspective);
+            side.king_square = king_after;
+            side.computed = false;
+            side.needs_refresh = architecture.king_layout(perspective, king_before) != architecture.king_layout(perspective, king_after);
+        }
+
+        self.len = index + 2;
+    }
+
+    fn on_undo(&mut self, board: &Board) {
+        self.len = self.stack_index(board) + 1;
+    }
+}
+
+#[cfg(test)]
+mod nnue {
+    use std::sync::Arc;
+    use crate::board::Board;
+    use crate::evaluator::Evaluator;
+
+    use super::{Activation, Architecture, Network, NnueError, NnueEvaluator, HEADER_MAGIC, INPUT_SIZE, MAX_SCALE, NNUE};
+
+    const EMBEDDED: &[u8] = include_bytes!("../../nnue_models/simple_with_more_data-40/quantised.bin");
+
+    const FENS: [&str; 5] = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 0",
+        "8/8/3k4/8/4K3/8/8/8 w - - 0 1",
+    ];
+
+    // walks every line to the given depth and compares the incrementally updated evaluation
+    // with one built from scratch, after every move or only at the end of the lines
+    fn check_incremental(board: &mut Board, evaluator: &mut NnueEvaluator, net: &Arc<Network>, depth: i32, every_ply: bool) {
+        if depth == 0 {
+            return;
+        }
+        for current_move in board.generate_legal_moves() {
+            evaluator.on_make(board, current_move);
+            board.make_move(current_move);
+
+            if every_ply || depth == 1 {
+                let mut fresh = NnueEvaluator::new(Arc::clone(net));
+                fresh.reset(board);
+                assert_eq!(evaluator.evaluate(board), fresh.evaluate(board), "after {}", current_move);
+            }
+            check_incremental(board, evaluator, net, depth - 1, every_ply);
+
+            board.undo_move();
+            evaluator.on_undo(board);
+        }
+    }
+
+    fn evaluate_with(net: Box<Network>, fen: &str) -> i32 {
+        let board = Board::from_fen(fen.to_string());
+        let mut evaluator = NnueEvaluator::new(Arc::from(net));
+        evaluator.reset(&board);
+        evaluator.evaluate(&board)
+    }
+
+    // a made up net with every feature of the architecture, filled with small pseudo random weights
+    fn bucketed_network() -> Vec<u8> {
+        let mut king_buckets = [0; 64];
+        for (square, bucket) in king_buckets.iter_mut().enumerate() {
+            *bucket = (square / 16) as u8;
+        }
+        let architecture = Architecture {
+            hidden_size: 32,
+            king_buckets,
+            mirrored: true,
+            output_buckets: 8,
+            activation: Activation::SCReLU,
+            ..Architecture::default()
+        };
+
+        let mut bytes = architecture.header();
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        for _ in 0..architecture.parameter_count() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let weight = (state % 64) as i16 - 32;
+            bytes.extend(weight.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn incremental_updates_match_refresh() {
+        let bucketed = Arc::from(Network::from_bytes(&bucketed_network()).unwrap());
+        for net in [Arc::clone(&NNUE), bucketed] {
+            for fen in FENS {
+                let mut board = Board::from_fen(fen.to_string());
+                let mut evaluator = NnueEvaluator::new(Arc::clone(&net));
+                evaluator.reset(&board);
+                check_incremental(&mut board, &mut evaluator, &net, 2, true);
+            }
+        }
+    }
+
+    // the inner plies are never evaluated, so the accumulators at the end of a line are built
+    // from several moves at once, or refreshed when there are too few pieces for that to pay off
+    #[test]
+    fn lazy_updates_match_refresh() {
+        let bucketed = Arc::from(Network::from_bytes(&bucketed_network()).unwrap());
+        for net in [Arc::clone(&NNUE), bucketed] {
+            for (fen, depth) in FENS.iter().zip([2, 2, 2, 4, 4]) {
+                let mut board = Board::from_fen(fen.to_string());
+                let mut evaluator = NnueEvaluator::new(Arc::clone(&net));
+                evaluator.reset(&board);
+                check_incremental(&mut board, &mut evaluator, &net, depth, false);
+            }
+        }
+    }
+
+    #[test]
+    fn mirrored_positions_evaluate_the_same() {
+        let net = Network::from_bytes(&bucketed_network()).unwrap();
+        let architecture = net.architecture().clone();
+        assert_eq!(architecture.input_buckets(), 4);
+
+        // the same position with the files flipped, each side's king crossing to the other half
+        let net = Arc::from(net);
+        let mut scores = Vec::new();
+        for fen in ["8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", "8/5p2/4p3/r5PK/k1p3R1/8/1P1P4/8 w - - 0 1"] {
+            let board = Board::from_fen(fen.to_string());
+            let mut evaluator = NnueEvaluator::new(Arc::clone(&net));
+            evaluator.reset(&board);
+            scores.push(evaluator.evaluate(&board));
+        }
+        assert_eq!(scores[0], scores[1]);
+    }
+
+    #[test]
+    fn networks_load_with_and_without_header() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0";
+        let expected = evaluate_with(Network::from_bytes(EMBEDDED).unwrap(), fen);
+
+        let unpadded = &EMBEDDED[..Architecture::default().parameter_count() * 2];
+        assert_eq!(evaluate_with(Network::from_bytes(unpadded).unwrap(), fen), expected);
+
+        let mut with_header = Architecture::default().header();
+        with_header.extend_from_slice(EMBEDDED);
+        let path = std::env::temp_dir().join(format!("rusty_brain_nnue_{}.bin", std::process::id()));
+        std::fs::write(&path, &with_header).unwrap();
+        let loaded = Network::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(evaluate_with(loaded.unwrap(), fen), expected);
+
+        // the first header version only had the sizes
+        let mut version_1 = HEADER_MAGIC.to_vec();
+        for value in [1, INPUT_SIZE as u32, 128] {
+            version_1.extend(u32::to_le_bytes(value));
+        }
+        version_1.extend_from_slice(EMBEDDED);
+        assert_eq!(evaluate_with(Network::from_bytes(&version_1).unwrap(), fen), expected);
+    }
+
+    #[test]
+    fn bad_network_files_are_rejected() {
+        let truncated = &EMBEDDED[..EMBEDDED.len() - 100];
+        assert!(matches!(Network::from_bytes(truncated), Err(NnueError::WrongSize { found, .. }) if found == truncated.len()));
+        assert!(matches!(Network::from_bytes(&[]), Err(NnueError::WrongSize { found: 0, .. })));
+
+        let mut newer = Architecture::default().header();
+        newer[4] += 1;
+        newer.extend_from_slice(EMBEDDED);
+        assert!(matches!(Network::from_bytes(&newer), Err(NnueError::UnsupportedVersion(_))));
+
+        // twice the hidden layer needs twice the weights
+        let wider = Architecture { hidden_size: 256, ..Architecture::default() };
+        let mut bytes = wider.header();
+        bytes.extend_from_slice(EMBEDDED);
+        assert!(matches!(Network::from_bytes(&bytes), Err(NnueError::WrongSize { .. })));
+
+        let mut lopsided = Architecture { mirrored: true, ..Architecture::default() };
+        lopsided.king_buckets[7] = 1;
+        let mut bytes = lopsided.header();
+        bytes.extend_from_slice(EMBEDDED);
+        assert!(matches!(Network::from_bytes(&bytes), Err(NnueError::InvalidArchitecture(_))));
+
+        // quantisation and scale that would overflow the output, a negative scale is read from a huge u32
+        let overflowing = [
+            Architecture { qa: i16::MAX as i32, qb: 70_000, ..Architecture::default() },
+            Architecture { scale: 0, ..Architecture::default() },
+            Architecture { scale: -1, ..Architecture::default() },
+            Architecture { scale: MAX_SCALE + 1, ..Architecture::default() },
+        ];
+        for architecture in overflowing {
+            let mut bytes = architecture.header();
+            bytes.extend_from_slice(EMBEDDED);
+            assert!(matches!(Network::from_bytes(&bytes), Err(NnueError::InvalidArchitecture(_))), "{:?}", architecture);
+        }
+
+        assert!(matches!(Network::load("no/such/network.bin"), Err(NnueError::Io(_))));
     }
 }
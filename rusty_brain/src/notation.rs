@@ -0,0 +1,323 @@
+use std::fmt;
+use crate::board::Board;
+use crate::movegen::MoveList;
+use crate::movement::Move;
+use crate::piece::Piece;
+use crate::square::Square;
+
+// Why a move written as text couldn't be turned into a move of the position
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoveParseError {
+    Malformed(String),
+    Illegal(String),
+    // SAN without enough of the start square to tell the moves apart
+    Ambiguous(String),
+}
+
+impl fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveParseError::Malformed(text) => write!(f, "\"{}\" isn't a move", text),
+            MoveParseError::Illegal(text) => write!(f, "\"{}\" isn't legal in this position", text),
+            MoveParseError::Ambiguous(text) => write!(f, "\"{}\" fits more than one legal move", text),
+        }
+    }
+}
+
+impl std::error::Error for MoveParseError {}
+
+fn square_name(square: u8) -> String {
+    Square::from(square).to_string().to_lowercase()
+}
+
+impl Board {
+    // Coordinate notation as UCI sends it, e2e4 or e7e8q
+    pub fn parse_uci_move(&self, text: &str) -> Result<Move, MoveParseError> {
+        let malformed = || MoveParseError::Malformed(text.to_string());
+        if !text.is_ascii() || !(4..=5).contains(&text.len()) {
+            return Err(malformed());
+        }
+        let from = Square::parse(&text[0..2]).ok_or_else(malformed)? as u8;
+        let to = Square::parse(&text[2..4]).ok_or_else(malformed)? as u8;
+        let promotion = match text[4..].chars().next() {
+            None => None,
+            Some(letter) => match Piece::from_char(letter) {
+                Some(piece @ (Piece::Knight | Piece::Bishop | Piece::Rook | Piece::Queen)) => Some(piece),
+                _ => return Err(malformed()),
+            },
+        };
+
+        let mut moves = MoveList::new();
+        self.generate_legal(&mut moves);
+        moves.iter()
+            .find(|legal| legal.get_from() == from && legal.get_to() == to && legal.get_promotion_piece() == promotion)
+            .copied()
+            .ok_or_else(|| MoveParseError::Illegal(text.to_string()))
+    }
+
+    // Standard algebraic notation, reads SAN as well as long algebraic notation like Ng1-f3 or e7xd8=Q.
+    // Check and annotation suffixes are skipped, castling may be written with zeros
+    pub fn parse_san(&self, san: &str) -> Result<Move, MoveParseError> {
+        let malformed = || MoveParseError::Malformed(san.to_string());
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        if !text.is_ascii() || text.is_empty() {
+            return Err(malformed());
+        }
+
+        let mut moves = MoveList::new();
+        self.generate_legal(&mut moves);
+
+        let castling = match text {
+            "O-O" | "0-0" => Some(Move::KING_CASTLE),
+            "O-O-O" | "0-0-0" => Some(Move::QUEEN_CASTLE),
+            _ => None,
+        };
+        if let Some(flag) = castling {
+            return moves.iter()
+                .find(|legal| legal.get_flags() == flag)
+                .copied()
+                .ok_or_else(|| MoveParseError::Illegal(san.to_string()));
+        }
+
+        // pieces are uppercase, a lowercase b is the b file of a pawn move
+        let (piece, text) = match text.chars().next() {
+            Some(letter) if letter.is_ascii_uppercase() => (Piece::from_char(letter).ok_or_else(malformed)?, &text[1..]),
+            _ => (Piece::Pawn, text),
+        };
+
+        let (text, promotion) = match text.char_indices().last() {
+            Some((index, letter)) if letter.is_ascii_uppercase() => {
+                let promotion = Piece::from_char(letter).filter(|piece| !matches!(piece, Piece::Pawn | Piece::King)).ok_or_else(malformed)?;
+                (text[..index].strip_suffix('=').unwrap_or(&text[..index]), Some(promotion))
+            }
+            _ => (text, None),
+        };
+
+        if text.len() < 2 {
+            return Err(malformed());
+        }
+        let (start, destination) = text.split_at(text.len() - 2);
+        let to = Square::parse(destination).ok_or_else(malformed)? as u8;
+
+        // what is left of the start square, a file, a rank or both, before an x or a -
+        let start = start.strip_suffix(['x', '-']).unwrap_or(start);
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in start.chars() {
+            match c {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some(c as u8 - b'a'),
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c as u8 - b'1'),
+                _ => return Err(malformed()),
+            }
+        }
+
+        let mut candidates = moves.iter().filter(|legal| {
+            let from = legal.get_from();
+            legal.get_to() == to
+                && legal.get_promotion_piece() == promotion
+                && self.piece_at(from).is_some_and(|(moving, _)| moving == piece)
+                && from_file.is_none_or(|file| from % 8 == file)
+                && from_rank.is_none_or(|rank| from / 8 == rank)
+        });
+        match (candidates.next(), candidates.next()) {
+            (Some(found), None) => Ok(*found),
+            (Some(_), Some(_)) => Err(MoveParseError::Ambiguous(san.to_string())),
+            (None, _) => Err(MoveParseError::Illegal(san.to_string())),
+        }
+    }
+
+    // The move in standard algebraic notation, with only as much of the start square
+    // as it takes to tell it apart from the other legal moves, and a + or # after checks
+    pub fn move_to_san(&self, move_to_write: Move) -> String {
+        let from = move_to_write.get_from();
+        let to = move_to_write.get_to();
+        let mut san = match move_to_write.get_flags() {
+            Move::KING_CASTLE => "O-O".to_string(),
+            Move::QUEEN_CASTLE => "O-O-O".to_string(),
+            _ => {
+                let (piece, _) = self.piece_at(from).expect("no piece on the move's start square");
+                let mut san = String::new();
+                if piece == Piece::Pawn {
+                    if move_to_write.is_capture() {
+                        san.push(char::from(b'a' + from % 8));
+                    }
+                } else {
+                    san.push(piece.to_char());
+                    san.push_str(&self.disambiguation(move_to_write, piece));
+                }
+                if move_to_write.is_capture() {
+                    san.push('x');
+                }
+                san.push_str(&square_name(to));
+                if let Some(promotion) = move_to_write.get_promotion_piece() {
+                    san.push('=');
+                    san.push(promotion.to_char());
+                }
+                san
+            }
+        };
+        san.push_str(self.check_suffix(move_to_write));
+        san
+    }
+
+    // Long algebraic notation, the whole start square and a - or an x, like Ng1-f3 or e7xd8=Q+
+    pub fn move_to_lan(&self, move_to_write: Move) -> String {
+        let from = move_to_write.get_from();
+        let mut lan = match move_to_write.get_flags() {
+            Move::KING_CASTLE => "O-O".to_string(),
+            Move::QUEEN_CASTLE => "O-O-O".to_string(),
+            _ => {
+                let (piece, _) = self.piece_at(from).expect("no piece on the move's start square");
+                let mut lan = String::new();
+                if piece != Piece::Pawn {
+                    lan.push(piece.to_char());
+                }
+                lan.push_str(&square_name(from));
+                lan.push(if move_to_write.is_capture() { 'x' } else { '-' });
+                lan.push_str(&square_name(move_to_write.get_to()));
+                if let Some(promotion) = move_to_write.get_promotion_piece() {
+                    lan.push('=');
+                    lan.push(promotion.to_char());
+                }
+                lan
+            }
+        };
+        lan.push_str(self.check_suffix(move_to_write));
+        lan
+    }
+
+    // The file, the rank or the whole start square, whatever is needed when
+    // another piece of the same kind can move to the same square
+    fn disambiguation(&self, move_to_write: Move, piece: Piece) -> String {
+        let from = move_to_write.get_from();
+        let mut moves = MoveList::new();
+        self.generate_legal(&mut moves);
+        let others: Vec<u8> = moves.iter()
+            .filter(|legal| legal.get_to() == move_to_write.get_to() && legal.get_from() != from)
+            .filter(|legal| self.piece_at(legal.get_from()).is_some_and(|(other, _)| other == piece))
+            .map(|legal| legal.get_from())
+            .collect();
+
+        let file = char::from(b'a' + from % 8);
+        let rank = char::from(b'1' + from / 8);
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|other| other % 8 != from % 8) {
+            file.to_string()
+        } else if others.iter().all(|other| other / 8 != from / 8) {
+            rank.to_string()
+        } else {
+            format!("{}{}", file, rank)
+        }
+    }
+
+    fn check_suffix(&self, move_to_write: Move) -> &'static str {
+        let mut board = self.clone();
+        board.make_move(move_to_write);
+        if board.checkers == 0 {
+            ""
+        } else if board.has_legal_move() {
+            "+"
+        } else {
+            "#"
+        }
+    }
+}
+
+#[cfg(test)]
+mod notation {
+    use crate::board::Board;
+    use crate::movegen::MoveList;
+
+    use super::MoveParseError;
+
+    // every legal move has to be read back from what it is written as, in all three notations
+    fn round_trip(board: &mut Board, depth: i32) {
+        let mut moves = MoveList::new();
+        board.generate_legal(&mut moves);
+        for &current_move in &moves {
+            let san = board.move_to_san(current_move);
+            assert_eq!(board.parse_san(&san), Ok(current_move), "{} in {}", san, board.to_fen());
+            let lan = board.move_to_lan(current_move);
+            assert_eq!(board.parse_san(&lan), Ok(current_move), "{} in {}", lan, board.to_fen());
+            assert_eq!(board.parse_uci_move(&current_move.to_string()), Ok(current_move));
+
+            if depth > 1 {
+                board.make_move(current_move);
+                round_trip(board, depth - 1);
+                board.undo_move();
+            }
+        }
+    }
+
+    fn san_moves(fen: &str) -> Vec<String> {
+        let board = Board::from_fen(fen.to_string());
+        let mut moves = MoveList::new();
+        board.generate_legal(&mut moves);
+        moves.iter().map(|&legal| board.move_to_san(legal)).collect()
+    }
+
+    #[test]
+    fn every_move_round_trips() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 0",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+        for fen in fens {
+            round_trip(&mut Board::from_fen(fen.to_string()), 2);
+        }
+    }
+
+    #[test]
+    fn san_is_written_like_pgn() {
+        let queens = san_moves("8/7k/8/8/8/Q7/8/Q1Q4K w - - 0 1");
+        for san in ["Qa1b2", "Q3b2", "Qcb2", "Qab1+"] {
+            assert!(queens.contains(&san.to_string()), "{} missing from {:?}", san, queens);
+        }
+
+        let pawns = san_moves("3r3k/4P3/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        for san in ["exd6", "e6", "exd8=Q+", "e8=N", "e8=R+"] {
+            assert!(pawns.contains(&san.to_string()), "{} missing from {:?}", san, pawns);
+        }
+
+        let rooks = san_moves("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+        for san in ["Ra8+", "O-O-O", "Kd2"] {
+            assert!(rooks.contains(&san.to_string()), "{} missing from {:?}", san, rooks);
+        }
+        assert!(san_moves("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").contains(&"Ra8#".to_string()));
+    }
+
+    #[test]
+    fn other_spellings_are_read() {
+        let board = Board::from_fen("3r3k/4P3/8/3pP3/8/8/8/4K3 w - d6 0 1".to_string());
+        let promotion = board.parse_uci_move("e7d8q").unwrap();
+        assert_eq!(board.parse_san("exd8=Q"), Ok(promotion));
+        assert_eq!(board.parse_san("exd8Q+"), Ok(promotion));
+        assert_eq!(board.parse_san("e7xd8=Q!?"), Ok(promotion));
+
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1".to_string());
+        assert_eq!(board.parse_san("0-0-0"), board.parse_uci_move("e1c1"));
+        assert_eq!(board.parse_san("Ra1-a8+"), board.parse_uci_move("a1a8"));
+    }
+
+    #[test]
+    fn bad_moves_are_rejected() {
+        let board = Board::new();
+        assert_eq!(board.parse_san("Nf6"), Err(MoveParseError::Illegal("Nf6".to_string())));
+        assert_eq!(board.parse_san("O-O"), Err(MoveParseError::Illegal("O-O".to_string())));
+        assert_eq!(board.parse_san("Xe4"), Err(MoveParseError::Malformed("Xe4".to_string())));
+        assert_eq!(board.parse_san("e9"), Err(MoveParseError::Malformed("e9".to_string())));
+        assert_eq!(board.parse_san(""), Err(MoveParseError::Malformed("".to_string())));
+        assert_eq!(board.parse_uci_move("e2e5"), Err(MoveParseError::Illegal("e2e5".to_string())));
+        assert_eq!(board.parse_uci_move("e2"), Err(MoveParseError::Malformed("e2".to_string())));
+        assert_eq!(board.parse_uci_move("e2e4k"), Err(MoveParseError::Malformed("e2e4k".to_string())));
+
+        let queens = Board::from_fen("8/7k/8/8/8/Q7/8/Q1Q4K w - - 0 1".to_string());
+        assert_eq!(queens.parse_san("Qb2"), Err(MoveParseError::Ambiguous("Qb2".to_string())));
+        assert_eq!(queens.parse_san("Qab2"), Err(MoveParseError::Ambiguous("Qab2".to_string())));
+        assert!(queens.parse_san("Qa1b2").is_ok());
+    }
+}
@@ -0,0 +1,698 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+use crate::board::{Board, Turn};
+use crate::fen::FenError;
+use crate::movement::Move;
+use crate::notation::MoveParseError;
+
+// Engine evaluation stored in a [%eval] comment, in centipawns from white's point of view
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Eval {
+    Centipawns(i32),
+    // moves to mate, negative when black mates
+    Mate(i32),
+}
+
+impl Eval {
+    // 0.17, -1.5 or #-3, with an optional search depth after a comma as lichess writes it
+    fn parse(text: &str) -> Option<Eval> {
+        let value = text.split(',').next()?.trim();
+        if let Some(moves) = value.strip_prefix('#') {
+            return moves.parse().ok().map(Eval::Mate);
+        }
+        let pawns: f64 = value.parse().ok()?;
+        Some(Eval::Centipawns((pawns * 100.0).round() as i32))
+    }
+}
+
+impl fmt::Display for Eval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Eval::Centipawns(centipawns) => {
+                let sign = if *centipawns < 0 { "-" } else { "" };
+                write!(f, "{}{}.{:02}", sign, centipawns.abs() / 100, centipawns.abs() % 100)
+            }
+            Eval::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+// h:mm:ss with optional fractions of a second, as in [%clk 0:03:00]
+fn parse_clock(text: &str) -> Option<Duration> {
+    let mut seconds = 0.0;
+    for part in text.split(':') {
+        let value: f64 = part.parse().ok()?;
+        if value < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(Duration::from_millis((seconds * 1000.0).round() as u64))
+}
+
+fn format_clock(clock: Duration) -> String {
+    let seconds = clock.as_secs();
+    let mut text = format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if clock.subsec_millis() != 0 {
+        text.push_str(format!(".{:03}", clock.subsec_millis()).trim_end_matches('0'));
+    }
+    text
+}
+
+// A move of a game with what was written about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameMove {
+    pub played_move: Move,
+    pub san: String,
+    // comment text after the move, without the [%clk] and [%eval] commands
+    pub comment: Option<String>,
+    pub clock: Option<Duration>,
+    pub eval: Option<Eval>,
+    // numeric annotation glyphs, $1 or ! is 1
+    pub nags: Vec<u8>,
+    // lines played instead of this move, each starting from the position before it
+    pub variations: Vec<Vec<GameMove>>,
+}
+
+impl GameMove {
+    // The move as played on the board, the board has to be the position before it
+    pub fn new(board: &Board, played_move: Move) -> Self {
+        GameMove {
+            played_move,
+            san: board.move_to_san(played_move),
+            comment: None,
+            clock: None,
+            eval: None,
+            nags: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+
+    // Takes the [%clk] and [%eval] commands out of a comment and keeps the rest as text
+    fn add_comment(&mut self, text: &str) {
+        let mut remaining = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("[%") {
+            remaining.push_str(&rest[..start]);
+            let Some(length) = rest[start..].find(']') else {
+                break;
+            };
+            let command = &rest[start..start + length + 1];
+            let inner = command[2..command.len() - 1].trim();
+            let (name, value) = inner.split_once(char::is_whitespace).unwrap_or((inner, ""));
+            match name {
+                "clk" if parse_clock(value.trim()).is_some() => self.clock = parse_clock(value.trim()),
+                "eval" if Eval::parse(value).is_some() => self.eval = Eval::parse(value),
+                _ => remaining.push_str(command),
+            }
+            rest = &rest[start + length + 1..];
+        }
+        remaining.push_str(rest);
+
+        let remaining = remaining.split_whitespace().collect::<Vec<_>>().join(" ");
+        if remaining.is_empty() {
+            return;
+        }
+        self.comment = Some(match self.comment.take() {
+            Some(comment) => format!("{} {}", comment, remaining),
+            None => remaining,
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Game {
+    // in the order they were read or set
+    pub tags: Vec<(String, String)>,
+    // comment before the first move
+    pub comment: Option<String>,
+    pub moves: Vec<GameMove>,
+    // 1-0, 0-1, 1/2-1/2 or * for a game that isn't over
+    pub result: String,
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Game {
+            tags: Vec::new(),
+            comment: None,
+            moves: Vec::new(),
+            result: "*".to_string(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old_value)) => *old_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // The position of the FEN tag, the standard starting position without one
+    pub fn starting_board(&self) -> Result<Board, FenError> {
+        match self.tag("FEN") {
+            Some(fen) => Board::try_from_fen(fen),
+            None => Ok(Board::new()),
+        }
+    }
+
+    // The position after the last move of the main line
+    pub fn final_board(&self) -> Result<Board, FenError> {
+        let mut board = self.starting_board()?;
+        for game_move in &self.moves {
+            board.make_move(game_move.played_move);
+        }
+        Ok(board)
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::new()
+    }
+}
+
+#[derive(Debug)]
+pub enum PgnError {
+    Io(io::Error),
+    InvalidTag(String),
+    InvalidFen(FenError),
+    // ply counts from the starting position of the game
+    IllegalMove { ply: usize, san: String, error: MoveParseError },
+    Unterminated(&'static str),
+    UnexpectedToken(String),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Io(error) => write!(f, "{}", error),
+            PgnError::InvalidTag(line) => write!(f, "invalid tag pair {}", line),
+            PgnError::InvalidFen(error) => write!(f, "invalid FEN tag: {}", error),
+            PgnError::IllegalMove { ply, san, error } => write!(f, "move {} at ply {}: {}", san, ply, error),
+            PgnError::Unterminated(what) => write!(f, "{} not closed before the end of the game", what),
+            PgnError::UnexpectedToken(token) => write!(f, "unexpected \"{}\" in the movetext", token),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<io::Error> for PgnError {
+    fn from(error: io::Error) -> Self {
+        PgnError::Io(error)
+    }
+}
+
+// [Name "Value"] with \" and \\ escaped in the value
+fn parse_tag(line: &str) -> Result<(String, String), PgnError> {
+    let invalid = || PgnError::InvalidTag(line.to_string());
+    let inner = line.trim().strip_prefix('[').and_then(|inner| inner.strip_suffix(']')).ok_or_else(invalid)?;
+    let (name, value) = inner.trim().split_once(char::is_whitespace).ok_or_else(invalid)?;
+    let quoted = value.trim().strip_prefix('"').and_then(|value| value.strip_suffix('"')).ok_or_else(invalid)?;
+
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next().ok_or_else(invalid)?),
+            _ => value.push(c),
+        }
+    }
+    Ok((name.to_string(), value))
+}
+
+// Whether a line of movetext ends inside a {} comment, given whether it started in one
+fn ends_in_comment(mut in_comment: bool, line: &str) -> bool {
+    for c in line.chars() {
+        match (in_comment, c) {
+            (true, '}') => in_comment = false,
+            (false, '{') => in_comment = true,
+            (false, ';') => return false,
+            _ => (),
+        }
+    }
+    in_comment
+}
+
+// Suffix annotations written as part of a move, e4!? is e4 with $5
+fn glyph_nag(glyph: &str) -> Option<u8> {
+    match glyph {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+enum Token<'a> {
+    Move(&'a str),
+    Comment(&'a str),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(&'a str),
+}
+
+struct Tokens<'a> {
+    text: &'a str,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<Token<'a>, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.text = self.text.trim_start();
+            let token = match self.text.chars().next()? {
+                '{' => {
+                    let Some(end) = self.text.find('}') else {
+                        return Some(Err(PgnError::Unterminated("comment")));
+                    };
+                    let comment = &self.text[1..end];
+                    self.text = &self.text[end + 1..];
+                    Token::Comment(comment)
+                }
+                ';' => {
+                    let end = self.text.find('\n').unwrap_or(self.text.len());
+                    let comment = &self.text[1..end];
+                    self.text = &self.text[end..];
+                    Token::Comment(comment)
+                }
+                '(' => {
+                    self.text = &self.text[1..];
+                    Token::VariationStart
+                }
+                ')' => {
+                    self.text = &self.text[1..];
+                    Token::VariationEnd
+                }
+                _ => {
+                    let end = self.text.find(|c: char| c.is_whitespace() || "{}();".contains(c)).unwrap_or(self.text.len());
+                    let symbol = &self.text[..end];
+                    self.text = &self.text[end..];
+
+                    if let Some(nag) = symbol.strip_prefix('$') {
+                        match nag.parse() {
+                            Ok(nag) => Token::Nag(nag),
+                            Err(_) => return Some(Err(PgnError::UnexpectedToken(symbol.to_string()))),
+                        }
+                    } else if let Some(nag) = glyph_nag(symbol) {
+                        Token::Nag(nag)
+                    } else if ["1-0", "0-1", "1/2-1/2", "*"].contains(&symbol) {
+                        Token::Result(symbol)
+                    } else if symbol.starts_with(|c: char| c.is_ascii_digit()) && symbol.contains('.') {
+                        // a move number, on its own or glued to the move as in 12.Nf3
+                        let after_number = symbol.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                        if after_number.is_empty() {
+                            continue;
+                        }
+                        Token::Move(after_number)
+                    } else if symbol.chars().all(|c| c.is_ascii_digit()) {
+                        continue;
+                    } else {
+                        Token::Move(symbol)
+                    }
+                }
+            };
+            return Some(Ok(token));
+        }
+    }
+}
+
+struct MovetextParser<'a> {
+    tokens: Tokens<'a>,
+    keep_variations: bool,
+    comment: Option<String>,
+    result: Option<String>,
+}
+
+impl MovetextParser<'_> {
+    // Reads moves until the end of the line: the result or the end of the text for the main
+    // line, the closing parenthesis for a variation. A variation takes its moves back before
+    // returning, the main line leaves the board at the end of the game
+    fn parse_line(&mut self, board: &mut Board, depth: usize) -> Result<Vec<GameMove>, PgnError> {
+        let mut line: Vec<GameMove> = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            match token? {
+                Token::Move(text) => {
+                    let san = text.trim_end_matches(['!', '?']);
+                    let played_move = board.parse_san(san).map_err(|error| PgnError::IllegalMove {
+                        ply: board.state_stack.len(),
+                        san: text.to_string(),
+                        error,
+                    })?;
+                    let mut game_move = GameMove::new(board, played_move);
+                    game_move.nags.extend(glyph_nag(&text[san.len()..]));
+                    board.make_move(played_move);
+                    line.push(game_move);
+                }
+                Token::Comment(text) => match line.last_mut() {
+                    Some(game_move) => game_move.add_comment(text),
+                    // comments before the first move of a variation have nowhere to go
+                    None if depth == 0 => {
+                        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                        if !text.is_empty() {
+                            self.comment = Some(text);
+                        }
+                    }
+                    None => (),
+                },
+                Token::Nag(nag) => {
+                    if let Some(game_move) = line.last_mut() {
+                        game_move.nags.push(nag);
+                    }
+                }
+                Token::VariationStart => {
+                    let Some(last_move) = line.last().map(|game_move| game_move.played_move) else {
+                        return Err(PgnError::UnexpectedToken("(".to_string()));
+                    };
+                    if !self.keep_variations {
+                        self.skip_variation()?;
+                        continue;
+                    }
+                    // the variation replaces the last move, so it starts from the position before it
+                    board.undo_move();
+                    let variation = self.parse_line(board, depth + 1)?;
+                    board.make_move(last_move);
+                    if let Some(game_move) = line.last_mut() {
+                        game_move.variations.push(variation);
+                    }
+                }
+                Token::VariationEnd => {
+                    if depth == 0 {
+                        return Err(PgnError::UnexpectedToken(")".to_string()));
+                    }
+                    for _ in &line {
+                        board.undo_move();
+                    }
+                    return Ok(line);
+                }
+                Token::Result(result) => {
+                    if depth == 0 {
+                        self.result = Some(result.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+        if depth > 0 {
+            return Err(PgnError::Unterminated("variation"));
+        }
+        Ok(line)
+    }
+
+    fn skip_variation(&mut self) -> Result<(), PgnError> {
+        let mut depth = 0;
+        for token in self.tokens.by_ref() {
+            match token? {
+                Token::VariationStart => depth += 1,
+                Token::VariationEnd if depth == 0 => return Ok(()),
+                Token::VariationEnd => depth -= 1,
+                _ => (),
+            }
+        }
+        Err(PgnError::Unterminated("variation"))
+    }
+}
+
+// Reads the games of a PGN file one at a time. A game that can't be read is returned as an
+// error and the reader carries on with the next one, so one bad game doesn't stop a database
+pub struct PgnReader<R> {
+    input: R,
+    keep_variations: bool,
+    // the first tag of the next game, read while looking for the end of the current one
+    pending_line: Option<String>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    // Variations are skipped unless keep_variations is set, then they are checked and kept
+    // in GameMove::variations
+    pub fn new(input: R, keep_variations: bool) -> Self {
+        PgnReader {
+            input,
+            keep_variations,
+            pending_line: None,
+        }
+    }
+
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        if let Some(line) = self.pending_line.take() {
+            return Ok(Some(line));
+        }
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            // lines starting with % are escaped, meant for other programs
+            if !line.starts_with('%') {
+                return Ok(Some(line.trim_start_matches('\u{feff}').trim_end().to_string()));
+            }
+        }
+    }
+
+    // The tag lines and the movetext of the next game. The movetext ends where
+    // the tags of the next game start, or at the end of the input
+    fn read_game_text(&mut self) -> io::Result<Option<(Vec<String>, String)>> {
+        let mut tag_lines = Vec::new();
+        let mut movetext = String::new();
+        let mut in_comment = false;
+        while let Some(line) = self.next_line()? {
+            if !in_comment && line.trim_start().starts_with('[') {
+                if !movetext.trim().is_empty() {
+                    self.pending_line = Some(line);
+                    break;
+                }
+                tag_lines.push(line);
+                continue;
+            }
+            in_comment = ends_in_comment(in_comment, &line);
+            movetext.push_str(&line);
+            movetext.push('\n');
+        }
+
+        if tag_lines.is_empty() && movetext.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((tag_lines, movetext)))
+    }
+
+    fn parse_game(&self, tag_lines: Vec<String>, movetext: &str) -> Result<Game, PgnError> {
+        let mut game = Game::new();
+        for line in tag_lines {
+            let (name, value) = parse_tag(&line)?;
+            game.tags.push((name, value));
+        }
+
+        let mut board = game.starting_board().map_err(PgnError::InvalidFen)?;
+        let mut parser = MovetextParser {
+            tokens: Tokens { text: movetext },
+            keep_variations: self.keep_variations,
+            comment: None,
+            result: None,
+        };
+        game.moves = parser.parse_line(&mut board, 0)?;
+        game.comment = parser.comment;
+        game.result = parser.result.or_else(|| game.tag("Result").map(str::to_string)).unwrap_or_else(|| "*".to_string());
+        Ok(game)
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_game_text() {
+            Ok(Some((tag_lines, movetext))) => Some(self.parse_game(tag_lines, &movetext)),
+            Ok(None) => None,
+            Err(error) => Some(Err(PgnError::Io(error))),
+        }
+    }
+}
+
+const LINE_LENGTH: usize = 80;
+
+// Writes games in export format: tags, then the movetext with move numbers,
+// comments and variations wrapped to 80 columns, and an empty line between games
+pub struct PgnWriter<W> {
+    output: W,
+}
+
+impl<W: Write> PgnWriter<W> {
+    pub fn new(output: W) -> Self {
+        PgnWriter { output }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    pub fn write_game(&mut self, game: &Game) -> io::Result<()> {
+        for (name, value) in &game.tags {
+            writeln!(self.output, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+        if !game.tags.is_empty() {
+            writeln!(self.output)?;
+        }
+
+        // move numbers carry on from the FEN tag
+        let board = game.starting_board().map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let ply = (board.full_move_number.max(1) as usize - 1) * 2 + usize::from(board.turn == Turn::Black);
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = &game.comment {
+            tokens.push(format!("{{ {} }}", comment));
+        }
+        write_line(&game.moves, ply, &mut tokens);
+        tokens.push(game.result.clone());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+                writeln!(self.output, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(self.output, "{}", line)?;
+        writeln!(self.output)
+    }
+}
+
+fn write_line(moves: &[GameMove], mut ply: usize, tokens: &mut Vec<String>) {
+    // black's moves get a number at the start of a line and after anything that interrupts it
+    let mut needs_number = true;
+    for game_move in moves {
+        if ply.is_multiple_of(2) {
+            tokens.push(format!("{}.", ply / 2 + 1));
+        } else if needs_number {
+            tokens.push(format!("{}...", ply / 2 + 1));
+        }
+        tokens.push(game_move.san.clone());
+        tokens.extend(game_move.nags.iter().map(|nag| format!("${}", nag)));
+
+        let mut comment = Vec::new();
+        if let Some(eval) = game_move.eval {
+            comment.push(format!("[%eval {}]", eval));
+        }
+        if let Some(clock) = game_move.clock {
+            comment.push(format!("[%clk {}]", format_clock(clock)));
+        }
+        comment.extend(game_move.comment.clone());
+        if !comment.is_empty() {
+            tokens.push(format!("{{ {} }}", comment.join(" ")));
+        }
+
+        for variation in &game_move.variations {
+            let start = tokens.len();
+            write_line(variation, ply, tokens);
+            if tokens.len() > start {
+                tokens[start].insert(0, '(');
+                tokens.last_mut().unwrap().push(')');
+            }
+        }
+        needs_number = !comment.is_empty() || !game_move.variations.is_empty();
+        ply += 1;
+    }
+}
+
+#[cfg(test)]
+mod pgn {
+    use std::time::Duration;
+
+    use super::{Eval, Game, PgnError, PgnReader, PgnWriter};
+
+    const GAMES: &str = r#"[Event "Rated Blitz game"]
+[Site "https://lichess.org/abcdefgh"]
+[White "Alice \"the rook\""]
+[Black "Bob"]
+[Result "1-0"]
+
+{ Opening comment }
+1. e4 { [%eval 0.17] [%clk 0:03:00] } 1... e5 { [%eval 0.2] [%clk 0:03:00] } 2. Nf3 $1 Nc6
+(2... d6 3. d4 (3. Bc4) 3... Nf6) 3. Bc4!? Nf6?? 4. Ng5 d5 5. exd5 Nxd5?
+6. Nxf7 Kxf7 7. Qf3+ Ke6 8. Nc3 Ncb4 9. a3 Nxc2+ 10. Kd1 Nxa1 11. Nxd5 Qh4
+12. Nxc7+ Kd7 13. Qf7+ Qe7 14. Nxa8 Qxf7 15. Bxf7 Bd6 16. d3 Rf8 1-0
+
+[Event "Study"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 b - - 0 12"]
+[Result "*"]
+
+12... Kd7 13.e4 Ke6 ; line comment
+14. Ke2 *
+"#;
+
+    #[test]
+    fn reads_tags_moves_and_comments() {
+        let games: Vec<Game> = PgnReader::new(GAMES.as_bytes(), false).map(Result::unwrap).collect();
+        assert_eq!(games.len(), 2);
+
+        let game = &games[0];
+        assert_eq!(game.tag("White"), Some("Alice \"the rook\""));
+        assert_eq!(game.comment.as_deref(), Some("Opening comment"));
+        assert_eq!(game.result, "1-0");
+        assert_eq!(game.moves.len(), 32);
+        assert_eq!(game.moves[0].eval, Some(Eval::Centipawns(17)));
+        assert_eq!(game.moves[1].clock, Some(Duration::from_secs(180)));
+        assert_eq!(game.moves[2].nags, [1]);
+        assert_eq!(game.moves[4].nags, [5]);
+        assert_eq!(game.moves[12].san, "Qf3+");
+        assert!(game.moves[3].variations.is_empty());
+        assert_eq!(game.final_board().unwrap().to_fen(), "N1b2r2/pp1k1Bpp/3b4/4p3/8/P2P4/1P3PPP/n1BK3R w - - 1 17");
+
+        let study = &games[1];
+        assert_eq!(study.moves.len(), 4);
+        assert_eq!(study.moves[2].comment.as_deref(), Some("line comment"));
+        assert_eq!(study.result, "*");
+    }
+
+    #[test]
+    fn variations_are_kept_on_request() {
+        let game = PgnReader::new(GAMES.as_bytes(), true).next().unwrap().unwrap();
+        let variation = &game.moves[3].variations[0];
+        let sans: Vec<&str> = variation.iter().map(|game_move| game_move.san.as_str()).collect();
+        assert_eq!(sans, ["d6", "d4", "Nf6"]);
+        assert_eq!(variation[1].variations[0][0].san, "Bc4");
+        assert_eq!(game.moves.len(), 32);
+    }
+
+    #[test]
+    fn written_games_read_back_the_same() {
+        for keep_variations in [false, true] {
+            let games: Vec<Game> = PgnReader::new(GAMES.as_bytes(), keep_variations).map(Result::unwrap).collect();
+            let mut writer = PgnWriter::new(Vec::new());
+            for game in &games {
+                writer.write_game(game).unwrap();
+            }
+            let written = String::from_utf8(writer.into_inner()).unwrap();
+            assert!(written.lines().all(|line| line.len() <= 80), "{}", written);
+            assert!(written.contains("12... Kd7 13. e4"), "{}", written);
+
+            let read_back: Vec<Game> = PgnReader::new(written.as_bytes(), keep_variations).map(Result::unwrap).collect();
+            assert_eq!(read_back, games, "{}", written);
+        }
+    }
+
+    #[test]
+    fn a_bad_game_does_not_stop_the_reader() {
+        let pgn = "[Event \"a\"]\n\n1. e4 e5 2. Ke3 1-0\n\n[Event \"b\"]\n\n1. d4 (1. e4 1/2-1/2\n\n[Event \"c\"]\n\n1. c4 0-1\n";
+        let results: Vec<Result<Game, PgnError>> = PgnReader::new(pgn.as_bytes(), false).collect();
+        assert_eq!(results.len(), 3);
+        assert!(matches!(&results[0], Err(PgnError::IllegalMove { ply: 2, san, .. }) if san == "Ke3"));
+        assert!(matches!(results[1], Err(PgnError::Unterminated("variation"))));
+        assert_eq!(results[2].as_ref().unwrap().moves[0].san, "c4");
+    }
+}
@@ -6,4 +6,42 @@ pub enum Piece {
     Rook,
     Queen,
     King,
-}
\ No newline at end of file
+}
+
+impl Piece {
+    // Material values used to order and prune captures, not by the evaluation functions
+    pub fn value(&self) -> i32 {
+        match self {
+            Piece::Pawn => 100,
+            Piece::Knight => 320,
+            Piece::Bishop => 330,
+            Piece::Rook => 500,
+            Piece::Queen => 900,
+            Piece::King => 20_000,
+        }
+    }
+
+    // Letter of the piece in FEN and SAN, uppercase as for white
+    pub fn to_char(&self) -> char {
+        match self {
+            Piece::Pawn => 'P',
+            Piece::Knight => 'N',
+            Piece::Bishop => 'B',
+            Piece::Rook => 'R',
+            Piece::Queen => 'Q',
+            Piece::King => 'K',
+        }
+    }
+
+    pub fn from_char(letter: char) -> Option<Piece> {
+        match letter.to_ascii_uppercase() {
+            'P' => Some(Piece::Pawn),
+            'N' => Some(Piece::Knight),
+            'B' => Some(Piece::Bishop),
+            'R' => Some(Piece::Rook),
+            'Q' => Some(Piece::Queen),
+            'K' => Some(Piece::King),
+            _ => None,
+        }
+    }
+}
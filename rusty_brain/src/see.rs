@@ -0,0 +1,151 @@
+use crate::board::{Board, Turn};
+use crate::magic::Magic;
+use crate::movement::Move;
+use crate::piece::Piece;
+
+// Static exchange evaluation: the material a capture wins or loses once every piece
+// attacking the target square has recaptured, least valuable attackers first.
+// Pins are ignored, x-rays through the pieces that already captured are not.
+impl Board {
+
+    pub fn see(&self, move_to_check: Move) -> i32 {
+        let from = move_to_check.get_from();
+        let to = move_to_check.get_to();
+        let Some((mut piece_on_square, us)) = self.piece_at(from) else {
+            return 0;
+        };
+
+        let mut occupied = !self.bitboards.get_empty_squares() & !(1 << from);
+        let mut gain = [0; 32];
+        gain[0] = if move_to_check.get_flags() == Move::EP_CAPTURE {
+            let captured_pawn = if us == Turn::White { to - 8 } else { to + 8 };
+            occupied &= !(1 << captured_pawn);
+            Piece::Pawn.value()
+        } else if move_to_check.is_capture() {
+            self.piece_at(to).map_or(0, |(captured, _)| captured.value())
+        } else {
+            0
+        };
+        if let Some(promotion) = move_to_check.get_promotion_piece() {
+            gain[0] += promotion.value() - Piece::Pawn.value();
+            piece_on_square = promotion;
+        }
+
+        let mut side = us.opposite();
+        let mut depth = 0;
+        loop {
+            let attackers = self.attackers_to(to, occupied) & occupied;
+            let Some((attacker, attacker_bitboard)) = self.least_valuable_attacker(attackers & self.bitboards.get_ally_pieces(side), side) else {
+                break;
+            };
+            // the king can only recapture when nothing defends the square anymore
+            if attacker == Piece::King && attackers & self.bitboards.get_ally_pieces(side.opposite()) != 0 {
+                break;
+            }
+
+            depth += 1;
+            gain[depth] = piece_on_square.value() - gain[depth - 1];
+            // neither side can do better by going on
+            if (-gain[depth - 1]).max(gain[depth]) < 0 {
+                break;
+            }
+
+            occupied &= !attacker_bitboard;
+            piece_on_square = attacker;
+            side = side.opposite();
+        }
+
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    // Pieces of both colours attacking the square with the given occupancy
+    pub fn attackers_to(&self, square: u8, occupied: u64) -> u64 {
+        let not_a_file: u64 = 0xfefefefefefefefe;
+        let not_h_file: u64 = 0x7f7f7f7f7f7f7f7f;
+        let square_bitboard = 1u64 << square;
+        let bitboards = &self.bitboards;
+
+        // the squares a pawn has to stand on to attack the square
+        let white_pawn_squares = ((square_bitboard >> 7) & not_a_file) | ((square_bitboard >> 9) & not_h_file);
+        let black_pawn_squares = ((square_bitboard << 7) & not_h_file) | ((square_bitboard << 9) & not_a_file);
+
+        let orthogonal = bitboards.white_rooks | bitboards.black_rooks | bitboards.white_queens | bitboards.black_queens;
+        let diagonal = bitboards.white_bishops | bitboards.black_bishops | bitboards.white_queens | bitboards.black_queens;
+
+        (white_pawn_squares & bitboards.white_pawns)
+            | (black_pawn_squares & bitboards.black_pawns)
+            | (self.get_knight_attacked_squares(square_bitboard) & (bitboards.white_knights | bitboards.black_knights))
+            | (self.get_king_attacked_squares(square_bitboard) & (bitboards.white_king | bitboards.black_king))
+            | (Magic::rook_attacks(square, occupied) & orthogonal)
+            | (Magic::bishop_attacks(square, occupied) & diagonal)
+    }
+
+    fn least_valuable_attacker(&self, attackers: u64, side: Turn) -> Option<(Piece, u64)> {
+        let bitboards = &self.bitboards;
+        let pieces = match side {
+            Turn::White => [
+                (Piece::Pawn, bitboards.white_pawns),
+                (Piece::Knight, bitboards.white_knights),
+                (Piece::Bishop, bitboards.white_bishops),
+                (Piece::Rook, bitboards.white_rooks),
+                (Piece::Queen, bitboards.white_queens),
+                (Piece::King, bitboards.white_king),
+            ],
+            Turn::Black => [
+                (Piece::Pawn, bitboards.black_pawns),
+                (Piece::Knight, bitboards.black_knights),
+                (Piece::Bishop, bitboards.black_bishops),
+                (Piece::Rook, bitboards.black_rooks),
+                (Piece::Queen, bitboards.black_queens),
+                (Piece::King, bitboards.black_king),
+            ],
+        };
+
+        pieces.into_iter()
+            .find(|(_, bitboard)| bitboard & attackers != 0)
+            .map(|(piece, bitboard)| {
+                let piece_attackers = bitboard & attackers;
+                (piece, piece_attackers & piece_attackers.wrapping_neg())
+            })
+    }
+}
+
+#[cfg(test)]
+mod see {
+    use crate::board::Board;
+
+    fn see_of(fen: &str, uci_move: &str) -> i32 {
+        let mut board = Board::from_fen(fen.to_string());
+        let move_to_check = board.generate_legal_moves().into_iter().find(|legal| legal.to_string() == uci_move).unwrap();
+        board.see(move_to_check)
+    }
+
+    #[test]
+    fn undefended_pawn_is_won() {
+        assert_eq!(see_of("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), 100);
+    }
+
+    #[test]
+    fn defended_pawn_costs_the_knight() {
+        // the white knight, rook and bishop battery loses to the black recaptures
+        assert!(see_of("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5") < 0);
+    }
+
+    #[test]
+    fn xrays_join_the_exchange() {
+        // Rxd5 Rxd5 Rxd5, the second white rook stands behind the first
+        assert_eq!(see_of("3r2k1/8/8/3p4/8/8/3R4/3R2K1 w - - 0 1", "d2d5"), 100);
+    }
+
+    #[test]
+    fn pieces_are_valued_by_what_they_capture() {
+        // Rxd8+ Kxd8, the queen is worth more than the rook
+        assert_eq!(see_of("3qk3/8/8/8/8/8/8/3RK3 w - - 0 1", "d1d8"), 400);
+        // a capture on the back rank has no square behind it for an en passant pawn
+        assert_eq!(see_of("R3k3/8/1n6/8/8/8/8/4K3 b - - 0 1", "b6a8"), 500);
+    }
+}
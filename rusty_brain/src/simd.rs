@@ -0,0 +1,470 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// Vectorised versions of the loops the NNUE spends its time in. Every kernel takes slices
+// of any length, the vector code handles as many whole registers as fit and the scalar
+// code the rest, so all backends give exactly the same results as the scalar one.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Scalar,
+    Avx2,
+    Avx512,
+    Neon,
+}
+
+impl Backend {
+    // fastest first
+    pub const ALL: [Backend; 4] = [Backend::Avx512, Backend::Avx2, Backend::Neon, Backend::Scalar];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Scalar => "scalar",
+            Backend::Avx2 => "avx2",
+            Backend::Avx512 => "avx512",
+            Backend::Neon => "neon",
+        }
+    }
+
+    // whether the cpu the engine runs on has the instructions of the backend
+    pub fn is_supported(self) -> bool {
+        match self {
+            Backend::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx512 => is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw"),
+            #[cfg(target_arch = "aarch64")]
+            Backend::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    pub fn detect() -> Backend {
+        Backend::ALL.into_iter().find(|backend| backend.is_supported()).unwrap_or(Backend::Scalar)
+    }
+}
+
+// index into Backend::ALL of the backend in use, detected on first use
+static ACTIVE: AtomicU8 = AtomicU8::new(UNDETECTED);
+const UNDETECTED: u8 = u8::MAX;
+
+pub fn active() -> Backend {
+    match ACTIVE.load(Ordering::Relaxed) {
+        UNDETECTED => {
+            let backend = Backend::detect();
+            set_backend(backend);
+            backend
+        }
+        index => Backend::ALL[index as usize],
+    }
+}
+
+// Switches every evaluator of the process to another backend, used to compare them.
+// Returns false and keeps the current one if the cpu doesn't support it.
+pub fn set_backend(backend: Backend) -> bool {
+    if !backend.is_supported() {
+        return false;
+    }
+    let index = Backend::ALL.iter().position(|&other| other == backend).unwrap();
+    ACTIVE.store(index as u8, Ordering::Relaxed);
+    true
+}
+
+// Runs the kernel of the active backend, which returns how many elements it handled
+// (and its partial result), the scalar code finishes the remainder
+macro_rules! dispatch {
+    ($kernel:ident($($arg:expr),*), $otherwise:expr) => {
+        match active() {
+            #[cfg(target_arch = "x86_64")]
+            // SAFETY: a backend is only active on cpus that support it
+            Backend::Avx512 => unsafe { avx512::$kernel($($arg),*) },
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2 => unsafe { avx2::$kernel($($arg),*) },
+            #[cfg(target_arch = "aarch64")]
+            Backend::Neon => unsafe { neon::$kernel($($arg),*) },
+            _ => $otherwise,
+        }
+    };
+}
+
+// acc += weights
+pub fn add(acc: &mut [i16], weights: &[i16]) {
+    assert_eq!(acc.len(), weights.len());
+    let done = dispatch!(add(acc, weights), 0);
+    for (value, weight) in acc[done..].iter_mut().zip(&weights[done..]) {
+        *value += *weight;
+    }
+}
+
+// acc -= weights
+pub fn sub(acc: &mut [i16], weights: &[i16]) {
+    assert_eq!(acc.len(), weights.len());
+    let done = dispatch!(sub(acc, weights), 0);
+    for (value, weight) in acc[done..].iter_mut().zip(&weights[done..]) {
+        *value -= *weight;
+    }
+}
+
+// out = input - removed + added, a quiet move in one pass
+pub fn sub_add(out: &mut [i16], input: &[i16], removed: &[i16], added: &[i16]) {
+    let len = out.len();
+    assert!(input.len() == len && removed.len() == len && added.len() == len);
+    let done = dispatch!(sub_add(out, input, removed, added), 0);
+    for i in done..len {
+        out[i] = input[i] - removed[i] + added[i];
+    }
+}
+
+// out = input - removed - also_removed + added, a capture in one pass
+pub fn sub_sub_add(out: &mut [i16], input: &[i16], removed: &[i16], also_removed: &[i16], added: &[i16]) {
+    let len = out.len();
+    assert!(input.len() == len && removed.len() == len && also_removed.len() == len && added.len() == len);
+    let done = dispatch!(sub_sub_add(out, input, removed, also_removed, added), 0);
+    for i in done..len {
+        out[i] = input[i] - removed[i] - also_removed[i] + added[i];
+    }
+}
+
+// sum of clamp(input, 0, qa) * weight
+pub fn crelu_dot(inputs: &[i16], weights: &[i16], qa: i16) -> i32 {
+    assert_eq!(inputs.len(), weights.len());
+    let (done, mut sum) = dispatch!(crelu_dot(inputs, weights, qa), (0, 0));
+    for (&input, &weight) in inputs[done..].iter().zip(&weights[done..]) {
+        sum += i32::from(input.clamp(0, qa)) * i32::from(weight);
+    }
+    sum
+}
+
+// sum of clamp(input, 0, qa)^2 * weight
+pub fn screlu_dot(inputs: &[i16], weights: &[i16], qa: i16) -> i32 {
+    assert_eq!(inputs.len(), weights.len());
+    let (done, mut sum) = dispatch!(screlu_dot(inputs, weights, qa), (0, 0));
+    for (&input, &weight) in inputs[done..].iter().zip(&weights[done..]) {
+        let activated = i32::from(input.clamp(0, qa));
+        sum += activated * i32::from(weight) * activated;
+    }
+    sum
+}
+
+// The vector kernels wrap on overflow where the scalar code would panic in debug builds,
+// a sensible network never gets near the limits of its integers either way.
+// None of them needs aligned memory.
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 16;
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn load(values: &[i16], i: usize) -> __m256i {
+        _mm256_loadu_si256(values.as_ptr().add(i) as *const __m256i)
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn store(values: &mut [i16], i: usize, vector: __m256i) {
+        _mm256_storeu_si256(values.as_mut_ptr().add(i) as *mut __m256i, vector)
+    }
+
+    #[target_feature(enable = "avx2")]
+    fn horizontal_sum(vector: __m256i) -> i32 {
+        let sum = _mm_add_epi32(_mm256_castsi256_si128(vector), _mm256_extracti128_si256::<1>(vector));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32::<0b01_00_11_10>(sum));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32::<0b10_11_00_01>(sum));
+        _mm_cvtsi128_si32(sum)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn add(acc: &mut [i16], weights: &[i16]) -> usize {
+        let done = acc.len() - acc.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            store(acc, i, _mm256_add_epi16(load(acc, i), load(weights, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sub(acc: &mut [i16], weights: &[i16]) -> usize {
+        let done = acc.len() - acc.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            store(acc, i, _mm256_sub_epi16(load(acc, i), load(weights, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sub_add(out: &mut [i16], input: &[i16], removed: &[i16], added: &[i16]) -> usize {
+        let done = out.len() - out.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            let value = _mm256_sub_epi16(load(input, i), load(removed, i));
+            store(out, i, _mm256_add_epi16(value, load(added, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sub_sub_add(out: &mut [i16], input: &[i16], removed: &[i16], also_removed: &[i16], added: &[i16]) -> usize {
+        let done = out.len() - out.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            let value = _mm256_sub_epi16(load(input, i), load(removed, i));
+            let value = _mm256_sub_epi16(value, load(also_removed, i));
+            store(out, i, _mm256_add_epi16(value, load(added, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn crelu_dot(inputs: &[i16], weights: &[i16], qa: i16) -> (usize, i32) {
+        let done = inputs.len() - inputs.len() % LANES;
+        let (zero, max) = (_mm256_setzero_si256(), _mm256_set1_epi16(qa));
+        let mut sum = zero;
+        for i in (0..done).step_by(LANES) {
+            let activated = _mm256_min_epi16(_mm256_max_epi16(load(inputs, i), zero), max);
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(activated, load(weights, i)));
+        }
+        (done, horizontal_sum(sum))
+    }
+
+    // the squares don't fit in 16 bits, the products are taken in 32 bit lanes
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn screlu_dot(inputs: &[i16], weights: &[i16], qa: i16) -> (usize, i32) {
+        let done = inputs.len() - inputs.len() % LANES;
+        let (zero, max) = (_mm256_setzero_si256(), _mm256_set1_epi16(qa));
+        let mut sum = zero;
+        for i in (0..done).step_by(LANES) {
+            let activated = _mm256_min_epi16(_mm256_max_epi16(load(inputs, i), zero), max);
+            let weight = load(weights, i);
+            for (activated, weight) in [
+                (_mm256_castsi256_si128(activated), _mm256_castsi256_si128(weight)),
+                (_mm256_extracti128_si256::<1>(activated), _mm256_extracti128_si256::<1>(weight)),
+            ] {
+                let activated = _mm256_cvtepi16_epi32(activated);
+                let product = _mm256_mullo_epi32(_mm256_mullo_epi32(activated, _mm256_cvtepi16_epi32(weight)), activated);
+                sum = _mm256_add_epi32(sum, product);
+            }
+        }
+        (done, horizontal_sum(sum))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx512 {
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 32;
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    unsafe fn load(values: &[i16], i: usize) -> __m512i {
+        _mm512_loadu_si512(values.as_ptr().add(i) as *const __m512i)
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    unsafe fn store(values: &mut [i16], i: usize, vector: __m512i) {
+        _mm512_storeu_si512(values.as_mut_ptr().add(i) as *mut __m512i, vector)
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn add(acc: &mut [i16], weights: &[i16]) -> usize {
+        let done = acc.len() - acc.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            store(acc, i, _mm512_add_epi16(load(acc, i), load(weights, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn sub(acc: &mut [i16], weights: &[i16]) -> usize {
+        let done = acc.len() - acc.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            store(acc, i, _mm512_sub_epi16(load(acc, i), load(weights, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn sub_add(out: &mut [i16], input: &[i16], removed: &[i16], added: &[i16]) -> usize {
+        let done = out.len() - out.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            let value = _mm512_sub_epi16(load(input, i), load(removed, i));
+            store(out, i, _mm512_add_epi16(value, load(added, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn sub_sub_add(out: &mut [i16], input: &[i16], removed: &[i16], also_removed: &[i16], added: &[i16]) -> usize {
+        let done = out.len() - out.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            let value = _mm512_sub_epi16(load(input, i), load(removed, i));
+            let value = _mm512_sub_epi16(value, load(also_removed, i));
+            store(out, i, _mm512_add_epi16(value, load(added, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn crelu_dot(inputs: &[i16], weights: &[i16], qa: i16) -> (usize, i32) {
+        let done = inputs.len() - inputs.len() % LANES;
+        let (zero, max) = (_mm512_setzero_si512(), _mm512_set1_epi16(qa));
+        let mut sum = zero;
+        for i in (0..done).step_by(LANES) {
+            let activated = _mm512_min_epi16(_mm512_max_epi16(load(inputs, i), zero), max);
+            sum = _mm512_add_epi32(sum, _mm512_madd_epi16(activated, load(weights, i)));
+        }
+        (done, _mm512_reduce_add_epi32(sum))
+    }
+
+    // the squares don't fit in 16 bits, the products are taken in 32 bit lanes
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn screlu_dot(inputs: &[i16], weights: &[i16], qa: i16) -> (usize, i32) {
+        let done = inputs.len() - inputs.len() % LANES;
+        let (zero, max) = (_mm512_setzero_si512(), _mm512_set1_epi16(qa));
+        let mut sum = zero;
+        for i in (0..done).step_by(LANES) {
+            let activated = _mm512_min_epi16(_mm512_max_epi16(load(inputs, i), zero), max);
+            let weight = load(weights, i);
+            for (activated, weight) in [
+                (_mm512_castsi512_si256(activated), _mm512_castsi512_si256(weight)),
+                (_mm512_extracti64x4_epi64::<1>(activated), _mm512_extracti64x4_epi64::<1>(weight)),
+            ] {
+                let activated = _mm512_cvtepi16_epi32(activated);
+                let product = _mm512_mullo_epi32(_mm512_mullo_epi32(activated, _mm512_cvtepi16_epi32(weight)), activated);
+                sum = _mm512_add_epi32(sum, product);
+            }
+        }
+        (done, _mm512_reduce_add_epi32(sum))
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    const LANES: usize = 8;
+
+    #[target_feature(enable = "neon")]
+    unsafe fn load(values: &[i16], i: usize) -> int16x8_t {
+        vld1q_s16(values.as_ptr().add(i))
+    }
+
+    #[target_feature(enable = "neon")]
+    unsafe fn store(values: &mut [i16], i: usize, vector: int16x8_t) {
+        vst1q_s16(values.as_mut_ptr().add(i), vector)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn add(acc: &mut [i16], weights: &[i16]) -> usize {
+        let done = acc.len() - acc.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            store(acc, i, vaddq_s16(load(acc, i), load(weights, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn sub(acc: &mut [i16], weights: &[i16]) -> usize {
+        let done = acc.len() - acc.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            store(acc, i, vsubq_s16(load(acc, i), load(weights, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn sub_add(out: &mut [i16], input: &[i16], removed: &[i16], added: &[i16]) -> usize {
+        let done = out.len() - out.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            let value = vsubq_s16(load(input, i), load(removed, i));
+            store(out, i, vaddq_s16(value, load(added, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn sub_sub_add(out: &mut [i16], input: &[i16], removed: &[i16], also_removed: &[i16], added: &[i16]) -> usize {
+        let done = out.len() - out.len() % LANES;
+        for i in (0..done).step_by(LANES) {
+            let value = vsubq_s16(vsubq_s16(load(input, i), load(removed, i)), load(also_removed, i));
+            store(out, i, vaddq_s16(value, load(added, i)));
+        }
+        done
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn crelu_dot(inputs: &[i16], weights: &[i16], qa: i16) -> (usize, i32) {
+        let done = inputs.len() - inputs.len() % LANES;
+        let (zero, max) = (vdupq_n_s16(0), vdupq_n_s16(qa));
+        let mut sum = vdupq_n_s32(0);
+        for i in (0..done).step_by(LANES) {
+            let activated = vminq_s16(vmaxq_s16(load(inputs, i), zero), max);
+            let weight = load(weights, i);
+            sum = vmlal_s16(sum, vget_low_s16(activated), vget_low_s16(weight));
+            sum = vmlal_high_s16(sum, activated, weight);
+        }
+        (done, vaddvq_s32(sum))
+    }
+
+    // the squares don't fit in 16 bits, the products are taken in 32 bit lanes
+    #[target_feature(enable = "neon")]
+    pub unsafe fn screlu_dot(inputs: &[i16], weights: &[i16], qa: i16) -> (usize, i32) {
+        let done = inputs.len() - inputs.len() % LANES;
+        let (zero, max) = (vdupq_n_s16(0), vdupq_n_s16(qa));
+        let mut sum = vdupq_n_s32(0);
+        for i in (0..done).step_by(LANES) {
+            let activated = vminq_s16(vmaxq_s16(load(inputs, i), zero), max);
+            let weight = load(weights, i);
+            for (activated, weight) in [
+                (vmovl_s16(vget_low_s16(activated)), vmovl_s16(vget_low_s16(weight))),
+                (vmovl_high_s16(activated), vmovl_high_s16(weight)),
+            ] {
+                sum = vaddq_s32(sum, vmulq_s32(vmulq_s32(activated, weight), activated));
+            }
+        }
+        (done, vaddvq_s32(sum))
+    }
+}
+
+#[cfg(test)]
+mod simd {
+    use super::{active, set_backend, Backend};
+
+    // every supported backend has to agree with the scalar code, including the lengths
+    // that leave a remainder for it and accumulators that leave the activation's range
+    #[test]
+    fn backends_match_scalar() {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut random = |range: i32| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % (2 * range as u64 + 1)) as i32 as i16 - range as i16
+        };
+
+        let original = active();
+        for len in [8, 16, 32, 128, 200, 1024] {
+            let vectors: Vec<Vec<i16>> = (0..5).map(|_| (0..len).map(|_| random(400)).collect()).collect();
+            let weights: Vec<i16> = (0..len).map(|_| random(64)).collect();
+
+            let results = |backend| {
+                assert!(set_backend(backend));
+                let mut added = vectors[0].clone();
+                super::add(&mut added, &vectors[1]);
+                let mut removed = vectors[0].clone();
+                super::sub(&mut removed, &vectors[1]);
+                let mut quiet = vec![0; len];
+                super::sub_add(&mut quiet, &vectors[0], &vectors[1], &vectors[2]);
+                let mut capture = vec![0; len];
+                super::sub_sub_add(&mut capture, &vectors[0], &vectors[1], &vectors[2], &vectors[3]);
+                let dots = [super::crelu_dot(&vectors[4], &weights, 255), super::screlu_dot(&vectors[4], &weights, 255)];
+                (added, removed, quiet, capture, dots)
+            };
+
+            let expected = results(Backend::Scalar);
+            for backend in Backend::ALL.into_iter().filter(|backend| backend.is_supported()) {
+                assert!(results(backend) == expected, "{} differs from scalar with {} values", backend.name(), len);
+            }
+        }
+        set_backend(original);
+    }
+}
@@ -103,6 +103,18 @@ impl From<&str> for Square {
 }
 
 impl Square {
+    // Like From<&str> but None instead of a panic for text that isn't a square name
+    pub fn parse(name: &str) -> Option<Square> {
+        let mut chars = name.chars();
+        let (file, rank, None) = (chars.next()?, chars.next()?, chars.next()) else {
+            return None;
+        };
+        if !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+            return None;
+        }
+        Some(Square::from((rank as u8 - b'1') * 8 + (file as u8 - b'a')))
+    }
+
     pub fn rank(self) -> Rank {
         let rank_index = (self as u8) / 8;
         match rank_index {
@@ -1,450 +1,195 @@
 #[cfg(test)]
 mod tests {
+    use crate::bitboards::Bitboards;
     use crate::board::Turn;
+    use crate::magic::Magic;
+    use crate::movegen::MoveList;
     use crate::movement::Move;
     use crate::square::File;
     use crate::{board::Board, square::{Rank, Square}};
 
-    #[test]
-    fn test_pawns() {
-        let mut board = Board::empty();
-        board.bitboards.white_pawns = 1;
-        for i in 0..64 {
-            let moves = board.pawn_moves(&Vec::new(), !0);
-            let rank = Square::from(i).rank();
-
-            if rank == Rank::Second{
-                assert_eq!(moves.len(), 2);
-            }else if rank == Rank::Eighth{
-                assert_eq!(moves.len(), 0);
-            }else if rank == Rank::Seventh {
-                assert_eq!(moves.len(), 4)
-            }else{
-                assert_eq!(moves.len(), 1);
-            }
+    // the legal moves of the pieces on a bitboard
+    fn moves_of(board: &mut Board, pieces: fn(&Bitboards) -> u64) -> Vec<Move> {
+        let pieces = pieces(&board.bitboards);
+        board.generate_legal_moves().into_iter().filter(|legal| pieces & 1 << legal.get_from() != 0).collect()
+    }
 
-            board.bitboards.white_pawns <<= 1;
+    // how many legal moves a piece alone on the square has, both kings are put where the piece
+    // can't reach them from any square
+    fn lone_piece_moves(turn: Turn, square: u8, piece: fn(&mut Bitboards) -> &mut u64) -> usize {
+        let mut board = Board::empty();
+        let position = 1 << square;
+        let reach = position | Magic::rook_attacks(square, 0) | Magic::bishop_attacks(square, 0)
+            | board.get_knight_attacked_squares(position) | board.get_king_attacked_squares(position);
+        board.bitboards.white_king = 1 << (!reach).trailing_zeros();
+        let white_king_area = board.bitboards.white_king | board.get_king_attacked_squares(board.bitboards.white_king);
+        board.bitboards.black_king = 1 << (!(reach | white_king_area)).trailing_zeros();
+        *piece(&mut board.bitboards) |= position;
+        board.turn = turn;
+        board.update_position_state();
+
+        board.generate_legal_moves().iter().filter(|legal| legal.get_from() == square).count()
+    }
 
+    // how many legal moves a king alone with the other king has, the other one two squares away or further
+    fn lone_king_moves(turn: Turn, square: u8) -> usize {
+        let mut board = Board::empty();
+        let position = 1 << square;
+        let area = position | board.get_king_attacked_squares(position);
+        let other_king = 1 << (!(area | board.get_king_attacked_squares(area))).trailing_zeros();
+        match turn {
+            Turn::White => (board.bitboards.white_king, board.bitboards.black_king) = (position, other_king),
+            Turn::Black => (board.bitboards.black_king, board.bitboards.white_king) = (position, other_king),
         }
-        
-        board.bitboards.white_pawns = 0;
-
-        board.turn = Turn::Black;
-        board.bitboards.black_pawns = 1;
+        board.turn = turn;
+        board.update_position_state();
 
-        for i in 0..64 {
-            let moves = board.pawn_moves(&Vec::new(), !0);
-            let rank = Square::from(i).rank();
-
-            if rank == Rank::Seventh{
-                assert_eq!(moves.len(), 2);
-            }else if rank == Rank::First{
-                assert_eq!(moves.len(), 0);
-            }else if rank == Rank::Second {
-                assert_eq!(moves.len(), 4)
-            }else{
-                assert_eq!(moves.len(), 1);
-            }
+        board.generate_legal_moves().len()
+    }
 
-            board.bitboards.black_pawns <<= 1;
+    // how many squares a bishop alone on the square reaches, the closer to the centre the more
+    fn diagonal_moves(square: Square) -> usize {
+        let (file, rank) = (square.file(), square.rank());
+        if rank == Rank::First || rank == Rank::Eighth || file == File::A || file == File::H {
+            7
+        }else if rank == Rank::Second || rank == Rank::Seventh || file == File::B || file == File::G {
+            9
+        }else if rank == Rank::Third || rank == Rank::Sixth || file == File::C || file == File::F {
+            11
+        }else{
+            13
+        }
+    }
 
+    #[test]
+    fn test_pawns() {
+        for square in Square::A2 as u8..=Square::H7 as u8 {
+            let white = lone_piece_moves(Turn::White, square, |bitboards| &mut bitboards.white_pawns);
+            let black = lone_piece_moves(Turn::Black, square, |bitboards| &mut bitboards.black_pawns);
+            let expected = match Square::from(square).rank() {
+                Rank::Second => (2, 4),
+                Rank::Seventh => (4, 2),
+                _ => (1, 1),
+            };
+            assert_eq!((white, black), expected, "{}", Square::from(square));
         }
 
         // make sure no friendly captures are allowed
-        board.bitboards.black_pawns = u64::MAX;
-        
-        let moves = board.pawn_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
-        
-        board.bitboards.black_pawns = 0;
-
-        board.turn = Turn::White;
-
-        board.bitboards.white_pawns = u64::MAX;
-
-        let moves = board.pawn_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
-        
+        let mut board = Board::new();
+        assert!(moves_of(&mut board, |bitboards| bitboards.white_pawns).iter().all(|legal| !legal.is_capture()));
+        assert_eq!(moves_of(&mut board, |bitboards| bitboards.white_pawns).len(), 16);
+
         // test en passant
-        board.turn = Turn::Black;
-        board.bitboards.white_pawns = 0x0800000000;
-        board.bitboards.black_pawns = 0x10000000000000;
+        let mut board = Board::from_fen("4k3/4p3/8/3P4/8/8/8/4K3 b - - 0 1".to_string());
         board.make_move(Move::encode(Square::E7 as u8, Square::E5 as u8, Move::DOUBLE_PAWN_PUSH));
 
-        let moves = board.pawn_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 2);        
-        
-        board.bitboards.black_pawns = 0x05000000;
-        board.bitboards.white_pawns = 0x0200;
+        assert_eq!(moves_of(&mut board, |bitboards| bitboards.white_pawns).len(), 2);
 
+        let mut board = Board::from_fen("4k3/8/8/8/p1p5/8/1P6/4K3 w - - 0 1".to_string());
         board.make_move(Move::encode(Square::B2 as u8, Square::B4 as u8, Move::DOUBLE_PAWN_PUSH));
-        board.print_board();
 
-        let moves = board.pawn_moves(&Vec::new(), !0);
-        
-        for m in &moves {
-            println!("move {}", m);
-
-        }
-
-        assert_eq!(moves.len(), 4);
-              
+        assert_eq!(moves_of(&mut board, |bitboards| bitboards.black_pawns).len(), 4);
     }
-    
+
     #[test]
     fn test_knights() {
-        let mut board = Board::empty();
-        board.bitboards.white_knights = 1;
-
         for i in 0..64 {
             let square = Square::from(i);
             let rank = square.rank();
             let file = square.file();
-            let moves = board.knight_moves(&Vec::new(), !0);
-            if square == Square::A1 || square == Square::H1 || square == Square::A8 || square == Square::H8 {
-                assert_eq!(moves.len(), 2);
+            let expected = if square == Square::A1 || square == Square::H1 || square == Square::A8 || square == Square::H8 {
+                2
             }else if square == Square::B1 || square == Square::G1 || square == Square::B8 || square == Square::G8 || square == Square::A2 || square == Square::A7 || square == Square::H2 || square == Square::H7 {
-                assert_eq!(moves.len(), 3);
-            }else if rank == Rank::First || rank == Rank::Eighth {
-                assert_eq!(moves.len(), 4);
-            }else if square == Square::B2 || square == Square::G2 || square == Square::B7 || square == Square::G7 || file == File::A || file == File::H {
-                assert_eq!(moves.len(), 4);
+                3
+            }else if rank == Rank::First || rank == Rank::Eighth || square == Square::B2 || square == Square::G2 || square == Square::B7 || square == Square::G7 || file == File::A || file == File::H {
+                4
             }else if rank == Rank::Second || rank == Rank::Seventh || file == File::B || file == File::G {
-                assert_eq!(moves.len(), 6);
+                6
             }else{
-                assert_eq!(moves.len(), 8);
-            }
-            
-            board.bitboards.white_knights <<= 1;
-        }
-        
-        board.bitboards.white_knights = 0;
+                8
+            };
 
-        board.turn = Turn::Black;
-        board.bitboards.black_knights = 1;
-
-        for i in 0..64 {
-            let square = Square::from(i);
-            let rank = square.rank();
-            let file = square.file();
-            let moves = board.knight_moves(&Vec::new(), !0);
-            if square == Square::A1 || square == Square::H1 || square == Square::A8 || square == Square::H8 {
-                assert_eq!(moves.len(), 2);
-            }else if square == Square::B1 || square == Square::G1 || square == Square::B8 || square == Square::G8 || square == Square::A2 || square == Square::A7 || square == Square::H2 || square == Square::H7 {
-                assert_eq!(moves.len(), 3);
-            }else if rank == Rank::First || rank == Rank::Eighth {
-                assert_eq!(moves.len(), 4);
-            }else if square == Square::B2 || square == Square::G2 || square == Square::B7 || square == Square::G7 || file == File::A || file == File::H {
-                assert_eq!(moves.len(), 4);
-            }else if rank == Rank::Second || rank == Rank::Seventh || file == File::B || file == File::G {
-                assert_eq!(moves.len(), 6);
-            }else{
-                assert_eq!(moves.len(), 8);
-            }
-            
-            board.bitboards.black_knights <<= 1;
+            assert_eq!(lone_piece_moves(Turn::White, i, |bitboards| &mut bitboards.white_knights), expected, "{}", square);
+            assert_eq!(lone_piece_moves(Turn::Black, i, |bitboards| &mut bitboards.black_knights), expected, "{}", square);
         }
-        
-        // make sure no friendly captures are allowed
-        board.bitboards.black_knights = u64::MAX;
-        
-        let moves = board.knight_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
-        
-        board.bitboards.black_knights = 0;
-
-        board.turn = Turn::White;
-
-        board.bitboards.white_knights = u64::MAX;
-
-        let moves = board.knight_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
-        
-        // white knight on dark squares and black knights on light squares
-        board.bitboards.white_knights = 0xAA55AA55AA55AA55;
-
-        board.bitboards.black_knights = 0x55AA55AA55AA55AA;
-        
-        let moves = board.knight_moves(&Vec::new(), !0);
-
-        assert_eq!(moves.len(), 168);
-
-        board.turn = Turn::Black;
-
-        let moves = board.knight_moves(&Vec::new(), !0);
 
-        assert_eq!(moves.len(), 168);
+        // make sure no friendly captures are allowed
+        assert_eq!(moves_of(&mut Board::new(), |bitboards| bitboards.white_knights).len(), 4);
     }
 
     #[test]
     fn test_bishops() {
-        let mut board = Board::empty();
-        board.bitboards.white_bishops = 1;
-
         for i in 0..64 {
-            let moves = board.bishop_moves(&Vec::new(), !0);
-            let file = Square::from(i).file();
-            let rank = Square::from(i).rank();
-            if rank == Rank::First || rank == Rank::Eighth{
-                assert_eq!(moves.len(), 7);
-            }else if file == File::A || file == File::H {
-                assert_eq!(moves.len(), 7);
-            }else if rank == Rank::Second || rank == Rank::Seventh{
-                assert_eq!(moves.len(), 9);
-            }else if file == File::B || file == File::G {
-                assert_eq!(moves.len(), 9);
-            }else if file == File::C || file == File::F{
-                assert_eq!(moves.len(), 11);
-            }else if rank == Rank::Third || rank == Rank::Sixth{
-                assert_eq!(moves.len(), 11);
-            }else if file == File::D || file == File::E {
-                assert_eq!(moves.len(), 13);
-            }
-            board.bitboards.white_bishops <<= 1;
-        }
-        board.bitboards.white_bishops = 0;
+            let expected = diagonal_moves(Square::from(i));
 
-        board.turn = Turn::Black;
-        board.bitboards.black_bishops = 1;
-
-        for i in 0..64 {
-            let moves = board.bishop_moves(&Vec::new(), !0);
-            let file = Square::from(i).file();
-            let rank = Square::from(i).rank();
-            if rank == Rank::First || rank == Rank::Eighth{
-                assert_eq!(moves.len(), 7);
-            }else if file == File::A || file == File::H {
-                assert_eq!(moves.len(), 7);
-            }else if rank == Rank::Second || rank == Rank::Seventh{
-                assert_eq!(moves.len(), 9);
-            }else if file == File::B || file == File::G {
-                assert_eq!(moves.len(), 9);
-            }else if file == File::C || file == File::F{
-                assert_eq!(moves.len(), 11);
-            }else if rank == Rank::Third || rank == Rank::Sixth{
-                assert_eq!(moves.len(), 11);
-            }else if file == File::D || file == File::E {
-                assert_eq!(moves.len(), 13);
-            }
-            board.bitboards.black_bishops <<= 1;
+            assert_eq!(lone_piece_moves(Turn::White, i, |bitboards| &mut bitboards.white_bishops), expected, "{}", Square::from(i));
+            assert_eq!(lone_piece_moves(Turn::Black, i, |bitboards| &mut bitboards.black_bishops), expected, "{}", Square::from(i));
         }
 
         // make sure no friendly captures are allowed
-        board.bitboards.black_bishops = u64::MAX;
-        
-        let moves = board.bishop_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
-        
-        board.bitboards.black_bishops = 0;
-
-        board.turn = Turn::White;
-
-        board.bitboards.white_bishops = u64::MAX;
+        assert_eq!(moves_of(&mut Board::new(), |bitboards| bitboards.white_bishops).len(), 0);
 
-        let moves = board.bishop_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
-        
-        // test captures 
-        board.bitboards.white_bishops = 1;
-        board.bitboards.black_bishops = 0x302;
-        
-        let moves = board.bishop_moves(&Vec::new(), !0);
+        // test captures
+        let mut board = Board::from_fen("4k3/8/8/7K/8/8/bb6/Bb6 w - - 0 1".to_string());
 
-        assert_eq!(moves.len(), 1);
-        
-}
+        assert_eq!(moves_of(&mut board, |bitboards| bitboards.white_bishops).len(), 1);
+    }
 
     #[test]
     fn test_rooks() {
-        let mut board = Board::empty();
-        board.bitboards.white_rooks = 1;
-
-        for _ in 0..64 {
-            let moves = board.rook_moves(&Vec::new(), !0);
-
-            assert_eq!(moves.len(), 14);
-            
-            board.bitboards.white_rooks <<= 1;
-        }
-        
-        board.bitboards.white_rooks = 0;
-
-        board.turn = Turn::Black;
-        board.bitboards.black_rooks = 1;
-        
-        for _ in 0..64 {
-            let moves = board.rook_moves(&Vec::new(), !0);
-
-            assert_eq!(moves.len(), 14);
-            
-           board.bitboards.black_rooks <<= 1;
+        for i in 0..64 {
+            assert_eq!(lone_piece_moves(Turn::White, i, |bitboards| &mut bitboards.white_rooks), 14, "{}", Square::from(i));
+            assert_eq!(lone_piece_moves(Turn::Black, i, |bitboards| &mut bitboards.black_rooks), 14, "{}", Square::from(i));
         }
 
         // make sure no friendly captures are allowed
-        board.bitboards.black_rooks = u64::MAX;
-        
-        let moves = board.rook_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
-        
-        board.bitboards.black_rooks = 0;
-
-        board.turn = Turn::White;
-
-        board.bitboards.white_rooks = u64::MAX;
-
-        let moves = board.rook_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
-        
-        // test captures 
-        board.bitboards.white_rooks = 1;
-        board.bitboards.black_rooks = 0x302;
-        
-        let moves = board.rook_moves(&Vec::new(), !0);
-
-        assert_eq!(moves.len(), 2);
+        assert_eq!(moves_of(&mut Board::new(), |bitboards| bitboards.white_rooks).len(), 0);
+
+        // test captures
+        let mut board = Board::from_fen("4k3/8/8/7K/8/8/bb6/Rb6 w - - 0 1".to_string());
+
+        assert_eq!(moves_of(&mut board, |bitboards| bitboards.white_rooks).len(), 2);
     }
 
     #[test]
     fn test_queens() {
-        let mut board = Board::empty();
-        board.bitboards.white_queens = 1;
-
         for i in 0..64 {
-            let moves = board.queen_moves(&Vec::new(), !0);
-            let file = Square::from(i).file();
-            let rank = Square::from(i).rank();
-            if rank == Rank::First || rank == Rank::Eighth{
-                assert_eq!(moves.len(), 7 + 14);
-            }else if file == File::A || file == File::H {
-                assert_eq!(moves.len(), 7 + 14);
-            }else if rank == Rank::Second || rank == Rank::Seventh{
-                assert_eq!(moves.len(), 9 + 14);
-            }else if file == File::B || file == File::G {
-                assert_eq!(moves.len(), 9 + 14);
-            }else if file == File::C || file == File::F{
-                assert_eq!(moves.len(), 11 + 14);
-            }else if rank == Rank::Third || rank == Rank::Sixth{
-                assert_eq!(moves.len(), 11 + 14);
-            }else if file == File::D || file == File::E {
-                assert_eq!(moves.len(), 13 + 14);
-            }
-            board.bitboards.white_queens <<= 1;
-        }
-        board.bitboards.white_queens = 0;
-
-        board.turn = Turn::Black;
-        board.bitboards.black_queens = 1;
+            let expected = diagonal_moves(Square::from(i)) + 14;
 
-
-        for i in 0..64 {
-            let moves = board.queen_moves(&Vec::new(), !0);
-            let file = Square::from(i).file();
-            let rank = Square::from(i).rank();
-            if rank == Rank::First || rank == Rank::Eighth{
-                assert_eq!(moves.len(), 7 + 14);
-            }else if file == File::A || file == File::H {
-                assert_eq!(moves.len(), 7 + 14);
-            }else if rank == Rank::Second || rank == Rank::Seventh{
-                assert_eq!(moves.len(), 9 + 14);
-            }else if file == File::B || file == File::G {
-                assert_eq!(moves.len(), 9 + 14);
-            }else if file == File::C || file == File::F{
-                assert_eq!(moves.len(), 11 + 14);
-            }else if rank == Rank::Third || rank == Rank::Sixth{
-                assert_eq!(moves.len(), 11 + 14);
-            }else if file == File::D || file == File::E {
-                assert_eq!(moves.len(), 13 + 14);
-            }
-            board.bitboards.black_queens <<= 1;
+            assert_eq!(lone_piece_moves(Turn::White, i, |bitboards| &mut bitboards.white_queens), expected, "{}", Square::from(i));
+            assert_eq!(lone_piece_moves(Turn::Black, i, |bitboards| &mut bitboards.black_queens), expected, "{}", Square::from(i));
         }
 
         // make sure no friendly captures are allowed
-        board.bitboards.black_queens = u64::MAX;
-        
-        let moves = board.queen_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
-        
-        board.bitboards.black_queens = 0;
-
-        board.turn = Turn::White;
-
-        board.bitboards.white_queens = u64::MAX;
-
-        let moves = board.queen_moves(&Vec::new(), !0);
-        
-        assert_eq!(moves.len(), 0);
+        assert_eq!(moves_of(&mut Board::new(), |bitboards| bitboards.white_queens).len(), 0);
 
         // test captures
-        board.bitboards.white_queens = 1;
-        board.bitboards.black_queens = 0x302;
-        
-        let moves = board.queen_moves(&Vec::new(), !0);
+        let mut board = Board::from_fen("4k3/8/8/7K/8/8/bb6/Qb6 w - - 0 1".to_string());
 
-        assert_eq!(moves.len(), 3);
-        
+        assert_eq!(moves_of(&mut board, |bitboards| bitboards.white_queens).len(), 3);
     }
-    
+
     #[test]
     fn test_king() {
-        let mut board = Board::empty();
-        board.bitboards.white_king = 1;
-
         for i in 0..64 {
-            let moves = board.king_moves();
-            if i == 0 || i == 7 || i == 56 || i == 63 {
-                assert_eq!(moves.len(), 3);
-            }else if i < 8 || i > 55{
-                assert_eq!(moves.len(), 5);
-            }else if Square::from(i).file() == File::A || Square::from(i).file() == File::H {
-                assert_eq!(moves.len(), 5);
+            let square = Square::from(i);
+            let expected = if square == Square::A1 || square == Square::H1 || square == Square::A8 || square == Square::H8 {
+                3
+            }else if square.rank() == Rank::First || square.rank() == Rank::Eighth || square.file() == File::A || square.file() == File::H {
+                5
             }else{
-                assert_eq!(moves.len(), 8);
-            } 
-            
-            board.bitboards.white_king <<= 1;
-        }
-        
-        board.bitboards.white_king = 0;
+                8
+            };
 
-        board.turn = Turn::Black;
-        board.bitboards.black_king = 1;
-
-        for i in 0..64 {
-            let moves = board.king_moves();
-            if i == 0 || i == 7 || i == 56 || i == 63 {
-                assert_eq!(moves.len(), 3);
-            }else if i < 8 || i > 55{
-                assert_eq!(moves.len(), 5);
-            }else if Square::from(i).file() == File::A || Square::from(i).file() == File::H {
-                assert_eq!(moves.len(), 5);
-            }else{
-                assert_eq!(moves.len(), 8);
-            } 
-            
-            board.bitboards.black_king <<= 1;
+            assert_eq!(lone_king_moves(Turn::White, i), expected, "{}", square);
+            assert_eq!(lone_king_moves(Turn::Black, i), expected, "{}", square);
         }
-        
-        board.bitboards.black_king = 0;
-        board.turn = Turn::White;
-        
+
         // test captures
-        board.bitboards.white_king = 1;
-        board.bitboards.black_knights = 0x302;
-        
-        let moves = board.king_moves();
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/nn6/Kn6 w - - 0 1".to_string());
+
+        assert_eq!(moves_of(&mut board, |bitboards| bitboards.white_king).len(), 3);
 
-        assert_eq!(moves.len(), 3);
-        
         // test castling
         let mut board = Board::new();
         board.bitboards.white_bishops = 0;
@@ -453,42 +198,199 @@ mod tests {
         board.bitboards.black_bishops = 0;
         board.bitboards.black_knights = 0;
         board.bitboards.black_queens = 0;
-        
+        board.update_position_state();
 
-        let moves = board.king_moves();
+        let moves = moves_of(&mut board, |bitboards| bitboards.white_king);
 
         assert_eq!(moves.len(), 4);
-        
-        board.make_move(Move::encode(Square::E1 as u8, Square::G1 as u8, Move::KING_CASTLE)); 
-        
+
+        board.make_move(Move::encode(Square::E1 as u8, Square::G1 as u8, Move::KING_CASTLE));
+
         assert_eq!(board.bitboards.white_king.trailing_zeros() as u8, Square::G1 as u8);
         assert_eq!((board.bitboards.white_rooks & (1 << Square::F1 as u8)).trailing_zeros() as u8, Square::F1 as u8);
-        
-        let moves = board.king_moves();
+
+        let moves = moves_of(&mut board, |bitboards| bitboards.black_king);
 
         assert_eq!(moves.len(), 4);
 
         board.make_move(Move::encode(Square::E8 as u8, Square::C8 as u8, Move::QUEEN_CASTLE));
-        
+
         assert_eq!(board.bitboards.black_king.trailing_zeros() as u8, Square::C8 as u8);
         assert_eq!((board.bitboards.black_rooks & (1 << Square::D8 as u8)).trailing_zeros() as u8, Square::D8 as u8);
-        
-        let moves = board.king_moves();
-        
-        assert_eq!(board.castling_rights.white_king_side, false);
+
+        let moves = moves_of(&mut board, |bitboards| bitboards.white_king);
+
+        assert!(!board.castling_rights.white_king_side);
 
         assert_eq!(moves.len(), 1);
 
         board.make_move(*moves.last().unwrap());
-        
-        let moves = board.king_moves();
-        
-        assert_eq!(board.castling_rights.black_queen_side, false);
+
+        let moves = moves_of(&mut board, |bitboards| bitboards.black_king);
+
+        assert!(!board.castling_rights.black_queen_side);
 
         assert_eq!(moves.len(), 1);
 
         board.make_move(*moves.last().unwrap());
-        
+    }
+
+    #[test]
+    fn test_captures() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 0",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        // captures (en passant included) and promotions, the moves quiescence search looks at
+        fn legal_captures(board: &Board) -> Vec<String> {
+            let mut moves = MoveList::new();
+            if board.checkers != 0 {
+                board.generate_evasions(&mut moves);
+                moves.retain(|evasion| !evasion.is_quiet());
+            } else {
+                board.generate_noisy(&mut moves);
+            }
+            let pinned = board.pinned();
+            let mut captures: Vec<String> = moves.iter().filter(|noisy| board.is_legal(**noisy, pinned)).map(|capture| capture.to_string()).collect();
+            captures.sort();
+            captures
+        }
+
+        // every position two plies deep, checks and pins included
+        for fen in fens {
+            let mut board = Board::from_fen(fen.to_string());
+            for first_move in board.generate_legal_moves() {
+                board.make_move(first_move);
+                for second_move in board.generate_legal_moves() {
+                    board.make_move(second_move);
+
+                    let mut expected: Vec<String> = board.generate_legal_moves()
+                        .into_iter()
+                        .filter(|legal| legal.is_capture() || legal.get_promotion_piece().is_some())
+                        .map(|legal| legal.to_string())
+                        .collect();
+                    expected.sort();
+                    assert_eq!(legal_captures(&board), expected, "{} {} {}", fen, first_move, second_move);
+
+                    board.undo_move();
+                }
+                board.undo_move();
+            }
+        }
+    }
 
+    #[test]
+    fn test_undo_restores_state() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 0",
+        ];
+
+        fn snapshot(board: &Board) -> (u64, [bool; 4], Option<Square>, u8, u64, usize, bool) {
+            let rights = board.castling_rights;
+            (board.zobrist_key, [rights.white_king_side, rights.white_queen_side, rights.black_king_side, rights.black_queen_side],
+                board.en_passant_square, board.half_move_clock, board.checkers, board.accumulator_index, board.turn == Turn::White)
+        }
+
+        // captures, promotions, castling and en passant two plies deep, with a null move in between
+        for fen in fens {
+            let mut board = Board::from_fen(fen.to_string());
+            let root = snapshot(&board);
+            let root_bitboards = board.bitboards;
+
+            for first_move in board.generate_legal_moves() {
+                board.make_move(first_move);
+                let after_first = snapshot(&board);
+                let first_bitboards = board.bitboards;
+                assert_eq!(board.checkers, board.find_checkers(), "{} {}", fen, first_move);
+
+                if board.checkers == 0 {
+                    board.make_null_move();
+                    assert_eq!(board.last_move(), None);
+                    board.undo_null_move();
+                    assert_eq!(snapshot(&board), after_first, "{} {} null move", fen, first_move);
+                }
+
+                for second_move in board.generate_legal_moves() {
+                    board.make_move(second_move);
+                    assert_eq!(board.last_move(), Some(second_move));
+                    board.undo_move();
+                    assert_eq!(snapshot(&board), after_first, "{} {} {}", fen, first_move, second_move);
+                    assert!(board.bitboards == first_bitboards, "{} {} {}", fen, first_move, second_move);
+                }
+
+                board.undo_move();
+                assert_eq!(snapshot(&board), root, "{} {}", fen, first_move);
+                assert!(board.bitboards == root_bitboards, "{} {}", fen, first_move);
+            }
+            assert!(board.state_stack.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_draws() {
+        fn play(board: &mut Board, uci_moves: &[&str]) {
+            for uci_move in uci_moves {
+                let legal = board.generate_legal_moves().into_iter().find(|legal| legal.to_string() == *uci_move).unwrap();
+                board.make_move(legal);
+            }
+        }
+
+        // the knights going out and back repeat the starting position
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut board = Board::new();
+        play(&mut board, &shuffle);
+        // on the board twice, a draw only if the first time was inside the search
+        assert!(!board.is_draw(0));
+        assert!(!board.is_draw(4));
+        assert!(board.is_draw(5));
+        play(&mut board, &shuffle);
+        assert!(board.is_draw(0));
+        board.undo_move();
+        assert!(!board.is_draw(0));
+
+        // a pawn move makes the earlier positions unreachable, the clock starts over
+        let mut board = Board::new();
+        play(&mut board, &shuffle);
+        play(&mut board, &["e2e4", "e7e5"]);
+        assert_eq!(board.half_move_clock, 0);
+        play(&mut board, &shuffle);
+        assert_eq!(board.half_move_clock, 4);
+
+        let mut board = Board::from_fen("8/8/8/4k3/8/8/8/R3K3 w - - 99 80".to_string());
+        assert!(!board.is_draw(0));
+        play(&mut board, &["a1a2"]);
+        assert!(board.is_draw(0));
+        board.undo_move();
+        assert_eq!(board.half_move_clock, 99);
+
+        // mate on the hundredth ply still counts
+        let mut board = Board::from_fen("k7/8/1K6/8/8/8/8/7R w - - 99 80".to_string());
+        play(&mut board, &["h1h8"]);
+        assert!(!board.is_draw(0));
+
+        let insufficient = [
+            "8/8/8/4k3/8/8/8/4K3 w - - 0 1",
+            "8/8/8/4k3/8/8/8/3NK3 w - - 0 1",
+            "8/8/8/4k3/8/8/8/2B1K3 b - - 0 1",
+            "8/8/3b4/4k3/8/8/8/2B1K3 w - - 0 1",
+        ];
+        for fen in insufficient {
+            assert!(Board::from_fen(fen.to_string()).is_draw(0), "{}", fen);
+        }
+        let sufficient = [
+            "8/8/4b3/4k3/8/8/8/2B1K3 w - - 0 1",
+            "8/8/8/4k3/8/8/8/2NNK3 w - - 0 1",
+            "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1",
+            "8/8/8/4k3/8/8/8/3NK2b w - - 0 1",
+        ];
+        for fen in sufficient {
+            assert!(!Board::from_fen(fen.to_string()).is_draw(0), "{}", fen);
+        }
     }
-}
\ No newline at end of file
+}
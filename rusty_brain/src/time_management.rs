@@ -0,0 +1,125 @@
+use std::time::{Duration, Instant};
+use crate::board::Turn;
+
+// Time kept in reserve for every move to cover engine/gui communication
+pub const MOVE_OVERHEAD_MS: u64 = 30;
+// How many moves the remaining clock is spread over when the gui doesn't send movestogo
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+const MAX_MOVES_TO_GO: u64 = 50;
+
+// The clock fields of a uci go command, all times in milliseconds
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchLimits {
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u64>,
+    pub movetime: Option<u64>,
+}
+
+// Deadlines of the current move.
+//  soft limit: iterative deepening doesn't start a new iteration after it
+//  hard limit: the running iteration is abandoned when it is reached
+#[derive(Debug, Clone, Copy)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+}
+
+impl TimeManager {
+    // No deadlines, the search only stops at its depth limit
+    pub fn infinite() -> Self {
+        TimeManager {
+            start: Instant::now(),
+            soft_limit: None,
+            hard_limit: None,
+        }
+    }
+
+    pub fn new(limits: &SearchLimits, turn: Turn) -> Self {
+        let mut time_manager = Self::infinite();
+
+        if let Some(movetime) = limits.movetime {
+            let limit = Duration::from_millis(movetime.saturating_sub(MOVE_OVERHEAD_MS).max(1));
+            time_manager.soft_limit = Some(limit);
+            time_manager.hard_limit = Some(limit);
+            return time_manager;
+        }
+
+        let (time_left, increment) = match turn {
+            Turn::White => (limits.wtime, limits.winc.unwrap_or(0)),
+            Turn::Black => (limits.btime, limits.binc.unwrap_or(0)),
+        };
+
+        if let Some(time_left) = time_left {
+            let available = time_left.saturating_sub(MOVE_OVERHEAD_MS).max(1);
+            let moves_to_go = limits.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, MAX_MOVES_TO_GO);
+
+            // an even share of the clock plus most of the increment, the hard limit lets a
+            // troubled iteration run a few times longer but never spends most of the clock
+            let soft = available / moves_to_go + increment * 3 / 4;
+            let hard = (soft * 4).min(available * 3 / 4).max(1);
+
+            time_manager.soft_limit = Some(Duration::from_millis(soft.min(hard)));
+            time_manager.hard_limit = Some(Duration::from_millis(hard));
+        }
+
+        time_manager
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn is_time_limited(&self) -> bool {
+        self.hard_limit.is_some()
+    }
+
+    pub fn soft_limit_reached(&self) -> bool {
+        self.soft_limit.is_some_and(|limit| self.elapsed() >= limit)
+    }
+
+    pub fn hard_limit_reached(&self) -> bool {
+        self.hard_limit.is_some_and(|limit| self.elapsed() >= limit)
+    }
+}
+
+#[cfg(test)]
+mod time_management {
+    use std::time::Duration;
+
+    use crate::board::Turn;
+
+    use super::{SearchLimits, TimeManager};
+
+    #[test]
+    fn movetime_is_a_fixed_deadline() {
+        let limits = SearchLimits { movetime: Some(1000), wtime: Some(5000), ..SearchLimits::default() };
+        let time_manager = TimeManager::new(&limits, Turn::White);
+        assert_eq!(time_manager.soft_limit, Some(Duration::from_millis(970)));
+        assert_eq!(time_manager.hard_limit, Some(Duration::from_millis(970)));
+    }
+
+    #[test]
+    fn clock_is_shared_between_moves() {
+        let limits = SearchLimits { wtime: Some(1000), btime: Some(60_030), binc: Some(1000), ..SearchLimits::default() };
+
+        let time_manager = TimeManager::new(&limits, Turn::Black);
+        assert_eq!(time_manager.soft_limit, Some(Duration::from_millis(2750)));
+        assert_eq!(time_manager.hard_limit, Some(Duration::from_millis(11_000)));
+
+        // with a single move to go the hard limit still keeps a quarter of the clock
+        let limits = SearchLimits { movestogo: Some(1), ..limits };
+        let time_manager = TimeManager::new(&limits, Turn::Black);
+        assert_eq!(time_manager.hard_limit, Some(Duration::from_millis(45_000)));
+        assert_eq!(time_manager.soft_limit, time_manager.hard_limit);
+    }
+
+    #[test]
+    fn no_clock_means_no_deadline() {
+        let limits = SearchLimits { btime: Some(1000), ..SearchLimits::default() };
+        assert!(!TimeManager::new(&limits, Turn::White).is_time_limited());
+    }
+}
@@ -1,12 +1,16 @@
-use crate::zobrist::Zobrist;
+use crate::alphabeta::MATE_IN_MAX_PLY;
 use crate::movement::Move;
 use crate::board::Board;
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 
-pub struct TranspositionTable {
-    zobrist_key: Zobrist,
-    table: HashMap<u64,TTEntry>,
-}
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 65536;
+// Entries are grouped in buckets of 32 bytes, a position can only be stored in its own bucket
+const BUCKET_SIZE: usize = 4;
+// Each generation an entry is older counts as this much depth when picking one to replace
+const AGE_PENALTY: i32 = 8;
+
+#[derive(Debug, Clone, Copy)]
 pub struct TTEntry {
     pub best_move: Option<Move>, 
     pub depth: i32,          
@@ -14,28 +18,172 @@ pub struct TTEntry {
     pub node_type: Node,   
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Node {
     Exact,       // Exact (PV-Node)
     UpperBound,  // Upper bound (All-Node) , so it's <= alpha
     LowerBound,  // Lower bound (Cut-Node) , so it's >= beta
 }
 
+// Every entry is packed into a single u64 so it can be read and written atomically,
+// searching threads share the table without locks and never see half written entries.
+//  bits  0-15: upper 16 bits of the position hash, the bucket index covers the rest
+//  bits 16-31: best move, 0 for none
+//  bits 32-47: score
+//  bits 48-55: depth
+//  bits 56-57: bound, 0 marks an empty entry
+//  bits 58-63: generation of the search that stored it
+fn pack(key: u16, best_move: Option<Move>, score: i32, depth: i32, node_type: Node, generation: u8) -> u64 {
+    let bound = match node_type {
+        Node::Exact => 1,
+        Node::UpperBound => 2,
+        Node::LowerBound => 3,
+    };
+    key as u64
+        | (best_move.map_or(0, |best_move| best_move.to_u16()) as u64) << 16
+        | (score as i16 as u16 as u64) << 32
+        | (depth.clamp(0, u8::MAX as i32) as u64) << 48
+        | bound << 56
+        | ((generation & 0x3F) as u64) << 58
+}
+
+fn key_of(data: u64) -> u16 {
+    data as u16
+}
+
+fn depth_of(data: u64) -> i32 {
+    (data >> 48) as u8 as i32
+}
+
+fn bound_of(data: u64) -> u64 {
+    (data >> 56) & 0x3
+}
+
+fn generation_of(data: u64) -> u8 {
+    (data >> 58) as u8
+}
+
+fn unpack(data: u64) -> TTEntry {
+    let encoded_move = (data >> 16) as u16;
+    TTEntry {
+        best_move: if encoded_move == 0 { None } else { Some(Move::from_u16(encoded_move)) },
+        depth: depth_of(data),
+        score: (data >> 32) as u16 as i16 as i32,
+        node_type: match bound_of(data) {
+            1 => Node::Exact,
+            2 => Node::UpperBound,
+            _ => Node::LowerBound,
+        },
+    }
+}
+
+pub struct TranspositionTable {
+    entries: Vec<AtomicU64>,
+    // bumped at the start of every search so old entries are the first to go
+    generation: AtomicU8,
+}
+
+// Mate scores are stored as the distance to mate from the stored node instead of from the root,
+// the same position can be reached at different plies and the entry has to be right for all of them
+pub fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_IN_MAX_PLY {
+        score + ply
+    } else if score <= -MATE_IN_MAX_PLY {
+        score - ply
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_IN_MAX_PLY {
+        score - ply
+    } else if score <= -MATE_IN_MAX_PLY {
+        score + ply
+    } else {
+        score
+    }
+}
+
 impl TranspositionTable {
     pub fn init() -> Self {
-        //init the zobrist (fill random values)
+        Self::new(DEFAULT_HASH_MB)
+    }
+
+    // A table taking size_mb megabytes, rounded down to whole buckets
+    pub fn new(size_mb: usize) -> Self {
+        let buckets = (size_mb.clamp(1, MAX_HASH_MB) * 1024 * 1024 / (BUCKET_SIZE * 8)).max(1);
         TranspositionTable {
-            zobrist_key: Zobrist::init_zobrist(),
-            table: HashMap::new(),
+            entries: (0..buckets * BUCKET_SIZE).map(|_| AtomicU64::new(0)).collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    pub fn clear(&self) {
+        for entry in &self.entries {
+            entry.store(0, Ordering::Relaxed);
         }
+        self.generation.store(0, Ordering::Relaxed);
     }
 
-    pub fn retrieve_from_table(&self, board: &mut Board) -> Option<&TTEntry> {
-        let position_hash = self.zobrist_key.zobrist_hash(&board);
-        self.table.get(&position_hash)
+    pub fn new_search(&self) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        self.generation.store(generation.wrapping_add(1) & 0x3F, Ordering::Relaxed);
     }
 
-    pub fn store_in_table(&mut self, board: &mut Board, best_move: Option<Move>, depth_left: i32, best_value: i32, alpha: i32, beta: i32) {
-        let position_hash = self.zobrist_key.zobrist_hash(&board);
+    // The lower 48 bits of the hash pick the bucket, the upper 16 are the key within it
+    fn bucket(&self, hash: u64) -> &[AtomicU64] {
+        let index = ((hash & 0xFFFF_FFFF_FFFF) % (self.entries.len() / BUCKET_SIZE) as u64) as usize * BUCKET_SIZE;
+        &self.entries[index..index + BUCKET_SIZE]
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TTEntry> {
+        let key = (hash >> 48) as u16;
+        self.bucket(hash)
+            .iter()
+            .map(|entry| entry.load(Ordering::Relaxed))
+            .find(|&data| bound_of(data) != 0 && key_of(data) == key)
+            .map(unpack)
+    }
+
+    pub fn store(&self, hash: u64, best_move: Option<Move>, depth: i32, score: i32, node_type: Node) {
+        let key = (hash >> 48) as u16;
+        let generation = self.generation.load(Ordering::Relaxed);
+        let bucket = self.bucket(hash);
+
+        // the entry of the same position if there is one, otherwise the emptiest, oldest or shallowest
+        let slot = bucket.iter()
+            .find(|entry| {
+                let data = entry.load(Ordering::Relaxed);
+                bound_of(data) != 0 && key_of(data) == key
+            })
+            .unwrap_or_else(|| {
+                bucket.iter()
+                    .min_by_key(|entry| {
+                        let data = entry.load(Ordering::Relaxed);
+                        if bound_of(data) == 0 {
+                            return i32::MIN;
+                        }
+                        let age = (generation.wrapping_sub(generation_of(data)) & 0x3F) as i32;
+                        depth_of(data) - AGE_PENALTY * age
+                    })
+                    .unwrap()
+            });
+
+        // a search that didn't find a best move keeps the one already known for the position
+        let previous = slot.load(Ordering::Relaxed);
+        let best_move = best_move.or_else(|| {
+            if bound_of(previous) != 0 && key_of(previous) == key { unpack(previous).best_move } else { None }
+        });
+        slot.store(pack(key, best_move, score, depth, node_type, generation), Ordering::Relaxed);
+    }
+
+    pub fn retrieve_from_table(&self, board: &Board) -> Option<TTEntry> {
+        self.probe(board.zobrist_key)
+    }
+
+    pub fn store_in_table(&self, board: &Board, best_move: Option<Move>, depth_left: i32, best_value: i32, alpha: i32, beta: i32) {
+        let position_hash = board.zobrist_key;
         let node = if best_value <= alpha {
             Node::UpperBound
         } else if best_value >= beta {
@@ -44,14 +192,79 @@ impl TranspositionTable {
             Node::Exact
         };
 
-        self.table.insert(
-            position_hash,
-            TTEntry {
-                best_move: best_move,
-                depth: depth_left,
-                score: best_value,
-                node_type: node,
-            },
-        );
-    }
-}
\ No newline at end of file
+        self.store(position_hash, best_move, depth_left, best_value, node);
+    }
+
+    // How full the table is in permille, as uci hashfull reports it.
+    // Only entries of the current search count, sampled from the first thousand.
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = &self.entries[..self.entries.len().min(1000)];
+        let used = sample.iter()
+            .map(|entry| entry.load(Ordering::Relaxed))
+            .filter(|&data| bound_of(data) != 0 && generation_of(data) == generation)
+            .count();
+        used * 1000 / sample.len()
+    }
+}
+
+#[cfg(test)]
+mod transposition {
+    use crate::movement::Move;
+
+    use super::{Node, TranspositionTable};
+
+    #[test]
+    fn entries_round_trip() {
+        let table = TranspositionTable::new(1);
+        let best_move = Move::encode(12, 28, Move::DOUBLE_PAWN_PUSH);
+        table.store(0xDEAD_BEEF_1234_5678, Some(best_move), 7, -31_990, Node::LowerBound);
+
+        let entry = table.probe(0xDEAD_BEEF_1234_5678).unwrap();
+        assert_eq!(entry.best_move, Some(best_move));
+        assert_eq!(entry.depth, 7);
+        assert_eq!(entry.score, -31_990);
+        assert_eq!(entry.node_type, Node::LowerBound);
+
+        // same bucket, different key
+        assert!(table.probe(0xBEEF_BEEF_1234_5678).is_none());
+    }
+
+    #[test]
+    fn replaces_old_and_shallow_entries() {
+        let table = TranspositionTable::new(1);
+        let buckets = (table.entries.len() / 4) as u64;
+        let same_bucket = |n: u64| (n << 48) | (42 % buckets);
+
+        for depth in 1..=4 {
+            table.store(same_bucket(depth as u64), None, depth * 2, 0, Node::Exact);
+        }
+        // the bucket is full, the shallowest entry makes room
+        table.store(same_bucket(5), None, 3, 0, Node::Exact);
+        assert!(table.probe(same_bucket(1)).is_none());
+        assert!(table.probe(same_bucket(2)).is_some());
+
+        // entries of older searches go first even when they are deeper
+        table.new_search();
+        table.new_search();
+        table.store(same_bucket(6), None, 1, 0, Node::Exact);
+        table.store(same_bucket(7), None, 1, 0, Node::Exact);
+        assert!(table.probe(same_bucket(6)).is_some());
+        assert!(table.probe(same_bucket(7)).is_some());
+        assert_eq!(table.probe(same_bucket(4)).unwrap().depth, 8);
+    }
+
+    #[test]
+    fn hashfull_and_clear() {
+        let table = TranspositionTable::new(1);
+        assert_eq!(table.hashfull(), 0);
+        for hash in 0..250u64 {
+            table.store(hash, None, 1, 0, Node::Exact);
+        }
+        assert_eq!(table.hashfull(), 250);
+
+        table.clear();
+        assert_eq!(table.hashfull(), 0);
+        assert!(table.probe(0).is_none());
+    }
+}
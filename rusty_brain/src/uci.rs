@@ -1,13 +1,45 @@
 use std::io::{self, Write};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::alphabeta::{SearchConfig, SearchInfo, SearchSignals, MAX_DEPTH, MAX_THREADS};
 use crate::board::Board;
-use crate::movement::Move;
-use crate::square::Square;
-use crate::transposition::TranspositionTable;
+use crate::evaluator::{EvalTiming, EvalType, Evaluator};
+use crate::kan_eval::{KanEvaluator, KanNetwork, UNTRAINED};
+use crate::nnue::{Network, NnueEvaluator, NNUE};
+use crate::simd::{self, Backend};
+use crate::time_management::{SearchLimits, TimeManager};
+use crate::transposition::{TranspositionTable, DEFAULT_HASH_MB, MAX_HASH_MB};
+// EvalFile value that stands for the network compiled into the engine
+const EMBEDDED_NETWORK: &str = "<embedded>";
+// KanFile value of the untrained network the KAN evaluates with until a file is loaded
+const UNTRAINED_KAN: &str = "<untrained>";
+// positions the bench command searches and how deep, unless it is given another depth
+const BENCH_FENS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+];
+const BENCH_DEPTH: i32 = 7;
+
 pub struct Uci {
     current_board: Board,
     // default_depth: u8,
     depth: i32,
-    transposition_table: TranspositionTable,
+    // shared with the search thread, the table itself is safe to use from several threads
+    transposition_table: Arc<TranspositionTable>,
+    search_config: SearchConfig,
+    eval_type: EvalType,
+    // the net NNUE evaluators of the next searches use, replaced by the EvalFile option
+    network: Arc<Network>,
+    // the same for KAN evaluators, replaced by the KanFile option
+    kan_network: Arc<KanNetwork>,
+    signals: Arc<SearchSignals>,
+    search_thread: Option<JoinHandle<()>>,
 
 
 
@@ -23,7 +55,13 @@ impl Uci {
         Uci {
             current_board : Board::new(),
             depth: 6,
-            transposition_table : TranspositionTable::init(),
+            transposition_table : Arc::new(TranspositionTable::init()),
+            search_config: SearchConfig::default(),
+            eval_type: EvalType::Nnue,
+            network: Arc::clone(&NNUE),
+            kan_network: Arc::clone(&UNTRAINED),
+            signals: Arc::new(SearchSignals::default()),
+            search_thread: None,
 
 
         }
@@ -48,6 +86,7 @@ impl Uci {
             // println!("Paramters: {:?}", params);
 
             if command.eq_ignore_ascii_case("quit") {
+                self.stop();
                 println!("Goodbye!");
                 break;
             }
@@ -63,6 +102,11 @@ impl Uci {
             "uci" => self.uci(),
             "go" => self.go(params),
             "position" => self.position(params),
+            "setoption" => self.setoption(params),
+            "ucinewgame" => self.ucinewgame(),
+            "stop" => self.stop(),
+            "ponderhit" => self.ponderhit(),
+            "bench" => self.bench(params),
             _ => self.unknown_command(command)
         }
     }
@@ -74,7 +118,31 @@ impl Uci {
     fn uci(&self) {
         println!("id name rough hook");
         println!("id author rough hook team");
-        //insert options when done
+        let defaults = SearchConfig::default();
+        println!("option name AlphaBeta type check default {}", defaults.alpha_beta);
+        println!("option name TranspositionTable type check default {}", defaults.transposition_table);
+        println!("option name Hash type spin default {} min 1 max {}", DEFAULT_HASH_MB, MAX_HASH_MB);
+        println!("option name Clear Hash type button");
+        println!("option name Threads type spin default {} min 1 max {}", defaults.threads, MAX_THREADS);
+        println!("option name Ponder type check default false");
+        println!("option name IterativeDeepening type check default {}", defaults.iterative_deepening);
+        println!("option name AspirationWindows type check default {}", defaults.aspiration_windows);
+        println!("option name AspirationDelta type spin default {} min 1 max 1000", defaults.aspiration_delta);
+        println!("option name Quiescence type check default {}", defaults.quiescence);
+        println!("option name QuiescenceEvasions type check default {}", defaults.quiescence_evasions);
+        println!("option name SeePruning type check default {}", defaults.see_pruning);
+        println!("option name MoveOrdering type check default {}", defaults.move_ordering);
+        println!("option name PrincipalVariationSearch type check default {}", defaults.principal_variation_search);
+        println!("option name NullMovePruning type check default {}", defaults.null_move_pruning);
+        println!("option name LateMoveReductions type check default {}", defaults.late_move_reductions);
+        println!("option name FutilityPruning type check default {}", defaults.futility_pruning);
+        println!("option name ReverseFutilityPruning type check default {}", defaults.reverse_futility_pruning);
+        println!("option name Razoring type check default {}", defaults.razoring);
+        println!("option name CheckExtensions type check default {}", defaults.check_extensions);
+        let eval_types: Vec<String> = EvalType::ALL.iter().map(|eval_type| format!("var {}", eval_type.name())).collect();
+        println!("option name EvalType type combo default {} {}", EvalType::Nnue.name(), eval_types.join(" "));
+        println!("option name EvalFile type string default {}", EMBEDDED_NETWORK);
+        println!("option name KanFile type string default {}", UNTRAINED_KAN);
         println!("uciok")
     }
 
@@ -82,8 +150,102 @@ impl Uci {
         println!("unknown command \"{}\". please enter a valid command.", input);
     }
 
-    fn setoption(&self){
-        //will return to later 
+    fn setoption(&mut self, input_params: Vec<&str>){
+        let parameters = vec!["name", "value"];
+        let vector = self.filter_by_params(parameters, input_params);
+        let mut name = String::new();
+        let mut value = String::new();
+        for (param, param_value) in vector {
+            match param.as_str() {
+                "name" => name = param_value,
+                "value" => value = param_value,
+                _ => ()
+            }
+        }
+
+        // option names are case insensitive in the uci protocol
+        let enabled = value.eq_ignore_ascii_case("true");
+        match name.to_lowercase().as_str() {
+            "alphabeta" => self.search_config.alpha_beta = enabled,
+            "transpositiontable" => self.search_config.transposition_table = enabled,
+            "hash" => match value.parse() {
+                Ok(size_mb) => {
+                    // the running search still holds the old table
+                    self.stop();
+                    self.transposition_table = Arc::new(TranspositionTable::new(size_mb));
+                },
+                Err(_) => println!("info string invalid value \"{}\" for option {}", value, name),
+            },
+            "clear hash" => self.transposition_table.clear(),
+            "threads" => match value.parse::<usize>() {
+                Ok(threads) => self.search_config.threads = threads.clamp(1, MAX_THREADS),
+                Err(_) => println!("info string invalid value \"{}\" for option {}", value, name),
+            },
+            // go ponder works either way, the option only tells the gui it may send it
+            "ponder" => (),
+            "iterativedeepening" => self.search_config.iterative_deepening = enabled,
+            "aspirationwindows" => self.search_config.aspiration_windows = enabled,
+            "quiescence" => self.search_config.quiescence = enabled,
+            "quiescenceevasions" => self.search_config.quiescence_evasions = enabled,
+            "seepruning" => self.search_config.see_pruning = enabled,
+            "moveordering" => self.search_config.move_ordering = enabled,
+            "principalvariationsearch" => self.search_config.principal_variation_search = enabled,
+            "nullmovepruning" => self.search_config.null_move_pruning = enabled,
+            "latemovereductions" => self.search_config.late_move_reductions = enabled,
+            "futilitypruning" => self.search_config.futility_pruning = enabled,
+            "reversefutilitypruning" => self.search_config.reverse_futility_pruning = enabled,
+            "razoring" => self.search_config.razoring = enabled,
+            "checkextensions" => self.search_config.check_extensions = enabled,
+            "aspirationdelta" => match value.parse() {
+                Ok(delta) => self.search_config.aspiration_delta = delta,
+                Err(_) => println!("info string invalid value \"{}\" for option {}", value, name),
+            },
+            "evaltype" => match EvalType::from_name(&value) {
+                Some(eval_type) => {
+                    self.eval_type = eval_type;
+                    if eval_type == EvalType::Kan && Arc::ptr_eq(&self.kan_network, &UNTRAINED) {
+                        println!("info string no KanFile loaded, the untrained KAN evaluates every position as equal");
+                    }
+                },
+                None => println!("info string invalid value \"{}\" for option {}", value, name),
+            },
+            "evalfile" => {
+                if value.is_empty() || value == EMBEDDED_NETWORK {
+                    self.network = Arc::clone(&NNUE);
+                    return;
+                }
+                // a search that is running keeps the net it started with
+                match Network::load(&value) {
+                    Ok(network) => {
+                        self.network = Arc::from(network);
+                        println!("info string loaded network {}", value);
+                    },
+                    Err(error) => println!("info string invalid value \"{}\" for option {}: {}", value, name, error),
+                }
+            },
+            "kanfile" => {
+                if value.is_empty() || value == UNTRAINED_KAN {
+                    self.kan_network = Arc::clone(&UNTRAINED);
+                    return;
+                }
+                match KanNetwork::load(&value) {
+                    Ok(network) => {
+                        self.kan_network = Arc::new(network);
+                        println!("info string loaded KAN network {}", value);
+                    },
+                    Err(error) => println!("info string invalid value \"{}\" for option {}: {}", value, name, error),
+                }
+            },
+            _ => println!("info string unknown option \"{}\"", name),
+        }
+    }
+
+    fn create_evaluator(&self) -> Box<dyn Evaluator> {
+        match self.eval_type {
+            EvalType::Nnue => Box::new(NnueEvaluator::new(Arc::clone(&self.network))),
+            EvalType::Kan => Box::new(KanEvaluator::new(Arc::clone(&self.kan_network))),
+            eval_type => eval_type.create(),
+        }
     }
 
     fn position(&mut self, input_params: Vec<&str>){
@@ -92,48 +254,21 @@ impl Uci {
         for (param, value) in vector {
             match param.as_str(){
                 "startpos" => self.current_board = Board::new(),
-                "fen" => self.current_board = Board::from_fen(value),
+                "fen" => match Board::try_from_fen(&value) {
+                    Ok(board) => self.current_board = board,
+                    Err(error) => {
+                        println!("info string invalid fen \"{}\": {}", value, error);
+                        return;
+                    }
+                },
                 "moves" => {
-                    let moves = value.split_whitespace();
-                    for one_move in moves {
-                        let (from, to) = one_move.split_at(2);
-                        let (to, promotion) = if to.len() == 3 {
-                            to.split_at(2)
-                        }else {
-                            (to, "")
-                        };
-                        let generated_moves = self.current_board.generate_legal_moves();
-                        for gen_move in generated_moves {
-                            if Square::from(gen_move.get_from()) == Square::from(from) && (Square::from(gen_move.get_to()) == Square::from(to)) {
-                                match promotion {
-                                    "n" => {
-                                        match gen_move.get_flags() {
-                                            Move::KNIGHT_PROMOTION | Move::KNIGHT_PROMO_CAPTURE => (),
-                                            _ => continue 
-                                        }
-                                    },
-                                    "b" => {
-                                        match gen_move.get_flags() {
-                                            Move::BISHOP_PROMOTION | Move::BISHOP_PROMO_CAPTURE => (),
-                                            _ => continue 
-                                        }
-                                    },
-                                    "r" => {
-                                        match gen_move.get_flags() {
-                                            Move::ROOK_PROMOTION | Move::ROOK_PROMO_CAPTURE => (),
-                                            _ => continue 
-                                        }
-                                    },
-                                    "q" => {
-                                        match gen_move.get_flags() {
-                                            Move::QUEEN_PROMOTION | Move::QUEEN_PROMO_CAPTURE => (),
-                                            _ => continue 
-                                        }
-                                    },
-                                    _ => ()
-                                }
-                                self.current_board.make_move(gen_move);
-                            } 
+                    for one_move in value.split_whitespace() {
+                        match self.current_board.parse_uci_move(one_move) {
+                            Ok(parsed_move) => self.current_board.make_move(parsed_move),
+                            Err(error) => {
+                                println!("info string {}", error);
+                                break;
+                            }
                         }
                     }
                     // self.current_board.print_board();
@@ -146,8 +281,9 @@ impl Uci {
     fn ucinewgame(&mut self){
         //clears hash and any information collected abou previous games.
         //should call isready after to check if it's done clearing, which would return readyok
+        self.stop();
         self.current_board = Board::new();
-        self.transposition_table = TranspositionTable::init()
+        self.transposition_table.clear();
     }
 
     fn go(&mut self, input_params: Vec<&str>){
@@ -155,27 +291,124 @@ impl Uci {
         "UCI_showWDL", "searchmoves", "ponder", "wtime", "btime",
         "winc", "binc", "movestogo", "movetime", "perft"];
         let vector = self.filter_by_params(parameters,input_params);
+        let mut depth = None;
+        let mut limits = SearchLimits::default();
+        let mut infinite = false;
+        let mut ponder = false;
         for (param, value) in vector {
             match param.as_str() {
-                "depth" => self.depth = value.parse().expect("error parsing depth failed"),
+                "depth" => depth = Some(value.parse().expect("error parsing depth failed")),
+                "infinite" => infinite = true,
+                "ponder" => ponder = true,
+                "wtime" => limits.wtime = value.parse().ok(),
+                "btime" => limits.btime = value.parse().ok(),
+                "winc" => limits.winc = value.parse().ok(),
+                "binc" => limits.binc = value.parse().ok(),
+                "movestogo" => limits.movestogo = value.parse().ok(),
+                "movetime" => limits.movetime = value.parse().ok(),
                 _ => ()
             };
         }
 
-        let best_move = self.current_board.find_best_move(&mut self.transposition_table, self.depth);
-        // self.current_board.print_board(); // print board
-        println!("bestmove {}", best_move.0)
-    }
+        // a new go while searching is a gui error, the old search is abandoned
+        self.stop();
 
-    fn stop(&self){
+        let mut search_info = SearchInfo::new(self.search_config, self.create_evaluator());
+        if !infinite {
+            search_info.time_manager = TimeManager::new(&limits, self.current_board.turn);
+        }
+        self.signals = Arc::new(SearchSignals::default());
+        self.signals.ponder.store(ponder, Ordering::Relaxed);
+        search_info.signals = Arc::clone(&self.signals);
+        search_info.report = true;
+
+        // a clock or go infinite lets the search go as deep as it can, otherwise the default depth is used
+        let unbounded = infinite || search_info.time_manager.is_time_limited();
+        let depth = depth.unwrap_or(if unbounded { MAX_DEPTH } else { self.depth });
+
+        // the search works on its own copy of the board so the stdin loop stays free to
+        // answer isready, stop and ponderhit while it runs
+        let mut board = self.current_board.clone();
+        let transposition_table = Arc::clone(&self.transposition_table);
+        let signals = Arc::clone(&self.signals);
+        self.search_thread = Some(thread::spawn(move || {
+            let best_move = board.search(&transposition_table, &mut search_info, depth);
+            if let Some(timing) = search_info.eval_timing {
+                println!("info string {}", describe_timing(search_info.evaluator.name(), timing));
+            }
 
+            // in infinite and ponder mode the gui only expects bestmove after stop or ponderhit
+            while (infinite || signals.ponder.load(Ordering::Relaxed)) && !signals.stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            println!("bestmove {}", best_move.0)
+        }));
+    }
+
+    // Searches a fixed set of positions to a fixed depth and reports the nodes and the speed.
+    // With the NNUE every simd backend the cpu supports runs the same searches, so they are
+    // compared at the same node count.
+    fn bench(&mut self, input_params: Vec<&str>) {
+        let depth = match input_params.first().map(|depth| depth.parse()) {
+            None => BENCH_DEPTH,
+            Some(Ok(depth)) if (1..=MAX_DEPTH).contains(&depth) => depth,
+            _ => {
+                println!("info string invalid bench depth \"{}\"", input_params.join(" "));
+                return;
+            }
+        };
+        self.stop();
+
+        let active = simd::active();
+        let backends = match self.eval_type {
+            EvalType::Nnue => Backend::ALL.into_iter().filter(|backend| backend.is_supported()).collect(),
+            _ => vec![active],
+        };
+        for backend in backends {
+            simd::set_backend(backend);
+            let (nodes, elapsed, timing) = self.bench_searches(depth);
+            let milliseconds = elapsed.as_millis().max(1) as u64;
+            println!("bench {} depth {} nodes {} time {} nps {}", backend.name(), depth, nodes, milliseconds, nodes * 1000 / milliseconds);
+            if let Some(timing) = timing {
+                println!("bench {}", describe_timing(self.eval_type.name(), timing));
+            }
+        }
+        simd::set_backend(active);
     }
 
-    fn ponderhit(&self){
+    // Total nodes and time of the bench searches, each one single threaded on an empty table,
+    // and the time spent evaluating if the evaluator measures it
+    fn bench_searches(&self, depth: i32) -> (u64, Duration, Option<EvalTiming>) {
+        let transposition_table = TranspositionTable::new(DEFAULT_HASH_MB);
+        let config = SearchConfig { threads: 1, ..self.search_config };
+        let mut nodes = 0;
+        let mut timing: Option<EvalTiming> = None;
+        let start = Instant::now();
+        for fen in BENCH_FENS {
+            transposition_table.clear();
+            let mut board = Board::from_fen(fen.to_string());
+            let mut search_info = SearchInfo::new(config, self.create_evaluator());
+            board.search(&transposition_table, &mut search_info, depth);
+            nodes += search_info.nodes;
+            if let Some(search_timing) = search_info.eval_timing {
+                *timing.get_or_insert_default() += search_timing;
+            }
+        }
+        (nodes, start.elapsed(), timing)
+    }
 
+    // Ends the running search, if any, and waits until it has sent its bestmove
+    fn stop(&mut self){
+        self.signals.stop.store(true, Ordering::Relaxed);
+        if let Some(search_thread) = self.search_thread.take() {
+            search_thread.join().expect("search thread panicked");
+        }
     }
 
- 
+    // The opponent played the expected move, the ponder search carries on as a normal timed search
+    fn ponderhit(&mut self){
+        self.signals.ponder.store(false, Ordering::Relaxed);
+    }
 
     fn filter_by_params(&self, defined_fields: Vec<&str>, input_params: Vec<&str>) -> Vec<(String, String)> {
         let mut found_fields: Vec<(String, String)> = Vec::new();
@@ -210,4 +443,15 @@ impl Uci {
         found_fields
     }
     
-}
\ No newline at end of file
+}
+
+// Evaluations and evaluation time of a search, to tell the cost of an evaluator per node
+fn describe_timing(name: &str, timing: EvalTiming) -> String {
+    format!(
+        "{} evaluations {} eval time {} ms {} ns per evaluation",
+        name,
+        timing.evaluations,
+        timing.time.as_millis(),
+        timing.per_evaluation().as_nanos()
+    )
+}
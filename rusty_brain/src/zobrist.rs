@@ -1,5 +1,11 @@
-use rand::Rng;
 use crate::board::{Board, Turn};
+use crate::castling::CastlingRights;
+use crate::piece::Piece;
+use crate::square::Square;
+
+// The keys are generated at compile time from a fixed seed,
+// so a position hashes to the same value in every run of the engine
+pub static ZOBRIST: Zobrist = Zobrist::init_zobrist();
 
 pub struct Zobrist {
     piece_key: [[u64; 12]; 64], // 1 value for each piece in each square
@@ -8,35 +14,49 @@ pub struct Zobrist {
     en_passant_key: [u64; 64], // 64 values for each square
 }
 
+// splitmix64, small and good enough to fill the tables
+const fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
 impl Zobrist {
-    //init the Zobrist with random values
-    pub fn init_zobrist() -> Self {
-        let mut rng = rand::thread_rng();
+    //init the Zobrist with pseudo random values
+    pub const fn init_zobrist() -> Self {
+        let mut state = 0x5EED_0F20_7460_0C0D;
         let mut piece_key = [[0u64; 12]; 64]; 
         let mut castling_rights_key = [0u64; 4]; 
         let mut en_passant_key = [0u64; 64];
-        //black to move key is directly initialized below
 
         //pieces key
-        for i in 0..64 {
-            for j in 0..12 {
-                piece_key[i][j] = rng.gen(); // Generate a random 64-bit value for each square and piece type
+        let mut square = 0;
+        while square < 64 {
+            let mut piece = 0;
+            while piece < 12 {
+                piece_key[square][piece] = next_random(&mut state);
+                piece += 1;
             }
+            square += 1;
         }
 
         //move key
-        let black_to_move_key = rng.gen();
+        let black_to_move_key = next_random(&mut state);
 
         //castling rights keys
-        for i in 0..4 {
-            castling_rights_key[i] = rng.gen();
+        let mut right = 0;
+        while right < 4 {
+            castling_rights_key[right] = next_random(&mut state);
+            right += 1;
         }
 
-        //en passant key. One for each file (Wiki approach)
-        //new approach. 64 values, one for each square.
-        //use the en_passant_square value in the board struct
-        for i in 0..64 {
-            en_passant_key[i] = rng.gen();
+        //en passant key, one for each possible en passant square
+        let mut square = 0;
+        while square < 64 {
+            en_passant_key[square] = next_random(&mut state);
+            square += 1;
         }
 
         Zobrist {
@@ -45,76 +65,60 @@ impl Zobrist {
             castling_rights_key, 
             en_passant_key,
         }
+    }
+
+    pub fn piece(&self, piece: Piece, color: Turn, square: u8) -> u64 {
+        let index = match piece {
+            Piece::Pawn => 0,
+            Piece::Queen => 2,
+            Piece::Knight => 4,
+            Piece::Bishop => 6,
+            Piece::Rook => 8,
+            Piece::King => 10,
+        } + match color {
+            Turn::White => 0,
+            Turn::Black => 1,
+        };
+        self.piece_key[square as usize][index]
+    }
 
+    pub fn black_to_move(&self) -> u64 {
+        self.black_to_move_key
     }
 
-    // Hash the position
+    pub fn castling_rights(&self, castling_rights: &CastlingRights) -> u64 {
+        let rights = [
+            castling_rights.white_king_side,
+            castling_rights.white_queen_side,
+            castling_rights.black_king_side,
+            castling_rights.black_queen_side,
+        ];
+        rights.iter()
+            .zip(self.castling_rights_key)
+            .filter(|(right, _)| **right)
+            .fold(0, |hash, (_, key)| hash ^ key)
+    }
+
+    pub fn en_passant(&self, en_passant_square: Option<Square>) -> u64 {
+        en_passant_square.map_or(0, |square| self.en_passant_key[square as usize])
+    }
+
+    // Hash the position from scratch, make_move keeps Board::zobrist_key up to date incrementally
     // Key elements to hash:
     //  1) one number for each piece in each square
     //  2) one number to indicate it is black to move
     //  3) four numbers for castling rights
-    //  4) eight numbers to indicate the files with valid en passant
+    //  4) one number for the en passant square
     pub fn zobrist_hash(&self, board: &Board) -> u64 {
         let mut hash_value = 0u64;
 
-        let mut occupied_squares = board.bitboards.get_ally_pieces(Turn::White)
-            | board.bitboards.get_enemy_pieces(Turn::White);
-
+        let mut occupied_squares = !board.bitboards.get_empty_squares();
         while occupied_squares != 0 {
-
-            //get the index of the first '1' in the binary
-            let square = occupied_squares.trailing_zeros();
-            
-            //en passant
-            //new approach, check if that square is the same as the one in board
-            // + 8 because the en passant square is the destination not current square 
-            if board.en_passant_square.is_some_and(|sqr| square == sqr as u32 + 8) {
-                hash_value ^= self.en_passant_key[square as usize];
-            }
-
-            //pieces key
-            //for efficiency start with most probable pieces
-            //pawns -> queen(s) -> knights -> bishops -> rooks -> king 
-            //total of 12 pieces
-            if board.bitboards.white_pawns & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][0];
-            } 
-            else if board.bitboards.black_pawns & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][1];
-            }
-            else if board.bitboards.white_queens & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][2];
-            }
-            else if board.bitboards.black_queens & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][3];
-            }
-            else if board.bitboards.white_knights & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][4];
-            }
-            else if board.bitboards.black_knights & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][5];
-            }
-            else if board.bitboards.white_bishops & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][6];
-            } 
-            else if board.bitboards.black_bishops & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][7];
-            }
-            else if board.bitboards.white_rooks & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][8];
-            }
-            else if board.bitboards.black_rooks & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][9];
+            let square = occupied_squares.trailing_zeros() as u8;
+            if let Some((piece, color)) = board.piece_at(square) {
+                hash_value ^= self.piece(piece, color, square);
             }
-            else if board.bitboards.white_king & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][10];
-            }
-            else if board.bitboards.black_king & (1 << square) != 0 {
-                hash_value ^= self.piece_key[square as usize][11];
-            }
-
             occupied_squares &= occupied_squares - 1;
-
         }
 
         //black turn key
@@ -122,21 +126,76 @@ impl Zobrist {
             hash_value ^= self.black_to_move_key;
         }
 
-        //castling rights key
-        if board.castling_rights.white_king_side {
-            hash_value ^= self.castling_rights_key[0];
+        hash_value ^= self.castling_rights(&board.castling_rights);
+        hash_value ^= self.en_passant(board.en_passant_square);
+
+        hash_value
+    }
+}
+
+#[cfg(test)]
+mod zobrist {
+    use crate::board::Board;
+    use crate::movement::Move;
+    use crate::square::Square;
+
+    use super::ZOBRIST;
+
+    fn check_keys(board: &mut Board, depth: i32) {
+        assert_eq!(board.zobrist_key, ZOBRIST.zobrist_hash(board));
+        if depth == 0 {
+            return;
         }
-        if board.castling_rights.white_queen_side {
-            hash_value ^= self.castling_rights_key[1];
+
+        for mv in board.generate_legal_moves() {
+            let key = board.zobrist_key;
+            board.make_move(mv);
+            check_keys(board, depth - 1);
+            board.undo_move();
+            assert_eq!(board.zobrist_key, key, "undo of {} didn't restore the key", mv);
         }
-        if board.castling_rights.black_king_side {
-            hash_value ^= self.castling_rights_key[2];
+    }
+
+    #[test]
+    fn incremental_key_matches_full_hash() {
+        // castling, en passant, promotions and captures of castling rooks
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 0",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 0",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        for fen in fens {
+            let mut board = Board::from_fen(fen.to_string());
+            check_keys(&mut board, 3);
+        }
+    }
+
+    #[test]
+    fn transpositions_share_a_key() {
+        let mut first = Board::new();
+        let mut second = Board::new();
+
+        for (from, to) in [(Square::G1, Square::F3), (Square::G8, Square::F6), (Square::B1, Square::C3), (Square::B8, Square::C6)] {
+            first.make_move(Move::encode(from as u8, to as u8, Move::QUIET_MOVE));
         }
-        if board.castling_rights.black_queen_side {
-            hash_value ^= self.castling_rights_key[3];
+        for (from, to) in [(Square::B1, Square::C3), (Square::B8, Square::C6), (Square::G1, Square::F3), (Square::G8, Square::F6)] {
+            second.make_move(Move::encode(from as u8, to as u8, Move::QUIET_MOVE));
         }
+        assert_eq!(first.zobrist_key, second.zobrist_key);
 
+        // the same pieces with a different en passant square are a different position
+        let with_en_passant = Board::from_fen("4k3/8/8/8/4Pp2/8/8/4K3 b - e3 0 1".to_string());
+        let without_en_passant = Board::from_fen("4k3/8/8/8/4Pp2/8/8/4K3 b - - 0 1".to_string());
+        assert_ne!(with_en_passant.zobrist_key, without_en_passant.zobrist_key);
+    }
 
-        hash_value
+    #[test]
+    fn keys_are_deterministic() {
+        // the keys are built at compile time, a fresh table is the same table
+        let table = super::Zobrist::init_zobrist();
+        let board = Board::new();
+        assert_eq!(table.zobrist_hash(&board), board.zobrist_key);
     }
-}
\ No newline at end of file
+}